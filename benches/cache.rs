@@ -0,0 +1,62 @@
+use std::env;
+
+use criterion::{criterion_group, criterion_main, Criterion, black_box};
+
+use ignite_client::{Client, Configuration, Cache, Value, ScanQuery};
+
+// Requires a running node, e.g. IGNITE_BENCH_ADDRESS=127.0.0.1:10800.
+fn cache() -> Option<Cache> {
+    let address = env::var("IGNITE_BENCH_ADDRESS").ok()?;
+
+    let client = Client::start(Configuration::default().address(&address))
+        .expect("Failed to create a client.");
+
+    Some(client.get_or_create_cache("bench-cache").expect("Failed to create a cache."))
+}
+
+fn cache_ops(c: &mut Criterion) {
+    let cache = match cache() {
+        Some(cache) => cache,
+        None => {
+            eprintln!("IGNITE_BENCH_ADDRESS is not set, skipping cache benchmarks.");
+
+            return;
+        },
+    };
+
+    let key = Value::I32(1);
+    let value = Value::String("x".repeat(256));
+
+    c.bench_function("put", |b| {
+        b.iter(|| cache.put(black_box(&key), black_box(&value)).unwrap())
+    });
+
+    c.bench_function("get", |b| {
+        b.iter(|| cache.get(black_box(&key)).unwrap())
+    });
+
+    let entries: Vec<(Value, Value)> = (0 .. 100).map(|i| (Value::I32(i), value.clone())).collect();
+
+    c.bench_function("put_all_100", |b| {
+        b.iter(|| cache.put_all(black_box(&entries)).unwrap())
+    });
+
+    c.bench_function("scan_100", |b| {
+        b.iter(|| cache.scan(ScanQuery::new()).unwrap().count())
+    });
+
+    c.bench_function("query_fields_1000", |b| {
+        b.iter(|| {
+            cache.query_fields_for_each("SELECT x FROM system_range(1, 1000)", &[], |row| {
+                black_box(row);
+
+                Ok(())
+            }).unwrap()
+        })
+    });
+
+    cache.destroy().unwrap();
+}
+
+criterion_group!(benches, cache_ops);
+criterion_main!(benches);
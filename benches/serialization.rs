@@ -0,0 +1,33 @@
+use criterion::{criterion_group, criterion_main, Criterion, black_box};
+
+use ignite_client::Value;
+
+fn values() -> Vec<(&'static str, Value)> {
+    vec![
+        ("i64", Value::I64(42)),
+        ("string", Value::String("x".repeat(256))),
+        ("i32_vec", Value::I32Vec((0 .. 1024).collect())),
+        ("string_vec", Value::StringVec((0 .. 64).map(|i| i.to_string()).collect())),
+    ]
+}
+
+fn write(c: &mut Criterion) {
+    for (name, value) in values() {
+        c.bench_function(&format!("write_{}", name), |b| {
+            b.iter(|| black_box(&value).to_bytes().unwrap())
+        });
+    }
+}
+
+fn read(c: &mut Criterion) {
+    for (name, value) in values() {
+        let bytes = value.to_bytes().unwrap();
+
+        c.bench_function(&format!("read_{}", name), |b| {
+            b.iter(|| Value::from_bytes(black_box(&bytes)).unwrap())
+        });
+    }
+}
+
+criterion_group!(benches, write, read);
+criterion_main!(benches);
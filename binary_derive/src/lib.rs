@@ -2,10 +2,10 @@ extern crate proc_macro;
 
 use proc_macro::TokenStream;
 use quote::quote;
-use syn;
 use syn::{Data, Fields};
+use syn::spanned::Spanned;
 
-#[proc_macro_derive(IgniteRead)]
+#[proc_macro_derive(IgniteRead, attributes(ignite))]
 pub fn binary_read_derive(input: TokenStream) -> TokenStream {
     let ast: syn::DeriveInput = syn::parse(input).unwrap();
 
@@ -13,28 +13,44 @@ pub fn binary_read_derive(input: TokenStream) -> TokenStream {
 
     let gen = match &ast.data {
         Data::Struct(data) => {
-            let mut field_names = Vec::new();
+            let attrs = match fields_attrs(&data.fields) {
+                Ok(attrs) => attrs,
+                Err(err) => return err.to_compile_error().into(),
+            };
 
-            match &data.fields {
+            // Skipped fields are not in the stream.
+            let reads = attrs.iter().map(|attrs| {
+                if attrs.skip {
+                    quote! { Default::default() }
+                }
+                else {
+                    quote! { IgniteRead::read(bytes)? }
+                }
+            });
+
+            let fields = match &data.fields {
                 Fields::Named(fields) => {
-                    for field in &fields.named {
-                        field_names.push(field.clone().ident.unwrap());
-                    }
+                    let field_names = fields.named.iter().map(|field| &field.ident);
+
+                    quote! { { #( #field_names: #reads, )* } }
                 },
-                _ => panic!("Only named fields are supported."),
-            }
+                Fields::Unnamed(_) => quote! { ( #( #reads, )* ) },
+                Fields::Unit => quote! {},
+            };
 
             quote! {
                 impl IgniteRead for #name {
                     fn read(bytes: &mut Bytes) -> Result<#name> {
-                        Ok(#name {
-                            #( #field_names: IgniteRead::read(bytes)?, )*
-                        })
+                        Ok(#name #fields)
                     }
                 }
             }
         },
-        Data::Enum(_) => {
+        Data::Enum(data) => {
+            if let Err(err) = check_c_like(data) {
+                return err.to_compile_error().into();
+            }
+
             quote! {
                 impl IgniteRead for #name {
                     fn read(bytes: &mut Bytes) -> Result<#name> {
@@ -48,13 +64,13 @@ pub fn binary_read_derive(input: TokenStream) -> TokenStream {
                 }
             }
         },
-        Data::Union(_) => panic!("Union not supported."),
+        Data::Union(data) => union_error(data).to_compile_error(),
     };
 
     gen.into()
 }
 
-#[proc_macro_derive(IgniteWrite)]
+#[proc_macro_derive(IgniteWrite, attributes(ignite))]
 pub fn binary_write_derive(input: TokenStream) -> TokenStream {
     let ast: syn::DeriveInput = syn::parse(input).unwrap();
 
@@ -62,16 +78,21 @@ pub fn binary_write_derive(input: TokenStream) -> TokenStream {
 
     let gen = match &ast.data {
         Data::Struct(data) => {
-            let mut field_names = Vec::new();
+            let attrs = match fields_attrs(&data.fields) {
+                Ok(attrs) => attrs,
+                Err(err) => return err.to_compile_error().into(),
+            };
 
-            match &data.fields {
-                Fields::Named(fields) => {
-                    for field in &fields.named {
-                        field_names.push(field.clone().ident.unwrap());
-                    }
-                },
-                _ => panic!("Only named fields are supported."),
-            }
+            let field_names: Vec<syn::Member> = match &data.fields {
+                Fields::Named(fields) => fields.named.iter().map(|field| syn::Member::Named(field.ident.clone().unwrap())).collect(),
+                Fields::Unnamed(fields) => (0 .. fields.unnamed.len()).map(|index| syn::Member::Unnamed(index.into())).collect(),
+                Fields::Unit => Vec::new(),
+            };
+
+            let field_names = field_names.into_iter()
+                .zip(&attrs)
+                .filter(|(_, attrs)| !attrs.skip)
+                .map(|(member, _)| member);
 
             quote! {
                 impl IgniteWrite for #name {
@@ -83,7 +104,11 @@ pub fn binary_write_derive(input: TokenStream) -> TokenStream {
                 }
             }
         },
-        Data::Enum(_) => {
+        Data::Enum(data) => {
+            if let Err(err) = check_c_like(data) {
+                return err.to_compile_error().into();
+            }
+
             quote! {
                 impl IgniteWrite for #name {
                     fn write(&self, bytes: &mut BytesMut) -> Result<()> {
@@ -94,8 +119,149 @@ pub fn binary_write_derive(input: TokenStream) -> TokenStream {
                 }
             }
         },
-        Data::Union(_) => panic!("Union not supported."),
+        Data::Union(data) => union_error(data).to_compile_error(),
+    };
+
+    gen.into()
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    skip: bool,
+}
+
+// Parses `#[ignite(skip)]`.
+fn fields_attrs(fields: &Fields) -> syn::Result<Vec<FieldAttrs>> {
+    let mut result = Vec::new();
+
+    for field in fields.iter() {
+        let mut attrs = FieldAttrs::default();
+
+        for attr in field.attrs.iter().filter(|attr| attr.path.is_ident("ignite")) {
+            let list = match attr.parse_meta()? {
+                syn::Meta::List(list) => list,
+                meta => return Err(syn::Error::new_spanned(meta, "Expected #[ignite(skip)].")),
+            };
+
+            for nested in list.nested {
+                match nested {
+                    syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("skip") => {
+                        attrs.skip = true;
+                    },
+                    nested => return Err(syn::Error::new_spanned(nested, "Expected `skip`.")),
+                }
+            }
+        }
+
+        result.push(attrs);
+    }
+
+    Ok(result)
+}
+
+// Enums are written as their `i32` discriminant, so variants can't carry data.
+fn check_c_like(data: &syn::DataEnum) -> syn::Result<()> {
+    match data.variants.iter().find(|variant| !matches!(variant.fields, Fields::Unit)) {
+        Some(variant) => Err(syn::Error::new_spanned(variant, "Only enums without variant data are supported.")),
+        None => Ok(()),
+    }
+}
+
+fn union_error(data: &syn::DataUnion) -> syn::Error {
+    syn::Error::new(data.union_token.span(), "Unions are not supported, use a struct or an enum.")
+}
+
+#[proc_macro_derive(FromRow, attributes(sql))]
+pub fn from_row_derive(input: TokenStream) -> TokenStream {
+    let ast: syn::DeriveInput = syn::parse(input).unwrap();
+
+    let name = &ast.ident;
+
+    let fields = match &ast.data {
+        Data::Struct(data) => {
+            match &data.fields {
+                Fields::Named(fields) => &fields.named,
+                fields => return syn::Error::new_spanned(fields, "Only structs with named fields are supported.").to_compile_error().into(),
+            }
+        },
+        _ => return syn::Error::new_spanned(&ast.ident, "Only structs are supported.").to_compile_error().into(),
+    };
+
+    let field_count = fields.len();
+
+    let mut field_values = Vec::new();
+
+    for (index, field) in fields.iter().enumerate() {
+        let field_name = field.ident.as_ref().unwrap();
+        let field_label = field_name.to_string();
+
+        let value = match sql_column(field) {
+            Ok(Some(column)) => quote! { reader.by_name(#column)? },
+            Ok(None) => quote! { reader.by_index(#index) },
+            Err(err) => return err.to_compile_error().into(),
+        };
+
+        let field_value = match option_type(&field.ty) {
+            Some(ty) => quote! { ::ignite_client::RowReader::optional::<#ty>(#value, #field_label)? },
+            None => {
+                let ty = &field.ty;
+
+                quote! { ::ignite_client::RowReader::required::<#ty>(#value, #field_label)? }
+            },
+        };
+
+        field_values.push(quote! { #field_name: #field_value });
+    }
+
+    let gen = quote! {
+        impl ::ignite_client::FromRow for #name {
+            fn from_row(row: ::ignite_client::Row, columns: &[String]) -> ::std::result::Result<#name, ::ignite_client::Error> {
+                let mut reader = ::ignite_client::RowReader::new(row, columns, #field_count)?;
+
+                Ok(#name {
+                    #( #field_values, )*
+                })
+            }
+        }
     };
 
     gen.into()
 }
+
+// Parses `#[sql(column = "...")]`.
+fn sql_column(field: &syn::Field) -> syn::Result<Option<String>> {
+    let attr = match field.attrs.iter().find(|attr| attr.path.is_ident("sql")) {
+        Some(attr) => attr,
+        None => return Ok(None),
+    };
+
+    if let syn::Meta::List(list) = attr.parse_meta()? {
+        for nested in list.nested {
+            if let syn::NestedMeta::Meta(syn::Meta::NameValue(value)) = nested {
+                if value.path.is_ident("column") {
+                    if let syn::Lit::Str(column) = value.lit {
+                        return Ok(Some(column.value()));
+                    }
+                }
+            }
+        }
+    }
+
+    Err(syn::Error::new_spanned(attr, "Expected #[sql(column = \"...\")]."))
+}
+
+fn option_type(ty: &syn::Type) -> Option<&syn::Type> {
+    if let syn::Type::Path(path) = ty {
+        let segment = path.path.segments.last()?;
+
+        if segment.ident == "Option" {
+            if let syn::PathArguments::AngleBracketed(args) = &segment.arguments {
+                if let Some(syn::GenericArgument::Type(ty)) = args.args.first() {
+                    return Some(ty);
+                }
+            }
+        }
+    }
+
+    None
+}
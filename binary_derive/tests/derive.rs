@@ -0,0 +1,10 @@
+#[test]
+fn test_derive() {
+    let t = trybuild::TestCases::new();
+
+    t.pass("tests/ui/tuple_struct.rs");
+    t.compile_fail("tests/ui/union.rs");
+    t.compile_fail("tests/ui/enum_data.rs");
+    t.compile_fail("tests/ui/bad_attribute.rs");
+    t.compile_fail("tests/ui/bad_sql_column.rs");
+}
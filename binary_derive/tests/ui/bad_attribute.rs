@@ -0,0 +1,9 @@
+use binary_derive::IgniteWrite;
+
+#[derive(IgniteWrite)]
+struct Point {
+    #[ignite(name = "px")]
+    x: i32,
+}
+
+fn main() {}
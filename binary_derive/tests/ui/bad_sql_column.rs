@@ -0,0 +1,9 @@
+use binary_derive::FromRow;
+
+#[derive(FromRow)]
+struct Person {
+    #[sql(name = "full_name")]
+    name: String,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use binary_derive::IgniteRead;
+
+#[derive(IgniteRead)]
+enum Shape {
+    Empty,
+    Circle(f64),
+}
+
+fn main() {}
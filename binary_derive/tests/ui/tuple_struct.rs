@@ -0,0 +1,46 @@
+// The derived code expects the client's serialization items in scope.
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use binary_derive::{IgniteRead, IgniteWrite};
+
+type Result<T> = std::result::Result<T, ()>;
+
+trait IgniteRead: Sized {
+    fn read(bytes: &mut Bytes) -> Result<Self>;
+}
+
+trait IgniteWrite {
+    fn write(&self, bytes: &mut BytesMut) -> Result<()>;
+}
+
+impl IgniteRead for i32 {
+    fn read(bytes: &mut Bytes) -> Result<i32> {
+        Ok(bytes.get_i32_le())
+    }
+}
+
+impl IgniteWrite for i32 {
+    fn write(&self, bytes: &mut BytesMut) -> Result<()> {
+        bytes.put_i32_le(*self);
+
+        Ok(())
+    }
+}
+
+#[derive(IgniteRead, IgniteWrite, PartialEq, Debug)]
+struct Point(i32, i32);
+
+#[derive(IgniteRead, IgniteWrite, PartialEq, Debug)]
+struct Marker;
+
+fn main() {
+    let mut bytes = BytesMut::new();
+
+    Point(1, -2).write(&mut bytes).unwrap();
+    Marker.write(&mut bytes).unwrap();
+
+    let mut bytes = bytes.freeze();
+
+    assert_eq!(Point::read(&mut bytes), Ok(Point(1, -2)));
+    assert_eq!(Marker::read(&mut bytes), Ok(Marker));
+    assert!(bytes.is_empty());
+}
@@ -0,0 +1,9 @@
+use binary_derive::IgniteWrite;
+
+#[derive(IgniteWrite)]
+union Bits {
+    int: i32,
+    float: f32,
+}
+
+fn main() {}
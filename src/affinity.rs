@@ -0,0 +1,537 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::time::{Duration, Instant};
+
+use bytes::{Bytes, BytesMut};
+use uuid::Uuid;
+
+use crate::AffinityTopologyVersion;
+use crate::binary::{Value, IgniteWrite, IgniteRead};
+use crate::cache::cache_id;
+use crate::configuration::Configuration;
+use crate::error::{Result, ErrorKind, Error};
+use crate::network::{Tcp, ConnectionState};
+
+const OP_CACHE_PARTITIONS: i16 = 1101;
+
+// Partition owners of a cache with the default (rendezvous) affinity function.
+struct CacheMapping {
+    owners: Vec<Uuid>,
+    // Affinity key field ID by key type ID.
+    key_fields: HashMap<i32, i32>,
+}
+
+impl CacheMapping {
+    fn partition(&self, key: &Value) -> Option<usize> {
+        let hash = match key {
+            Value::BinaryObject(object) => match self.key_fields.get(&object.type_id()) {
+                Some(field_id) => affinity_hash(&object.field_by_id(*field_id).ok()??)?,
+                None => object.hash_code(),
+            },
+            key => affinity_hash(key)?,
+        };
+
+        Some(partition(hash, self.owners.len()))
+    }
+}
+
+/// Routes keyed operations to the connection of the key's primary node. Keys that can't be
+/// routed, e.g. for caches with a custom affinity function or when the partition map couldn't
+/// be fetched, go through the main connection instead.
+pub(crate) struct Routing {
+    nodes: Vec<Tcp>,
+    // `None` for caches that can't be routed until the next topology change.
+    mappings: HashMap<i32, Option<CacheMapping>>,
+    version: Option<AffinityTopologyVersion>,
+    refreshed: Option<Instant>,
+    // `Configuration::reconnect`; the node connections themselves never reconnect on their own.
+    reconnect: bool,
+}
+
+impl Routing {
+    // Nodes that refuse protocol 1.4.0 are skipped.
+    pub(crate) fn connect(configuration: &Configuration) -> Routing {
+        let mut nodes: Vec<Tcp> = Vec::new();
+
+        for address in &configuration.addresses {
+            if let Ok(node) = Tcp::connect_node(configuration, address) {
+                // Several addresses may lead to the same node.
+                if nodes.iter().all(|other| other.node_id != node.node_id) {
+                    nodes.push(node);
+                }
+            }
+        }
+
+        Routing { nodes, mappings: HashMap::new(), version: None, refreshed: None, reconnect: configuration.reconnect }
+    }
+
+    pub(crate) fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub(crate) fn version(&self) -> Option<AffinityTopologyVersion> {
+        self.version
+    }
+
+    pub(crate) fn age(&self) -> Option<Duration> {
+        self.refreshed.map(|refreshed| refreshed.elapsed())
+    }
+
+    /// Runs the operation on the primary node of the key. `None` if the key can't be routed, or if
+    /// the node failed and `Configuration::reconnect` allows running a read-only operation again.
+    /// Writes are never run again, since the failed request may have reached the node.
+    pub(crate) fn execute<R, F1, F2>(&mut self, cache_id: i32, key: &Value, operation_code: i16, read_only: bool, request_writer: F1, response_reader: F2) -> Option<Result<R>>
+        where
+            F1: Fn(&mut BytesMut) -> Result<()>,
+            F2: Fn(&mut Bytes) -> Result<R>,
+    {
+        let node = self.route(cache_id, key)?;
+
+        let result = self.nodes[node].execute(operation_code, request_writer, response_reader);
+
+        if let Err(Error { kind: ErrorKind::Network, .. }) = result {
+            // The node may have left, so the map is refreshed before the next operation.
+            self.version = None;
+
+            if read_only && self.reconnect {
+                return None;
+            }
+        }
+
+        Some(result)
+    }
+
+    /// Number of partitions of the cache, if its partition map is available.
+    pub(crate) fn partitions(&mut self, cache_id: i32) -> Option<usize> {
+        self.mapping(cache_id).map(|mapping| mapping.owners.len())
+    }
+
+    fn route(&mut self, cache_id: i32, key: &Value) -> Option<usize> {
+        let owner = {
+            let mapping = self.mapping(cache_id)?;
+
+            mapping.owners[mapping.partition(key)?]
+        };
+
+        self.nodes.iter().position(|node| node.node_id == Some(owner) && node.state != ConnectionState::Closed)
+    }
+
+    fn mapping(&mut self, cache_id: i32) -> Option<&CacheMapping> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        if self.is_stale() || !self.mappings.contains_key(&cache_id) {
+            self.refresh(cache_id);
+        }
+
+        self.mappings.get(&cache_id)?.as_ref()
+    }
+
+    // Nodes report topology changes in response headers.
+    fn is_stale(&self) -> bool {
+        self.version.is_none() || self.nodes.iter().any(|node| node.affinity_version > self.version)
+    }
+
+    fn refresh(&mut self, cache_id: i32) {
+        let mut cache_ids: Vec<i32> = self.mappings.keys().copied().collect();
+
+        if !cache_ids.contains(&cache_id) {
+            cache_ids.push(cache_id);
+        }
+
+        // Nodes that were lost are tried again if reconnects are enabled, since the topology has changed anyway.
+        if self.reconnect {
+            for node in self.nodes.iter_mut().filter(|node| node.state == ConnectionState::Closed) {
+                let _ = node.reconnect();
+            }
+        }
+
+        let result = match self.nodes.iter_mut().find(|node| node.state == ConnectionState::Connected) {
+            Some(node) => node.execute(OP_CACHE_PARTITIONS, |request| write_cache_ids(request, &cache_ids), read_mappings),
+            None => Err(Error::new(ErrorKind::Network, "No node connections".to_string())),
+        };
+
+        self.mappings = cache_ids.into_iter().map(|cache_id| (cache_id, None)).collect();
+        self.refreshed = Some(Instant::now());
+
+        // If the map can't be fetched, caches are not routed until the topology changes.
+        self.version = self.nodes.iter().map(|node| node.affinity_version).fold(None, newer);
+
+        if let Ok((version, mappings)) = result {
+            self.version = newer(self.version, Some(version));
+
+            for (cache_id, mapping) in mappings {
+                if let Some(entry) = self.mappings.get_mut(&cache_id) {
+                    *entry = mapping;
+                }
+            }
+        }
+
+        // Version is unknown if no node reported it yet; don't refresh on every operation then.
+        if self.version.is_none() {
+            self.version = Some(AffinityTopologyVersion { major: 0, minor: 0 });
+        }
+    }
+}
+
+fn newer(a: Option<AffinityTopologyVersion>, b: Option<AffinityTopologyVersion>) -> Option<AffinityTopologyVersion> {
+    if b > a { b } else { a }
+}
+
+fn write_cache_ids(request: &mut BytesMut, cache_ids: &[i32]) -> Result<()> {
+    (cache_ids.len() as i32).write(request)?;
+
+    for cache_id in cache_ids {
+        cache_id.write(request)?;
+    }
+
+    Ok(())
+}
+
+// Mapping by cache ID, see `Routing::mappings`.
+type Mappings = Vec<(i32, Option<CacheMapping>)>;
+
+// Mappings are grouped by caches that share the same partition distribution.
+fn read_mappings(response: &mut Bytes) -> Result<(AffinityTopologyVersion, Mappings)> {
+    let major = i64::read(response)?;
+    let minor = i32::read(response)?;
+
+    let mut mappings = Vec::new();
+
+    for _ in 0 .. i32::read(response)? {
+        let applicable = bool::read(response)?;
+
+        let mut caches = Vec::new();
+
+        for _ in 0 .. i32::read(response)? {
+            let cache_id = i32::read(response)?;
+
+            let mut key_fields = HashMap::new();
+
+            if applicable {
+                for _ in 0 .. i32::read(response)? {
+                    let type_id = i32::read(response)?;
+                    let field_id = i32::read(response)?;
+
+                    key_fields.insert(type_id, field_id);
+                }
+            }
+
+            caches.push((cache_id, key_fields));
+        }
+
+        let owners = if applicable { read_owners(response)? } else { None };
+
+        for (cache_id, key_fields) in caches {
+            let mapping = owners.clone().map(|owners| CacheMapping { owners, key_fields });
+
+            mappings.push((cache_id, mapping));
+        }
+    }
+
+    Ok((AffinityTopologyVersion { major, minor }, mappings))
+}
+
+// `None` if some partitions have no owner, e.g. while nodes are leaving.
+fn read_owners(response: &mut Bytes) -> Result<Option<Vec<Uuid>>> {
+    let mut owners = Vec::new();
+
+    for _ in 0 .. i32::read(response)? {
+        let node_id = Uuid::read(response)?;
+
+        for _ in 0 .. i32::read(response)? {
+            let partition = i32::read(response)?;
+
+            let partition = usize::try_from(partition)
+                .map_err(|_| Error::new(ErrorKind::Serde, format!("Invalid partition: {}", partition)))?;
+
+            if owners.len() <= partition {
+                owners.resize(partition + 1, None);
+            }
+
+            owners[partition] = Some(node_id);
+        }
+    }
+
+    if owners.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(owners.into_iter().collect())
+}
+
+// Same as `RendezvousAffinityFunction.calculatePartition`.
+fn partition(hash: i32, partitions: usize) -> usize {
+    if partitions.is_power_of_two() {
+        ((hash ^ ((hash as u32) >> 16) as i32) & (partitions as i32 - 1)) as usize
+    }
+    else {
+        (hash % partitions as i32).unsigned_abs() as usize
+    }
+}
+
+// Java `hashCode` of the key as the server sees it. `None` for types the client doesn't hash.
+fn affinity_hash(key: &Value) -> Option<i32> {
+    let long_hash = |v: i64| (v ^ ((v as u64) >> 32) as i64) as i32;
+
+    match key {
+        Value::I8(v) => Some(*v as i32),
+        Value::I16(v) => Some(*v as i32),
+        Value::I32(v) => Some(*v),
+        Value::I64(v) => Some(long_hash(*v)),
+        // Java collapses all NaNs into a single canonical value.
+        Value::F32(v) => Some(if v.is_nan() { 0x7fc0_0000 } else { v.to_bits() as i32 }),
+        Value::F64(v) => Some(long_hash(if v.is_nan() { 0x7ff8_0000_0000_0000 } else { v.to_bits() as i64 })),
+        Value::Char(v) => Some(*v as i32),
+        Value::Bool(v) => Some(if *v { 1231 } else { 1237 }),
+        Value::String(v) => Some(cache_id(v)),
+        Value::Uuid(v) => {
+            let v = v.as_u128();
+
+            Some(long_hash(((v >> 64) as i64) ^ (v as i64)))
+        },
+        Value::BinaryObject(object) => Some(object.hash_code()),
+        _ => None,
+    }
+}
+
+// === Tests
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use uuid::Uuid;
+
+    use crate::{AffinityTopologyVersion, Client, Configuration, ErrorKind, VERSION_1_1_0, cache_id};
+    use crate::affinity::{affinity_hash, partition};
+    use crate::binary::{Value, IgniteWrite};
+    use crate::mock::MockServer;
+
+    fn partitions_response(version: i64, cache: &str, owners: &[(Uuid, &[i32])]) -> Vec<u8> {
+        let mut response = BytesMut::new();
+
+        version.write(&mut response).unwrap();
+        0i32.write(&mut response).unwrap();
+        1i32.write(&mut response).unwrap(); // Mappings.
+        true.write(&mut response).unwrap(); // Applicable.
+        1i32.write(&mut response).unwrap(); // Caches.
+        cache_id(cache).write(&mut response).unwrap();
+        0i32.write(&mut response).unwrap(); // Key configurations.
+        (owners.len() as i32).write(&mut response).unwrap();
+
+        for (node_id, partitions) in owners {
+            node_id.write(&mut response).unwrap();
+            (partitions.len() as i32).write(&mut response).unwrap();
+
+            for partition in partitions.iter() {
+                partition.write(&mut response).unwrap();
+            }
+        }
+
+        response.to_vec()
+    }
+
+    fn contains(server: &MockServer, key: i32) -> bool {
+        let client = Client::start(server.configuration()).unwrap();
+
+        client.cache("test-cache").contains_key(&Value::I32(key)).unwrap()
+    }
+
+    #[test]
+    fn test_affinity_hash() {
+        assert_eq!(affinity_hash(&Value::I32(-7)), Some(-7));
+        assert_eq!(affinity_hash(&Value::I64((1 << 32) | 5)), Some(4));
+        assert_eq!(affinity_hash(&Value::String("abc".to_string())), Some(96354));
+        assert_eq!(affinity_hash(&Value::Bool(true)), Some(1231));
+        assert_eq!(affinity_hash(&Value::Char('a')), Some(97));
+        assert_eq!(affinity_hash(&Value::F64(1.0)), Some(1072693248));
+        assert_eq!(affinity_hash(&Value::Uuid(Uuid::from_u128((1 << 64) | 2))), Some(3));
+        assert_eq!(affinity_hash(&Value::I32Vec(vec![1])), None);
+    }
+
+    #[test]
+    fn test_partition() {
+        assert_eq!(partition(5, 1024), 5);
+        assert_eq!(partition(0x10000, 1024), 1);
+        assert_eq!(partition(-1, 1024), 0);
+        assert_eq!(partition(-7, 10), 7);
+        assert_eq!(partition(23, 10), 3);
+    }
+
+    #[test]
+    fn test_routing() {
+        let first = MockServer::start();
+        let second = MockServer::start();
+
+        // Even partitions are on the first node, odd ones are on the second.
+        first.respond(1101, partitions_response(1, "test-cache", &[(first.node_id(), &[0, 2]), (second.node_id(), &[1, 3])]));
+
+        let configuration = Configuration::default().addresses(&[first.address(), second.address()]).partition_awareness(true);
+
+        let client = Client::start(configuration).unwrap();
+
+        let cache = client.cache("test-cache");
+
+        for key in 0 .. 4 {
+            assert_eq!(cache.put(&Value::I32(key), &Value::I32(key)), Ok(()));
+            assert_eq!(cache.get(&Value::I32(key)), Ok(Some(Value::I32(key))));
+        }
+
+        assert!(contains(&first, 0) && contains(&first, 2));
+        assert!(contains(&second, 1) && contains(&second, 3));
+        assert!(!contains(&first, 1) && !contains(&second, 0));
+
+        let state = client.debug_state();
+
+        assert_eq!(state.partition_aware_nodes, 2);
+        assert_eq!(state.partition_map_version, Some(AffinityTopologyVersion { major: 1, minor: 0 }));
+        assert!(state.partition_map_age.is_some());
+    }
+
+    #[test]
+    fn test_refresh_on_topology_change() {
+        let first = MockServer::start();
+        let second = MockServer::start();
+
+        first.respond(1101, partitions_response(1, "test-cache", &[(first.node_id(), &[0, 1])]));
+
+        let configuration = Configuration::default().addresses(&[first.address(), second.address()]).partition_awareness(true);
+
+        let client = Client::start(configuration).unwrap();
+
+        let cache = client.cache("test-cache");
+
+        assert_eq!(cache.put(&Value::I32(1), &Value::I32(1)), Ok(()));
+
+        // The next response from the first node reports the new topology.
+        first.topology_version(2, 0);
+        first.respond(1101, partitions_response(2, "test-cache", &[(second.node_id(), &[0, 1])]));
+
+        assert_eq!(cache.get(&Value::I32(1)), Ok(Some(Value::I32(1))));
+        assert_eq!(cache.put(&Value::I32(2), &Value::I32(2)), Ok(()));
+
+        assert!(contains(&first, 1) && !contains(&second, 1));
+        assert!(contains(&second, 2) && !contains(&first, 2));
+
+        assert_eq!(client.debug_state().partition_map_version, Some(AffinityTopologyVersion { major: 2, minor: 0 }));
+    }
+
+    #[test]
+    fn test_fallback_to_main_connection() {
+        let old = MockServer::start();
+
+        old.max_version(VERSION_1_1_0);
+
+        let client = Client::start(old.configuration().partition_awareness(true)).unwrap();
+
+        assert_eq!(client.cache("test-cache").put(&Value::I32(1), &Value::I32(1)), Ok(()));
+        assert_eq!(client.debug_state().partition_aware_nodes, 0);
+
+        let server = MockServer::start();
+
+        server.fail(1101, 1, "Partitions are not available");
+
+        let client = Client::start(server.configuration().partition_awareness(true)).unwrap();
+
+        let cache = client.cache("test-cache");
+
+        assert_eq!(cache.put(&Value::I32(1), &Value::I32(1)), Ok(()));
+        assert_eq!(cache.get(&Value::I32(1)), Ok(Some(Value::I32(1))));
+        assert_eq!(client.debug_state().partition_aware_nodes, 1);
+    }
+
+    #[test]
+    fn test_writes_not_replayed() {
+        let first = MockServer::start();
+        let second = MockServer::start();
+
+        // The partition map is fetched from the first node, before each operation below.
+        for _ in 0 .. 2 {
+            first.respond(1101, partitions_response(1, "test-cache", &[(second.node_id(), &[0, 1])]));
+        }
+
+        let configuration = Configuration::default()
+            .addresses(&[first.address(), second.address()])
+            .partition_awareness(true)
+            .reconnect(true);
+
+        let client = Client::start(configuration).unwrap();
+
+        let cache = client.cache("test-cache");
+
+        // The put may have reached the node before the connection broke, so it is sent only once.
+        second.disconnect(1001);
+
+        assert_eq!(cache.put(&Value::I32(1), &Value::I32(1)).unwrap_err().kind, ErrorKind::Network);
+        assert_eq!(second.request_count(1001), 1);
+        assert_eq!(first.request_count(1001), 0);
+
+        // Reads run again over the main connection.
+        second.disconnect(1000);
+
+        assert_eq!(cache.get(&Value::I32(1)), Ok(None));
+        assert_eq!(second.request_count(1000), 1);
+        assert_eq!(first.request_count(1000), 1);
+    }
+
+    #[test]
+    fn test_route_to_new_owner() {
+        let first = MockServer::start();
+        let second = MockServer::start();
+
+        first.respond(1101, partitions_response(1, "test-cache", &[(second.node_id(), &[0, 1])]));
+
+        let configuration = Configuration::default().addresses(&[first.address(), second.address()]).partition_awareness(true);
+
+        let client = Client::start(configuration).unwrap();
+
+        let cache = client.cache("test-cache");
+
+        assert_eq!(cache.put(&Value::I32(1), &Value::I32(1)), Ok(()));
+
+        // The partitions moved to the first node. The old owner still serves the key, and reports
+        // the new topology in its response.
+        second.topology_version(2, 0);
+        first.respond(1101, partitions_response(2, "test-cache", &[(first.node_id(), &[0, 1])]));
+
+        assert_eq!(cache.get(&Value::I32(1)), Ok(Some(Value::I32(1))));
+        assert_eq!(second.request_count(1000), 1);
+
+        assert_eq!(cache.put(&Value::I32(2), &Value::I32(2)), Ok(()));
+
+        assert_eq!(first.request_count(1001), 1);
+        assert!(contains(&first, 2) && !contains(&second, 2));
+    }
+
+    #[test]
+    fn test_scan_partition_range() {
+        let server = MockServer::start();
+
+        server.respond(1101, partitions_response(1, "test-cache", &[(server.node_id(), &[0, 1, 2, 3])]));
+
+        let client = Client::start(server.configuration().partition_awareness(true)).unwrap();
+
+        let cache = client.cache("test-cache");
+
+        assert_eq!(cache.scan_partition(4).err().unwrap().kind, ErrorKind::InvalidArgument);
+        assert_eq!(cache.scan_partition(-1).err().unwrap().kind, ErrorKind::InvalidArgument);
+
+        // In range, so it is sent; the mock doesn't support scans.
+        assert_eq!(cache.scan_partition(3).err().unwrap().kind, ErrorKind::Ignite(1));
+        assert_eq!(server.request_count(2000), 1);
+    }
+
+    #[test]
+    fn test_topology_version_from_partition_map() {
+        let server = MockServer::start();
+
+        server.respond(1101, partitions_response(3, "test-cache", &[(server.node_id(), &[0, 1])]));
+
+        let client = Client::start(server.configuration().partition_awareness(true)).unwrap();
+
+        assert_eq!(client.topology_version(), 0);
+        assert_eq!(client.cache("test-cache").put(&Value::I32(1), &Value::I32(1)), Ok(()));
+        assert_eq!(client.topology_version(), 3);
+    }
+}
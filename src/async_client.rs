@@ -0,0 +1,525 @@
+use std::sync::Arc;
+use std::time::Duration;
+use std::future::Future;
+
+use bytes::{BytesMut, Bytes, BufMut};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+use crate::VERSION_1_1_0;
+use crate::binary::{Value, IgniteWrite, IgniteRead, with_lossy_utf8};
+use crate::cache::{CacheFlags, CacheOp, PeekMode, cache_id};
+use crate::configuration::Configuration;
+use crate::error::{Result, ErrorKind, Error};
+use crate::network::{handshake_request, read_handshake_error, read_response_header, read_error};
+use crate::query::{SqlFieldsQuery, QueryResult, read_page};
+
+struct Stream {
+    tcp: TcpStream,
+    request_id: i64,
+}
+
+struct Connection {
+    stream: Mutex<Stream>,
+    read_timeout: Option<Duration>,
+    lossy_utf8: bool,
+    strict_status_codes: bool,
+    keep_binary: bool,
+    default_page_size: i32,
+    default_sql_schema: Option<String>,
+}
+
+impl Connection {
+    async fn connect(configuration: &Configuration) -> Result<Connection> {
+        configuration.validate()?;
+
+        if !configuration.request_middleware.is_empty() || !configuration.response_middleware.is_empty() {
+            return Err(Error::new(ErrorKind::NotSupported, "Middleware is not supported by the async client".to_string()));
+        }
+
+        #[cfg(unix)]
+        {
+            if configuration.unix_socket.is_some() {
+                return Err(Error::new(ErrorKind::NotSupported, "Unix sockets are not supported by the async client".to_string()));
+            }
+        }
+
+        #[cfg(feature = "tls")]
+        {
+            if configuration.tls.is_some() {
+                return Err(Error::new(ErrorKind::NotSupported, "TLS is not supported by the async client".to_string()));
+            }
+        }
+
+        let mut last_error = Error::new(ErrorKind::Configuration, "At least one address is required".to_string());
+
+        for address in &configuration.addresses {
+            match open(configuration, address).await {
+                Ok(tcp) => {
+                    return Ok(Connection {
+                        stream: Mutex::new(Stream { tcp, request_id: 0 }),
+                        read_timeout: configuration.read_timeout,
+                        lossy_utf8: configuration.lossy_utf8,
+                        strict_status_codes: configuration.strict_status_codes,
+                        keep_binary: configuration.keep_binary,
+                        default_page_size: configuration.default_page_size,
+                        default_sql_schema: configuration.default_sql_schema.clone(),
+                    });
+                },
+                Err(err) => last_error = err,
+            }
+        }
+
+        Err(last_error)
+    }
+
+    // The connection is locked until the response arrives, so requests from concurrent tasks are sent one by one.
+    async fn execute<R, F1, F2>(&self, operation_code: i16, request_writer: F1, response_reader: F2) -> Result<R>
+        where
+            F1: FnOnce(&mut BytesMut) -> Result<()>,
+            F2: FnOnce(&mut Bytes) -> Result<R>,
+    {
+        let mut stream = self.stream.lock().await;
+
+        stream.request_id = stream.request_id.wrapping_add(1);
+
+        let request_id = stream.request_id;
+
+        let mut request = BytesMut::with_capacity(1024);
+
+        request.put_i16_le(operation_code);
+        request.put_i64_le(request_id);
+
+        request_writer(&mut request)?;
+
+        let mut response = with_timeout(self.read_timeout, send(&mut stream.tcp, &request)).await?;
+
+        drop(stream);
+
+        let (status, _) = read_response_header(request_id, &mut response, VERSION_1_1_0)?;
+
+        if status == 0 {
+            with_lossy_utf8(self.lossy_utf8, || response_reader(&mut response))
+        }
+        else {
+            Err(read_error(status, &mut response, self.strict_status_codes))
+        }
+    }
+}
+
+async fn open(configuration: &Configuration, address: &str) -> Result<TcpStream> {
+    let mut tcp = with_timeout(configuration.connect_timeout, async {
+        Ok(TcpStream::connect(address).await?)
+    }).await?;
+
+    tcp.set_nodelay(true)?;
+
+    // Doesn't negotiate newer versions yet, so the response header never carries flags.
+    let request = handshake_request(configuration, VERSION_1_1_0)?;
+
+    let mut response = with_timeout(configuration.read_timeout, send(&mut tcp, &request)).await?;
+
+    if i8::read(&mut response)? == 1 {
+        Ok(tcp)
+    }
+    else {
+        Err(read_handshake_error(&mut response, VERSION_1_1_0)?)
+    }
+}
+
+async fn send(tcp: &mut TcpStream, msg: &[u8]) -> Result<Bytes> {
+    tcp.write_all(&(msg.len() as i32).to_le_bytes()).await?;
+    tcp.write_all(msg).await?;
+    tcp.flush().await?;
+
+    let len = tcp.read_i32_le().await?;
+
+    if len < 0 {
+        return Err(Error::new(ErrorKind::Network, format!("Invalid message length: {}", len)));
+    }
+
+    let mut msg = vec![0u8; len as usize];
+
+    tcp.read_exact(&mut msg).await?;
+
+    Ok(Bytes::from(msg))
+}
+
+async fn with_timeout<R, F: Future<Output = Result<R>>>(timeout: Option<Duration>, future: F) -> Result<R> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future).await
+            .map_err(|_| Error::new(ErrorKind::Network, format!("Timed out after {:?}", timeout)))?,
+        None => future.await,
+    }
+}
+
+/// Client on top of tokio, enabled with the `async` feature. Handles are cheap to clone and
+/// share a single connection.
+///
+/// Unlike `Client`, it doesn't reconnect, and middleware, TLS and Unix sockets are not supported.
+/// A request whose future is dropped before completion leaves the connection unusable.
+#[derive(Clone)]
+pub struct AsyncClient {
+    connection: Arc<Connection>,
+}
+
+impl AsyncClient {
+    /// Connects to the first address that accepts the handshake.
+    pub async fn start(configuration: Configuration) -> Result<AsyncClient> {
+        let connection = Connection::connect(&configuration).await?;
+
+        Ok(AsyncClient { connection: Arc::new(connection) })
+    }
+
+    pub async fn cache_names(&self) -> Result<Vec<String>> {
+        self.connection.execute(
+            1050,
+            |_| { Ok(()) },
+            |response| {
+                <Vec<String>>::read(response)
+            }
+        ).await
+    }
+
+    pub async fn create_cache(&self, name: &str) -> Result<AsyncCache> {
+        self.connection.execute(
+            1051,
+            |request| {
+                name.to_string().write(request)
+            },
+            |_| { Ok(()) }
+        ).await?;
+
+        Ok(self.cache(name))
+    }
+
+    pub async fn get_or_create_cache(&self, name: &str) -> Result<AsyncCache> {
+        self.connection.execute(
+            1052,
+            |request| {
+                name.to_string().write(request)
+            },
+            |_| { Ok(()) }
+        ).await?;
+
+        Ok(self.cache(name))
+    }
+
+    pub fn cache(&self, name: &str) -> AsyncCache {
+        let flags = if self.connection.keep_binary { CacheFlags::KEEP_BINARY } else { CacheFlags::empty() };
+
+        AsyncCache { id: cache_id(name), connection: self.connection.clone(), flags }
+    }
+}
+
+/// Async counterpart of `Cache`. Transactions are not supported.
+#[derive(Clone)]
+pub struct AsyncCache {
+    id: i32,
+    connection: Arc<Connection>,
+    flags: CacheFlags,
+}
+
+impl AsyncCache {
+    pub fn cache_id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn flags(&self) -> CacheFlags {
+        self.flags
+    }
+
+    pub async fn get(&self, key: &Value) -> Result<Option<Value>> {
+        self.execute(
+            CacheOp::Get,
+            |request| {
+                key.write(request)
+            },
+            |response| {
+                <Option<Value>>::read(response)
+            }
+        ).await
+    }
+
+    pub async fn put(&self, key: &Value, value: &Value) -> Result<()> {
+        self.execute(
+            CacheOp::Put,
+            |request| {
+                key.write(request)?;
+                value.write(request)?;
+
+                Ok(())
+            },
+            |_| { Ok(()) }
+        ).await
+    }
+
+    pub async fn put_if_absent(&self, key: &Value, value: &Value) -> Result<bool> {
+        self.execute(
+            CacheOp::PutIfAbsent,
+            |request| {
+                key.write(request)?;
+                value.write(request)?;
+
+                Ok(())
+            },
+            |response| {
+                bool::read(response)
+            }
+        ).await
+    }
+
+    /// Sends all keys in a single request; `Configuration::chunk_size` is not applied.
+    pub async fn get_all(&self, keys: &[Value]) -> Result<Vec<(Value, Option<Value>)>> {
+        self.execute(
+            CacheOp::GetAll,
+            |request| {
+                keys.write(request)
+            },
+            |response| {
+                <Vec<(Value, Option<Value>)>>::read(response)
+            }
+        ).await
+    }
+
+    pub async fn put_all(&self, entries: &[(Value, Value)]) -> Result<()> {
+        self.execute(
+            CacheOp::PutAll,
+            |request| {
+                entries.write(request)
+            },
+            |_| { Ok(()) }
+        ).await
+    }
+
+    pub async fn get_and_put(&self, key: &Value, value: &Value) -> Result<Option<Value>> {
+        self.execute(
+            CacheOp::GetAndPut,
+            |request| {
+                key.write(request)?;
+                value.write(request)?;
+
+                Ok(())
+            },
+            |response| {
+                <Option<Value>>::read(response)
+            }
+        ).await
+    }
+
+    pub async fn get_and_remove(&self, key: &Value) -> Result<Option<Value>> {
+        self.execute(
+            CacheOp::GetAndRemove,
+            |request| {
+                key.write(request)
+            },
+            |response| {
+                <Option<Value>>::read(response)
+            }
+        ).await
+    }
+
+    pub async fn replace(&self, key: &Value, value: &Value) -> Result<bool> {
+        self.execute(
+            CacheOp::Replace,
+            |request| {
+                key.write(request)?;
+                value.write(request)?;
+
+                Ok(())
+            },
+            |response| {
+                bool::read(response)
+            }
+        ).await
+    }
+
+    pub async fn contains_key(&self, key: &Value) -> Result<bool> {
+        self.execute(
+            CacheOp::ContainsKey,
+            |request| {
+                key.write(request)
+            },
+            |response| {
+                bool::read(response)
+            }
+        ).await
+    }
+
+    pub async fn remove_key(&self, key: &Value) -> Result<bool> {
+        self.execute(
+            CacheOp::RemoveKey,
+            |request| {
+                key.write(request)
+            },
+            |response| {
+                bool::read(response)
+            }
+        ).await
+    }
+
+    pub async fn remove_all(&self) -> Result<()> {
+        self.execute(
+            CacheOp::RemoveAll,
+            |_| { Ok(()) },
+            |_| { Ok(()) }
+        ).await
+    }
+
+    pub async fn clear(&self) -> Result<()> {
+        self.execute(
+            CacheOp::Clear,
+            |_| { Ok(()) },
+            |_| { Ok(()) }
+        ).await
+    }
+
+    pub async fn size(&self, peek_modes: &[PeekMode]) -> Result<i64> {
+        self.execute(
+            CacheOp::GetSize,
+            |request| {
+                peek_modes.write(request)
+            },
+            |response| {
+                i64::read(response)
+            }
+        ).await
+    }
+
+    /// Fetches all pages of the result, so the server cursor is closed once this returns.
+    pub async fn query_fields(&self, query: SqlFieldsQuery) -> Result<QueryResult> {
+        let query = query.default_page_size(self.connection.default_page_size)
+            .default_schema(self.connection.default_sql_schema.as_deref());
+
+        let (cursor_id, columns, column_count, mut rows, mut has_more) = self.execute(
+            CacheOp::QuerySqlFields,
+            |request| {
+                query.write(request)
+            },
+            |response| {
+                let cursor_id = i64::read(response)?;
+                let column_count = i32::read(response)? as usize;
+
+                let mut columns = Vec::new();
+
+                if query.include_field_names {
+                    for _ in 0 .. column_count {
+                        columns.push(String::read(response)?);
+                    }
+                }
+
+                let (rows, has_more) = read_page(response, column_count)?;
+
+                Ok((cursor_id, columns, column_count, rows, has_more))
+            }
+        ).await?;
+
+        while has_more {
+            let (page, more) = self.connection.execute(
+                2005,
+                |request| {
+                    cursor_id.write(request)
+                },
+                |response| {
+                    read_page(response, column_count)
+                }
+            ).await?;
+
+            rows.extend(page);
+
+            has_more = more;
+        }
+
+        Ok(QueryResult { columns, rows })
+    }
+
+    /// Shorthand for `query_fields` with default options and column names included.
+    pub async fn query_sql_fields(&self, sql: &str, args: &[Value]) -> Result<QueryResult> {
+        self.query_fields(SqlFieldsQuery::new(sql).args(args).include_field_names(true)).await
+    }
+
+    async fn execute<R, F1, F2>(&self, op: CacheOp, request_writer: F1, response_reader: F2) -> Result<R>
+        where
+            F1: FnOnce(&mut BytesMut) -> Result<()>,
+            F2: FnOnce(&mut Bytes) -> Result<R>,
+    {
+        self.connection.execute(
+            op.code(),
+            |request| {
+                self.id.write(request)?;
+                request.put_u8(self.flags.bits());
+
+                request_writer(request)
+            },
+            response_reader
+        ).await
+    }
+}
+
+// === Tests
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+
+    use crate::{AsyncClient, ErrorKind};
+    use crate::binary::{IgniteWrite, Value};
+    use crate::mock::MockServer;
+
+    #[tokio::test]
+    async fn test_put_get() {
+        let server = MockServer::start();
+
+        let client = AsyncClient::start(server.configuration()).await.unwrap();
+
+        let cache = client.cache("test-cache");
+
+        assert_eq!(cache.get(&Value::I32(1)).await, Ok(None));
+        assert_eq!(cache.put(&Value::I32(1), &Value::String("one".to_string())).await, Ok(()));
+        assert_eq!(cache.get(&Value::I32(1)).await, Ok(Some(Value::String("one".to_string()))));
+        assert_eq!(cache.contains_key(&Value::I32(1)).await, Ok(true));
+        assert_eq!(cache.size(&[]).await, Ok(1));
+        assert_eq!(cache.remove_key(&Value::I32(1)).await, Ok(true));
+        assert_eq!(cache.get(&Value::I32(1)).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_query_sql_fields() {
+        let server = MockServer::start();
+
+        let mut first = BytesMut::new();
+
+        7i64.write(&mut first).unwrap(); // Cursor ID.
+        1i32.write(&mut first).unwrap(); // Column count.
+        "X".to_string().write(&mut first).unwrap();
+        1i32.write(&mut first).unwrap(); // Row count.
+        Value::I64(1).write(&mut first).unwrap();
+        true.write(&mut first).unwrap(); // Has more.
+
+        let mut second = BytesMut::new();
+
+        1i32.write(&mut second).unwrap();
+        Value::I64(2).write(&mut second).unwrap();
+        false.write(&mut second).unwrap();
+
+        server.respond(2004, first.to_vec());
+        server.respond(2005, second.to_vec());
+
+        let client = AsyncClient::start(server.configuration()).await.unwrap();
+
+        let result = client.cache("test-cache").query_sql_fields("SELECT x FROM system_range(1, 2)", &[]).await.unwrap();
+
+        assert_eq!(result.columns, vec!["X".to_string()]);
+        assert_eq!(result.rows, vec![vec![Some(Value::I64(1))], vec![Some(Value::I64(2))]]);
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_configuration() {
+        let server = MockServer::start();
+
+        let configuration = server.configuration().on_request(|_, _| Ok(()));
+
+        assert_eq!(AsyncClient::start(configuration).await.err().unwrap().kind, ErrorKind::NotSupported);
+    }
+}
@@ -1,21 +1,63 @@
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{RefCell, Cell};
 use std::collections::{HashSet, HashMap, LinkedList};
 use std::hash::{Hash, Hasher};
+use std::collections::hash_map::DefaultHasher;
+use std::mem;
+use std::convert::TryFrom;
 
 use bytes::{BufMut, Buf, BytesMut, Bytes};
 use uuid::Uuid;
 use linked_hash_set::LinkedHashSet;
 use linked_hash_map::LinkedHashMap;
-use chrono::{NaiveDateTime, Timelike};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Timelike, TimeZone, Utc, Local};
 
+use crate::{Version, VERSION_1_1_0};
 use crate::error::{Result, ErrorKind, Error};
 use crate::network::Tcp;
 use bigdecimal::BigDecimal;
-use num_bigint::BigInt;
+use num_bigint::{BigInt, Sign};
+use num_traits::ToPrimitive;
+
+pub use visit::ValueVisitor;
+pub use builder::BinaryObjectBuilder;
+
+mod visit;
+mod builder;
 
 const PROTO_VER: i8 = 1;
 
+thread_local! {
+    static LOSSY_UTF8: Cell<bool> = const { Cell::new(false) };
+    static PROTOCOL_VERSION: Cell<Version> = const { Cell::new(VERSION_1_1_0) };
+}
+
+// Controls how `String::read` treats invalid UTF-8 while `f` runs.
+pub(crate) fn with_lossy_utf8<R, F: FnOnce() -> R>(lossy: bool, f: F) -> R {
+    let prev = LOSSY_UTF8.with(|cell| cell.replace(lossy));
+
+    let result = f();
+
+    LOSSY_UTF8.with(|cell| cell.set(prev));
+
+    result
+}
+
+// Negotiated protocol version for types whose layout depends on it while `f` runs.
+pub(crate) fn with_protocol_version<R, F: FnOnce() -> R>(version: Version, f: F) -> R {
+    let prev = PROTOCOL_VERSION.with(|cell| cell.replace(version));
+
+    let result = f();
+
+    PROTOCOL_VERSION.with(|cell| cell.set(prev));
+
+    result
+}
+
+pub(crate) fn protocol_version() -> Version {
+    PROTOCOL_VERSION.with(Cell::get)
+}
+
 pub struct Binary {
     tcp: Rc<RefCell<Tcp>>,
 }
@@ -51,7 +93,12 @@ impl Binary {
                 Ok(())
             },
             |_| { Ok(()) }
-        )
+        )?;
+
+        // Replayed if the connection is re-established.
+        self.tcp.borrow_mut().type_names.insert(type_id, type_name.to_string());
+
+        Ok(())
     }
 
     pub fn get_type(&self, type_id: i32) -> Result<Option<Type>> {
@@ -154,10 +201,10 @@ pub struct Field {
 #[derive(IgniteRead, IgniteWrite)]
 pub struct Schema {
     pub id: i32,
-    pub fields: Vec<(i32, i32)>,
+    pub fields: Vec<i32>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Value {
     I8(i8),
     I16(i16),
@@ -165,12 +212,20 @@ pub enum Value {
     I64(i64),
     F32(f32),
     F64(f64),
+    /// Java `char`: characters outside the Basic Multilingual Plane fail to write with a `Serde` error.
     Char(char),
     Bool(bool),
     String(String),
     Uuid(Uuid),
+    /// Always UTC. Use `From<DateTime<Tz>>` and `Value::to_utc`/`Value::to_local` to convert.
     Timestamp(NaiveDateTime),
+    /// Java `Date` at midnight UTC.
+    Date(NaiveDate),
+    /// Millisecond precision.
+    Time(NaiveTime),
     Decimal(BigDecimal),
+    /// Java enum constant.
+    Enum { type_id: i32, ordinal: i32 },
     I8Vec(Vec<i8>),
     I16Vec(Vec<i16>),
     I32Vec(Vec<i32>),
@@ -182,44 +237,447 @@ pub enum Value {
     StringVec(Vec<String>),
     UuidVec(Vec<Uuid>),
     TimestampVec(Vec<NaiveDateTime>),
-    DecimalVec(Vec<BigDecimal>),
+    DateVec(Vec<NaiveDate>),
+    TimeVec(Vec<NaiveTime>),
+    DecimalVec(Vec<Option<BigDecimal>>),
+    /// Object array; `type_id` is the component type (-1 for `Object`).
+    ObjectVec { type_id: i32, items: Vec<Value> },
+    /// Enum array; items are `Enum` or `Null`.
+    EnumVec { type_id: i32, items: Vec<Value> },
     Vec(Vec<Value>),
     LinkedList(LinkedList<Value>),
     HashSet(HashSet<Value>),
     LinkedHashSet(LinkedHashSet<Value>),
+    /// Collection with a type other than the ones above (e.g. user collections or singleton lists).
+    UserCollection { type_byte: i8, items: Vec<Value> },
     HashMap(HashMap<Value, Value>),
     LinkedHashMap(LinkedHashMap<Value, Value>),
     BinaryObject(BinaryObject),
+    /// Null element of an array, collection or map. Top-level nulls are read as `None`.
+    Null,
 }
 
-// TODO: Implement
-impl PartialEq for Value {
-    fn eq(&self, _other: &Self) -> bool {
-        unimplemented!()
+impl Value {
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = BytesMut::new();
+
+        self.write(&mut bytes)?;
+
+        Ok(bytes.to_vec())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Value> {
+        let mut bytes = Bytes::copy_from_slice(bytes);
+
+        let value = Value::read(&mut bytes)?;
+
+        if bytes.is_empty() {
+            Ok(value)
+        }
+        else {
+            Err(Error::new(ErrorKind::Serde, format!("Unexpected trailing bytes: {}", bytes.len())))
+        }
+    }
+
+    /// Exact number of bytes `to_bytes` would produce.
+    pub fn serialized_size(&self) -> usize {
+        match self {
+            Value::I8(_) | Value::Bool(_) => 1 + 1,
+            Value::I16(_) | Value::Char(_) => 1 + 2,
+            Value::I32(_) | Value::F32(_) => 1 + 4,
+            Value::I64(_) | Value::F64(_) => 1 + 8,
+            Value::String(v) => string_size(v),
+            Value::Uuid(_) => UUID_SIZE,
+            Value::Timestamp(_) => TIMESTAMP_SIZE,
+            Value::Date(_) | Value::Time(_) => 1 + 8,
+            Value::Decimal(v) => decimal_size(v),
+            Value::Enum { .. } => 1 + 4 + 4,
+            Value::I8Vec(v) => 1 + 4 + v.len(),
+            Value::I16Vec(v) => 1 + 4 + v.len() * 2,
+            Value::I32Vec(v) => 1 + 4 + v.len() * 4,
+            Value::I64Vec(v) => 1 + 4 + v.len() * 8,
+            Value::F32Vec(v) => 1 + 4 + v.len() * 4,
+            Value::F64Vec(v) => 1 + 4 + v.len() * 8,
+            Value::CharVec(v) => 1 + 4 + v.len() * 2,
+            Value::BoolVec(v) => 1 + 4 + v.len(),
+            Value::StringVec(v) => 1 + 4 + v.iter().map(|s| string_size(s)).sum::<usize>(),
+            Value::UuidVec(v) => 1 + 4 + v.len() * UUID_SIZE,
+            Value::TimestampVec(v) => 1 + 4 + v.len() * TIMESTAMP_SIZE,
+            Value::DateVec(v) => 1 + 4 + v.len() * (1 + 8),
+            Value::TimeVec(v) => 1 + 4 + v.len() * (1 + 8),
+            Value::DecimalVec(v) => 1 + 4 + v.iter().map(|d| d.as_ref().map_or(1, decimal_size)).sum::<usize>(),
+            Value::ObjectVec { items, .. } => 1 + 4 + 4 + items.iter().map(Value::serialized_size).sum::<usize>(),
+            Value::EnumVec { items, .. } => 1 + 4 + 4 + items.iter().map(Value::serialized_size).sum::<usize>(),
+            Value::Vec(v) => 1 + 4 + 1 + v.iter().map(Value::serialized_size).sum::<usize>(),
+            Value::LinkedList(v) => 1 + 4 + 1 + v.iter().map(Value::serialized_size).sum::<usize>(),
+            Value::HashSet(v) => 1 + 4 + 1 + v.iter().map(Value::serialized_size).sum::<usize>(),
+            Value::LinkedHashSet(v) => 1 + 4 + 1 + v.iter().map(Value::serialized_size).sum::<usize>(),
+            Value::UserCollection { items, .. } => 1 + 4 + 1 + items.iter().map(Value::serialized_size).sum::<usize>(),
+            Value::HashMap(v) => 1 + 4 + 1 + v.iter().map(|(k, v)| k.serialized_size() + v.serialized_size()).sum::<usize>(),
+            Value::LinkedHashMap(v) => 1 + 4 + 1 + v.iter().map(|(k, v)| k.serialized_size() + v.serialized_size()).sum::<usize>(),
+            Value::BinaryObject(v) => 1 + 1 + 2 + 4 + 4 + 4 + v.bytes.len(),
+            Value::Null => 1,
+        }
+    }
+
+    pub fn to_utc(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Value::Timestamp(v) => Some(Utc.from_utc_datetime(v)),
+            _ => None,
+        }
+    }
+
+    pub fn to_local(&self) -> Option<DateTime<Local>> {
+        self.to_utc().map(|v| v.with_timezone(&Local))
+    }
+}
+
+const UUID_SIZE: usize = 1 + 16;
+const TIMESTAMP_SIZE: usize = 1 + 8 + 4;
+
+fn string_size(v: &str) -> usize {
+    1 + 4 + v.len()
+}
+
+fn decimal_size(v: &BigDecimal) -> usize {
+    let (int, _) = v.as_bigint_and_exponent();
+    let bits = int.bits();
+
+    // Zero is a single byte, and an extra byte is needed if the sign bit is taken by the magnitude.
+    let len = if bits == 0 { 1 } else { bits / 8 + 1 };
+
+    1 + 4 + 4 + len
+}
+
+impl<Tz: TimeZone> From<DateTime<Tz>> for Value {
+    fn from(datetime: DateTime<Tz>) -> Value {
+        Value::Timestamp(datetime.naive_utc())
+    }
+}
+
+macro_rules! value_from {
+    ($($type:ty => $variant:ident),+ $(,)?) => {
+        $(
+            impl From<$type> for Value {
+                fn from(v: $type) -> Value {
+                    Value::$variant(v)
+                }
+            }
+        )+
+    }
+}
+
+value_from!(
+    i8 => I8,
+    i16 => I16,
+    i32 => I32,
+    i64 => I64,
+    f32 => F32,
+    f64 => F64,
+    char => Char,
+    bool => Bool,
+    String => String,
+    Uuid => Uuid,
+    NaiveDateTime => Timestamp,
+    NaiveDate => Date,
+    NaiveTime => Time,
+    BigDecimal => Decimal,
+    Vec<i8> => I8Vec,
+    Vec<i16> => I16Vec,
+    Vec<i32> => I32Vec,
+    Vec<i64> => I64Vec,
+    Vec<f32> => F32Vec,
+    Vec<f64> => F64Vec,
+    Vec<char> => CharVec,
+    Vec<bool> => BoolVec,
+    Vec<String> => StringVec,
+    Vec<Uuid> => UuidVec,
+    Vec<NaiveDateTime> => TimestampVec,
+    Vec<NaiveDate> => DateVec,
+    Vec<NaiveTime> => TimeVec,
+    Vec<Value> => Vec,
+);
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Value {
+        Value::String(v.to_string())
+    }
+}
+
+fn conversion_error(value: &Value, target: &str) -> Error {
+    Error::new(ErrorKind::Serde, format!("Can't convert {:?} to {}", value, target))
+}
+
+// Conversions that only accept the matching variant. `i64`, `f64` and the date types are
+// implemented separately since they also accept other variants.
+macro_rules! value_try_from {
+    ($($type:ty => $variant:ident),+ $(,)?) => {
+        $(
+            impl TryFrom<Value> for $type {
+                type Error = Error;
+
+                fn try_from(value: Value) -> Result<$type> {
+                    match value {
+                        Value::$variant(v) => Ok(v),
+                        value => Err(conversion_error(&value, stringify!($type))),
+                    }
+                }
+            }
+        )+
+    }
+}
+
+value_try_from!(
+    i8 => I8,
+    i16 => I16,
+    i32 => I32,
+    f32 => F32,
+    char => Char,
+    bool => Bool,
+    String => String,
+    Uuid => Uuid,
+    NaiveTime => Time,
+    Vec<i8> => I8Vec,
+    Vec<i16> => I16Vec,
+    Vec<i32> => I32Vec,
+    Vec<i64> => I64Vec,
+    Vec<f32> => F32Vec,
+    Vec<f64> => F64Vec,
+    Vec<char> => CharVec,
+    Vec<bool> => BoolVec,
+    Vec<String> => StringVec,
+    Vec<Uuid> => UuidVec,
+    Vec<NaiveDateTime> => TimestampVec,
+    Vec<NaiveDate> => DateVec,
+    Vec<NaiveTime> => TimeVec,
+    Vec<Value> => Vec,
+);
+
+impl TryFrom<Value> for BigDecimal {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<BigDecimal> {
+        match value {
+            Value::Decimal(v) => Ok(v),
+            value => Err(conversion_error(&value, "BigDecimal")),
+        }
+    }
+}
+
+/// Decimals are converted to the nearest `f64`, so digits beyond its precision are lost.
+impl TryFrom<Value> for f64 {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<f64> {
+        match value {
+            Value::F64(v) => Ok(v),
+            Value::Decimal(ref v) => v.to_f64().ok_or_else(|| conversion_error(&value, "f64")),
+            value => Err(conversion_error(&value, "f64")),
+        }
+    }
+}
+
+/// Decimals with a fractional part or out of the `i64` range are rejected.
+impl TryFrom<Value> for i64 {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<i64> {
+        match value {
+            Value::I64(v) => Ok(v),
+            Value::Decimal(ref v) if v.is_integer() => v.to_i64().ok_or_else(|| conversion_error(&value, "i64")),
+            value => Err(conversion_error(&value, "i64")),
+        }
     }
+}
+
+impl TryFrom<Value> for NaiveDateTime {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<NaiveDateTime> {
+        match value {
+            Value::Timestamp(v) => Ok(v),
+            Value::Date(v) => Ok(v.and_time(NaiveTime::MIN)),
+            value => Err(conversion_error(&value, "NaiveDateTime")),
+        }
+    }
+}
+
+/// Timestamps are truncated to their (UTC) date.
+impl TryFrom<Value> for NaiveDate {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<NaiveDate> {
+        match value {
+            Value::Timestamp(v) => Ok(v.date()),
+            Value::Date(v) => Ok(v),
+            value => Err(conversion_error(&value, "NaiveDate")),
+        }
+    }
+}
+
+// Tuples are written as `Value::Vec`, i.e. a Java `ArrayList`. Unlike arrays, lists compare by
+// content on the server, so tuples can be used as composite keys.
+macro_rules! tuple_conversions {
+    ($len:expr; $($type:ident $var:ident),+) => {
+        impl<$($type: Into<Value>),+> From<($($type,)+)> for Value {
+            fn from(($($var,)+): ($($type,)+)) -> Value {
+                Value::Vec(vec![$($var.into()),+])
+            }
+        }
+
+        impl<$($type),+> TryFrom<Value> for ($($type,)+)
+            where
+                $($type: TryFrom<Value>, Error: From<$type::Error>,)+
+        {
+            type Error = Error;
+
+            fn try_from(value: Value) -> Result<($($type,)+)> {
+                match value {
+                    Value::Vec(items) if items.len() == $len => {
+                        let mut items = items.into_iter();
 
-    fn ne(&self, _other: &Self) -> bool {
-        unimplemented!()
+                        $(let $var = $type::try_from(items.next().unwrap())?;)+
+
+                        Ok(($($var,)+))
+                    },
+                    value => Err(conversion_error(&value, concat!(stringify!($len), "-tuple"))),
+                }
+            }
+        }
+    };
+}
+
+tuple_conversions!(2; A a, B b);
+tuple_conversions!(3; A a, B b, C c);
+
+/// Structural equality. Floats are compared by their bits, so `NaN` equals itself and `0.0`
+/// differs from `-0.0`, which keeps equality consistent with `Hash`.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::I8(a), Value::I8(b)) => a == b,
+            (Value::I16(a), Value::I16(b)) => a == b,
+            (Value::I32(a), Value::I32(b)) => a == b,
+            (Value::I64(a), Value::I64(b)) => a == b,
+            (Value::F32(a), Value::F32(b)) => a.to_bits() == b.to_bits(),
+            (Value::F64(a), Value::F64(b)) => a.to_bits() == b.to_bits(),
+            (Value::Char(a), Value::Char(b)) => a == b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Uuid(a), Value::Uuid(b)) => a == b,
+            (Value::Timestamp(a), Value::Timestamp(b)) => a == b,
+            (Value::Date(a), Value::Date(b)) => a == b,
+            (Value::Time(a), Value::Time(b)) => a == b,
+            (Value::Decimal(a), Value::Decimal(b)) => a == b,
+            (Value::Enum { type_id: a_type, ordinal: a }, Value::Enum { type_id: b_type, ordinal: b }) =>
+                a_type == b_type && a == b,
+            (Value::I8Vec(a), Value::I8Vec(b)) => a == b,
+            (Value::I16Vec(a), Value::I16Vec(b)) => a == b,
+            (Value::I32Vec(a), Value::I32Vec(b)) => a == b,
+            (Value::I64Vec(a), Value::I64Vec(b)) => a == b,
+            (Value::F32Vec(a), Value::F32Vec(b)) => a.iter().map(|v| v.to_bits()).eq(b.iter().map(|v| v.to_bits())),
+            (Value::F64Vec(a), Value::F64Vec(b)) => a.iter().map(|v| v.to_bits()).eq(b.iter().map(|v| v.to_bits())),
+            (Value::CharVec(a), Value::CharVec(b)) => a == b,
+            (Value::BoolVec(a), Value::BoolVec(b)) => a == b,
+            (Value::StringVec(a), Value::StringVec(b)) => a == b,
+            (Value::UuidVec(a), Value::UuidVec(b)) => a == b,
+            (Value::TimestampVec(a), Value::TimestampVec(b)) => a == b,
+            (Value::DateVec(a), Value::DateVec(b)) => a == b,
+            (Value::TimeVec(a), Value::TimeVec(b)) => a == b,
+            (Value::DecimalVec(a), Value::DecimalVec(b)) => a == b,
+            (Value::ObjectVec { type_id: a_type, items: a }, Value::ObjectVec { type_id: b_type, items: b }) =>
+                a_type == b_type && a == b,
+            (Value::EnumVec { type_id: a_type, items: a }, Value::EnumVec { type_id: b_type, items: b }) =>
+                a_type == b_type && a == b,
+            (Value::Vec(a), Value::Vec(b)) => a == b,
+            (Value::LinkedList(a), Value::LinkedList(b)) => a == b,
+            (Value::HashSet(a), Value::HashSet(b)) => a == b,
+            (Value::LinkedHashSet(a), Value::LinkedHashSet(b)) => a == b,
+            (Value::UserCollection { type_byte: a_type, items: a }, Value::UserCollection { type_byte: b_type, items: b }) =>
+                a_type == b_type && a == b,
+            (Value::HashMap(a), Value::HashMap(b)) => a == b,
+            (Value::LinkedHashMap(a), Value::LinkedHashMap(b)) => a == b,
+            (Value::BinaryObject(a), Value::BinaryObject(b)) => a == b,
+            (Value::Null, Value::Null) => true,
+            _ => false,
+        }
     }
 }
 
-// TODO: Eq vs PartialEq?
 impl Eq for Value {}
 
-// TODO: Implement
 impl Hash for Value {
-    fn hash<H: Hasher>(&self, _state: &mut H) {
-        unimplemented!()
-    }
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
 
-    fn hash_slice<H: Hasher>(_data: &[Self], _state: &mut H)
-        where Self: Sized
-    {
-        unimplemented!()
+        match self {
+            Value::I8(v) => v.hash(state),
+            Value::I16(v) => v.hash(state),
+            Value::I32(v) => v.hash(state),
+            Value::I64(v) => v.hash(state),
+            Value::F32(v) => v.to_bits().hash(state),
+            Value::F64(v) => v.to_bits().hash(state),
+            Value::Char(v) => v.hash(state),
+            Value::Bool(v) => v.hash(state),
+            Value::String(v) => v.hash(state),
+            Value::Uuid(v) => v.hash(state),
+            Value::Timestamp(v) => v.hash(state),
+            Value::Date(v) => v.hash(state),
+            Value::Time(v) => v.hash(state),
+            Value::Decimal(v) => v.hash(state),
+            Value::Enum { type_id, ordinal } => {
+                type_id.hash(state);
+                ordinal.hash(state);
+            },
+            Value::I8Vec(v) => v.hash(state),
+            Value::I16Vec(v) => v.hash(state),
+            Value::I32Vec(v) => v.hash(state),
+            Value::I64Vec(v) => v.hash(state),
+            Value::F32Vec(v) => v.iter().map(|v| v.to_bits()).collect::<Vec<u32>>().hash(state),
+            Value::F64Vec(v) => v.iter().map(|v| v.to_bits()).collect::<Vec<u64>>().hash(state),
+            Value::CharVec(v) => v.hash(state),
+            Value::BoolVec(v) => v.hash(state),
+            Value::StringVec(v) => v.hash(state),
+            Value::UuidVec(v) => v.hash(state),
+            Value::TimestampVec(v) => v.hash(state),
+            Value::DateVec(v) => v.hash(state),
+            Value::TimeVec(v) => v.hash(state),
+            Value::DecimalVec(v) => v.hash(state),
+            Value::ObjectVec { type_id, items } | Value::EnumVec { type_id, items } => {
+                type_id.hash(state);
+                items.hash(state);
+            },
+            Value::Vec(v) => v.hash(state),
+            Value::LinkedList(v) => v.hash(state),
+            // Set equality ignores order (including for `LinkedHashSet`), so hashing must too.
+            Value::HashSet(v) => unordered_hash(v.iter()).hash(state),
+            Value::LinkedHashSet(v) => unordered_hash(v.iter()).hash(state),
+            Value::UserCollection { type_byte, items } => {
+                type_byte.hash(state);
+                items.hash(state);
+            },
+            Value::HashMap(v) => unordered_hash(v.iter()).hash(state),
+            Value::LinkedHashMap(v) => v.hash(state),
+            Value::BinaryObject(v) => v.hash(state),
+            Value::Null => {},
+        }
     }
 }
 
-#[derive(PartialEq, Debug)]
+// Order-independent hash of a collection: sum of the element hashes.
+fn unordered_hash<T: Hash, I: Iterator<Item = T>>(items: I) -> u64 {
+    items
+        .map(|item| {
+            let mut hasher = DefaultHasher::new();
+
+            item.hash(&mut hasher);
+
+            hasher.finish()
+        })
+        .fold(0u64, u64::wrapping_add)
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub struct BinaryObject {
     flags: i16,
     type_id: i32,
@@ -227,18 +685,191 @@ pub struct BinaryObject {
     bytes: Bytes,
 }
 
+const FLAG_HAS_SCHEMA: i16 = 0x0002;
+const FLAG_HAS_RAW_DATA: i16 = 0x0004;
+const FLAG_OFFSET_ONE_BYTE: i16 = 0x0008;
+const FLAG_OFFSET_TWO_BYTES: i16 = 0x0010;
+const FLAG_COMPACT_FOOTER: i16 = 0x0020;
+
+// Part of the header that is not stored in `BinaryObject::bytes`.
+const HEADER_PREFIX_LEN: usize = 16;
+
+const TYPE_HANDLE: u8 = 102;
+
 impl BinaryObject {
-    pub fn field(&self, _name: &str) -> Result<Option<Value>> {
-        Ok(None)
+    pub fn type_id(&self) -> i32 {
+        self.type_id
+    }
+
+    pub fn hash_code(&self) -> i32 {
+        self.hash_code
+    }
+
+    pub fn schema_id(&self) -> i32 {
+        self.header_i32(0)
+    }
+
+    /// Hash code Ignite expects for the object's content. Objects built or modified on the
+    /// client have to carry it, or the server misplaces them in hash-based indexes.
+    pub fn computed_hash_code(&self) -> Result<i32> {
+        // Field data lies between the header and the footer.
+        let end = if self.flags & FLAG_HAS_SCHEMA != 0 {
+            (self.header_i32(4) as usize).checked_sub(HEADER_PREFIX_LEN)
+                .filter(|end| (8 ..= self.bytes.len()).contains(end))
+                .ok_or_else(|| Error::new(ErrorKind::Serde, "Invalid schema offset.".to_string()))?
+        }
+        else {
+            self.bytes.len()
+        };
+
+        Ok(data_hash(&self.bytes[8 .. end]))
+    }
+
+    /// Compact footers only store field offsets, so field IDs have to come from the type metadata.
+    pub fn has_compact_footer(&self) -> bool {
+        self.flags & FLAG_COMPACT_FOOTER != 0
+    }
+
+    /// Use `Cache::get_field` for objects with a compact footer.
+    pub fn field(&self, name: &str) -> Result<Option<Value>> {
+        Ok(self.fields(&[name], None)?.pop().flatten())
+    }
+
+    // Same as `field`, by field ID.
+    pub(crate) fn field_by_id(&self, id: i32) -> Result<Option<Value>> {
+        match self.field_offsets(None)?.into_iter().find(|(field_id, _)| *field_id == id) {
+            Some((_, offset)) => self.read_field(offset),
+            None => Ok(None),
+        }
+    }
+
+    // `schema` lists field IDs in footer order and is required for compact footers.
+    pub(crate) fn fields(&self, names: &[&str], schema: Option<&[i32]>) -> Result<Vec<Option<Value>>> {
+        let offsets = self.field_offsets(schema)?;
+
+        names.iter()
+            .map(|name| {
+                let id = field_id(name);
+
+                match offsets.iter().find(|(field_id, _)| *field_id == id) {
+                    Some((_, offset)) => self.read_field(*offset),
+                    None => Ok(None),
+                }
+            })
+            .collect()
+    }
+
+    fn field_offsets(&self, schema: Option<&[i32]>) -> Result<Vec<(i32, usize)>> {
+        if self.flags & FLAG_HAS_SCHEMA == 0 {
+            return Ok(Vec::new());
+        }
+
+        let offset_len =
+            if self.flags & FLAG_OFFSET_ONE_BYTE != 0 { 1 }
+            else if self.flags & FLAG_OFFSET_TWO_BYTES != 0 { 2 }
+            else { 4 };
+
+        let start = (self.header_i32(4) as usize).checked_sub(HEADER_PREFIX_LEN)
+            .ok_or_else(|| Error::new(ErrorKind::Serde, "Invalid schema offset.".to_string()))?;
+
+        let end = if self.flags & FLAG_HAS_RAW_DATA != 0 { self.bytes.len() - 4 } else { self.bytes.len() };
+
+        if start > end {
+            return Err(Error::new(ErrorKind::Serde, "Invalid schema offset.".to_string()));
+        }
+
+        let mut footer = self.bytes.slice(start .. end);
+
+        let read_offset = |footer: &mut Bytes| -> Result<usize> {
+            if footer.remaining() < offset_len {
+                return Err(Error::new(ErrorKind::Serde, "Unexpected end of footer.".to_string()));
+            }
+
+            Ok(match offset_len {
+                1 => footer.get_u8() as usize,
+                2 => footer.get_u16_le() as usize,
+                _ => footer.get_i32_le() as usize,
+            })
+        };
+
+        let mut offsets = Vec::new();
+
+        if self.has_compact_footer() {
+            let schema = schema.ok_or_else(|| Error::new(ErrorKind::NotSupported, "Type metadata is required to read fields of an object with a compact footer.".to_string()))?;
+
+            for id in schema {
+                offsets.push((*id, read_offset(&mut footer)?));
+            }
+        }
+        else {
+            while footer.has_remaining() {
+                if footer.remaining() < 4 {
+                    return Err(Error::new(ErrorKind::Serde, "Unexpected end of footer.".to_string()));
+                }
+
+                let id = footer.get_i32_le();
+
+                offsets.push((id, read_offset(&mut footer)?));
+            }
+        }
+
+        Ok(offsets)
+    }
+
+    fn read_field(&self, offset: usize) -> Result<Option<Value>> {
+        let pos = match offset.checked_sub(HEADER_PREFIX_LEN) {
+            Some(pos) if pos < self.bytes.len() => pos,
+            _ => return Err(Error::new(ErrorKind::Serde, format!("Invalid field offset: {}", offset))),
+        };
+
+        if self.bytes[pos] != TYPE_HANDLE {
+            return <Option<Value>>::read(&mut self.bytes.slice(pos ..));
+        }
+
+        // Handle to an object written earlier, as a backward offset from the handle's position.
+        // Only targets within this object can be followed.
+        if pos + 5 > self.bytes.len() {
+            return Err(Error::new(ErrorKind::Serde, "Unexpected end of buffer.".to_string()));
+        }
+
+        let handle = self.header_i32(pos + 1);
+
+        match (offset as i64).checked_sub(handle as i64) {
+            Some(0) => Ok(Some(Value::BinaryObject(self.clone()))),
+            Some(target) if handle > 0 && target >= HEADER_PREFIX_LEN as i64 => self.read_field(target as usize),
+            _ => Err(Error::new(ErrorKind::NotSupported, format!("Handle points outside of the object: {}", handle))),
+        }
+    }
+
+    fn header_i32(&self, pos: usize) -> i32 {
+        let mut bytes = [0u8; 4];
+
+        bytes.copy_from_slice(&self.bytes[pos .. pos + 4]);
+
+        i32::from_le_bytes(bytes)
     }
 }
 
+// Same as Java's `BinaryArrayIdentityResolver`: bytes are signed, as in Java.
+pub(crate) fn data_hash(data: &[u8]) -> i32 {
+    data.iter().fold(1i32, |hash, b| hash.wrapping_mul(31).wrapping_add(*b as i8 as i32))
+}
+
+// Same as Java's `String.hashCode` of the lower-cased name.
+pub(crate) fn field_id(name: &str) -> i32 {
+    name.to_lowercase()
+        .encode_utf16()
+        .fold(0i32, |hash, c| hash.wrapping_mul(31).wrapping_add(c as i32))
+}
+
 pub(crate) trait Nullable {}
 
 impl Nullable for Value {}
 impl Nullable for String {}
 impl Nullable for Uuid {}
 impl Nullable for NaiveDateTime {}
+impl Nullable for NaiveDate {}
+impl Nullable for NaiveTime {}
 impl Nullable for BigDecimal {}
 
 pub(crate) trait IgniteWrite {
@@ -322,9 +953,22 @@ impl IgniteWrite for Value {
             Value::Timestamp(v) => {
                 v.write(bytes)
             },
+            Value::Date(v) => {
+                v.write(bytes)
+            },
+            Value::Time(v) => {
+                v.write(bytes)
+            },
             Value::Decimal(v) => {
                 v.write(bytes)
             },
+            Value::Enum { type_id, ordinal } => {
+                bytes.put_i8(28);
+                bytes.put_i32_le(*type_id);
+                bytes.put_i32_le(*ordinal);
+
+                Ok(())
+            },
             Value::I8Vec(v) => {
                 bytes.put_i8(12);
 
@@ -380,11 +1024,32 @@ impl IgniteWrite for Value {
 
                 v.write(bytes)
             },
+            Value::DateVec(v) => {
+                bytes.put_i8(22);
+
+                v.write(bytes)
+            },
+            Value::TimeVec(v) => {
+                bytes.put_i8(37);
+
+                v.write(bytes)
+            },
             Value::DecimalVec(v) => {
                 bytes.put_i8(31);
 
                 v.write(bytes)
             },
+            Value::ObjectVec { type_id, items } | Value::EnumVec { type_id, items } => {
+                bytes.put_i8(if let Value::ObjectVec { .. } = self { 23 } else { 29 });
+                bytes.put_i32_le(*type_id);
+                bytes.put_i32_le(items.len() as i32);
+
+                for item in items {
+                    item.write(bytes)?;
+                }
+
+                Ok(())
+            },
             Value::Vec(v) => {
                 write_collection!(bytes, v, 1);
 
@@ -405,6 +1070,11 @@ impl IgniteWrite for Value {
 
                 Ok(())
             },
+            Value::UserCollection { type_byte, items } => {
+                write_collection!(bytes, items, *type_byte);
+
+                Ok(())
+            },
             Value::HashMap(v) => {
                 write_map!(bytes, v, 1);
 
@@ -424,6 +1094,11 @@ impl IgniteWrite for Value {
                 bytes.put_i32_le((v.bytes.len() + 16) as i32);
                 bytes.put(v.bytes.clone()); // TODO: Can we get rid of clone?
 
+                Ok(())
+            },
+            Value::Null => {
+                bytes.put_i8(101);
+
                 Ok(())
             },
         }
@@ -481,12 +1156,12 @@ impl IgniteWrite for f64 {
 impl IgniteWrite for char {
     fn write(&self, bytes: &mut BytesMut) -> Result<()> {
         if self.len_utf16() == 1 {
-            bytes.put_u16(*self as u16);
+            bytes.put_u16_le(*self as u16);
 
             Ok(())
         }
         else {
-            Err(Error::new(ErrorKind::Serde, "Only UTF-16 characters are supported.".to_string()))
+            Err(Error::new(ErrorKind::Serde, "Only characters in the Basic Multilingual Plane are supported.".to_string()))
         }
     }
 }
@@ -518,12 +1193,12 @@ impl IgniteWrite for Uuid {
         let mut msb: i64 = 0;
         let mut lsb: i64 = 0;
 
-        for i in 0 .. 8 {
-            msb = (msb << 8) | (arr[i] as i64 & 0xFF);
+        for b in &arr[0 .. 8] {
+            msb = (msb << 8) | (*b as i64 & 0xFF);
         }
 
-        for i in 8 .. 16 {
-            lsb = (lsb << 8) | (arr[i] as i64 & 0xFF);
+        for b in &arr[8 .. 16] {
+            lsb = (lsb << 8) | (*b as i64 & 0xFF);
         }
 
         bytes.put_i8(10);
@@ -537,8 +1212,26 @@ impl IgniteWrite for Uuid {
 impl IgniteWrite for NaiveDateTime {
     fn write(&self, bytes: &mut BytesMut) -> Result<()> {
         bytes.put_i8(33);
-        bytes.put_i64_le(self.timestamp_millis());
-        bytes.put_i32_le(self.nanosecond() as i32);
+        bytes.put_i64_le(self.and_utc().timestamp_millis());
+        bytes.put_i32_le((self.nanosecond() % 1_000_000) as i32); // Nanoseconds within the millisecond.
+
+        Ok(())
+    }
+}
+
+impl IgniteWrite for NaiveDate {
+    fn write(&self, bytes: &mut BytesMut) -> Result<()> {
+        bytes.put_i8(11);
+        bytes.put_i64_le(self.and_time(NaiveTime::MIN).and_utc().timestamp_millis());
+
+        Ok(())
+    }
+}
+
+impl IgniteWrite for NaiveTime {
+    fn write(&self, bytes: &mut BytesMut) -> Result<()> {
+        bytes.put_i8(36);
+        bytes.put_i64_le((self.num_seconds_from_midnight() as i64) * 1000 + (self.nanosecond() / 1_000_000) as i64);
 
         Ok(())
     }
@@ -547,7 +1240,16 @@ impl IgniteWrite for NaiveDateTime {
 impl IgniteWrite for BigDecimal {
     fn write(&self, bytes: &mut BytesMut) -> Result<()> {
         let (int, scale) = self.as_bigint_and_exponent();
-        let vec = int.to_signed_bytes_le();
+        let (sign, mut vec) = int.to_bytes_be();
+
+        // Magnitude is big-endian with the sign kept in the highest bit of the first byte.
+        if vec[0] & 0x80 != 0 {
+            vec.insert(0, 0);
+        }
+
+        if sign == Sign::Minus {
+            vec[0] |= 0x80;
+        }
 
         bytes.put_i8(30);
         bytes.put_i32_le(scale as i32);
@@ -617,8 +1319,9 @@ impl IgniteRead for Value {
         let type_code = *bytes.first()
             .ok_or_else(|| Error::new(ErrorKind::Serde, "Out of bytes.".to_string()))?;
 
-        if type_code >= 1 && type_code <= 8 {
-            bytes.advance(1);
+        match type_code {
+            9 | 10 | 11 | 30 | 33 | 36 => {}, // These readers check the type code themselves.
+            _ => bytes.advance(1),
         }
 
         match type_code {
@@ -633,6 +1336,8 @@ impl IgniteRead for Value {
             9 => Ok(Value::String(String::read(bytes)?)),
             10 => Ok(Value::Uuid(Uuid::read(bytes)?)),
             33 => Ok(Value::Timestamp(NaiveDateTime::read(bytes)?)),
+            11 => Ok(Value::Date(NaiveDate::read(bytes)?)),
+            36 => Ok(Value::Time(NaiveTime::read(bytes)?)),
             30 => Ok(Value::Decimal(BigDecimal::read(bytes)?)),
             12 => Ok(Value::I8Vec(<Vec<i8>>::read(bytes)?)),
             13 => Ok(Value::I16Vec(<Vec<i16>>::read(bytes)?)),
@@ -645,13 +1350,38 @@ impl IgniteRead for Value {
             20 => Ok(Value::StringVec(<Vec<String>>::read(bytes)?)),
             21 => Ok(Value::UuidVec(<Vec<Uuid>>::read(bytes)?)),
             34 => Ok(Value::TimestampVec(<Vec<NaiveDateTime>>::read(bytes)?)),
-            31 => Ok(Value::DecimalVec(<Vec<BigDecimal>>::read(bytes)?)),
+            22 => Ok(Value::DateVec(<Vec<NaiveDate>>::read(bytes)?)),
+            37 => Ok(Value::TimeVec(<Vec<NaiveTime>>::read(bytes)?)),
+            31 => Ok(Value::DecimalVec(<Vec<Option<BigDecimal>>>::read(bytes)?)),
+            23 | 29 => {
+                let type_id = i32::read(bytes)?;
+                let len = read_len(bytes)?;
+
+                let mut items = Vec::with_capacity(len);
+
+                for _ in 0 .. len {
+                    items.push(Value::read(bytes)?);
+                }
+
+                if type_code == 23 {
+                    Ok(Value::ObjectVec { type_id, items })
+                }
+                else {
+                    Ok(Value::EnumVec { type_id, items })
+                }
+            },
+            28 => {
+                let type_id = i32::read(bytes)?;
+                let ordinal = i32::read(bytes)?;
+
+                Ok(Value::Enum { type_id, ordinal })
+            },
             24 => {
-                let len = bytes.get_i32_le() as usize;
-                let col_type = bytes.get_i8();
+                let len = read_len(bytes)?;
+                let col_type = i8::read(bytes)?;
 
                 match col_type {
-                    -1 | 0 | 1 | 5 => {
+                    1 => {
                         let mut vec = Vec::with_capacity(len);
 
                         for _ in 0 .. len {
@@ -687,12 +1417,20 @@ impl IgniteRead for Value {
 
                         Ok(Value::LinkedHashSet(linked_hash_set))
                     },
-                    _ => Err(Error::new(ErrorKind::Serde, format!("Invalid collection type: {}", col_type))),
+                    _ => {
+                        let mut items = Vec::with_capacity(len);
+
+                        for _ in 0 .. len {
+                            items.push(Value::read(bytes)?);
+                        }
+
+                        Ok(Value::UserCollection { type_byte: col_type, items })
+                    },
                 }
             },
             25 => {
-                let len = bytes.get_i32_le() as usize;
-                let map_type = bytes.get_i8();
+                let len = read_len(bytes)?;
+                let map_type = i8::read(bytes)?;
 
                 match map_type {
                     1 => {
@@ -716,26 +1454,54 @@ impl IgniteRead for Value {
                     _ => Err(Error::new(ErrorKind::Serde, format!("Invalid map type: {}", map_type))),
                 }
             },
+            27 => {
+                // Wrapped data is unwrapped into the value it contains.
+                let len = read_len(bytes)?;
+                let data = bytes.slice(..len);
+
+                bytes.advance(len);
+
+                let offset = i32::read(bytes)?;
+
+                if offset < 0 || offset as usize >= len {
+                    return Err(Error::new(ErrorKind::Serde, format!("Invalid wrapped data offset: {} (length: {})", offset, len)));
+                }
+
+                Value::read(&mut data.slice(offset as usize ..))
+            },
             103 => {
-                let proto_ver = bytes.get_i8();
+                let proto_ver = i8::read(bytes)?;
 
                 if proto_ver == PROTO_VER {
-                    let flags = bytes.get_i16_le();
-                    let type_id = bytes.get_i32_le();
-                    let hash_code = bytes.get_i32_le();
-                    let len = (bytes.get_i32_le() - 16) as usize;
+                    let flags = i16::read(bytes)?;
+                    let type_id = i32::read(bytes)?;
+                    let hash_code = i32::read(bytes)?;
+                    let len = i32::read(bytes)?;
+
+                    // Header includes schema ID and offset.
+                    if len < 24 || (len - 16) as usize > bytes.remaining() {
+                        return Err(Error::new(ErrorKind::Serde, format!("Invalid binary object length: {}", len)));
+                    }
+
+                    let len = (len - 16) as usize;
+
+                    // Copy so that a small object doesn't keep the whole response alive.
+                    let object_bytes = Bytes::copy_from_slice(&bytes[..len]);
+
+                    bytes.advance(len);
 
                     Ok(Value::BinaryObject(BinaryObject {
                         flags,
                         type_id,
                         hash_code,
-                        bytes: bytes.slice(..len),
+                        bytes: object_bytes,
                     }))
                 }
                 else {
                     Err(Error::new(ErrorKind::Serde, format!("Unsupported protocol version: {}", proto_ver)))
                 }
             },
+            101 => Ok(Value::Null),
             _ => Err(Error::new(ErrorKind::Serde, format!("Invalid type code: {}", type_code))),
         }
     }
@@ -743,42 +1509,56 @@ impl IgniteRead for Value {
 
 impl IgniteRead for i8 {
     fn read(bytes: &mut Bytes) -> Result<i8> {
+        ensure_remaining(bytes, 1)?;
+
         Ok(bytes.get_i8())
     }
 }
 
 impl IgniteRead for i16 {
     fn read(bytes: &mut Bytes) -> Result<i16> {
+        ensure_remaining(bytes, 2)?;
+
         Ok(bytes.get_i16_le())
     }
 }
 
 impl IgniteRead for i32 {
     fn read(bytes: &mut Bytes) -> Result<i32> {
+        ensure_remaining(bytes, 4)?;
+
         Ok(bytes.get_i32_le())
     }
 }
 
 impl IgniteRead for i64 {
     fn read(bytes: &mut Bytes) -> Result<i64> {
+        ensure_remaining(bytes, 8)?;
+
         Ok(bytes.get_i64_le())
     }
 }
 
 impl IgniteRead for f32 {
     fn read(bytes: &mut Bytes) -> Result<f32> {
+        ensure_remaining(bytes, 4)?;
+
         Ok(bytes.get_f32_le())
     }
 }
 
 impl IgniteRead for f64 {
     fn read(bytes: &mut Bytes) -> Result<f64> {
+        ensure_remaining(bytes, 8)?;
+
         Ok(bytes.get_f64_le())
     }
 }
 
 impl IgniteRead for char {
     fn read(bytes: &mut Bytes) -> Result<char> {
+        ensure_remaining(bytes, 2)?;
+
         let value = bytes.get_u16_le();
 
         if let Some(char) = std::char::from_u32(value as u32) {
@@ -792,6 +1572,8 @@ impl IgniteRead for char {
 
 impl IgniteRead for bool {
     fn read(bytes: &mut Bytes) -> Result<bool> {
+        ensure_remaining(bytes, 1)?;
+
         Ok(bytes.get_u8() != 0)
     }
 }
@@ -800,12 +1582,25 @@ impl IgniteRead for String {
     fn read(bytes: &mut Bytes) -> Result<String> {
         check_flag(bytes, 9)?;
 
-        let len = bytes.get_i32_le() as usize;
+        let len = read_len(bytes)?;
         let vec = bytes.slice(..len).to_vec();
 
         bytes.advance(len);
 
-        Ok(String::from_utf8(vec)?)
+        match String::from_utf8(vec) {
+            Ok(string) => Ok(string),
+            Err(err) if LOSSY_UTF8.with(Cell::get) => Ok(String::from_utf8_lossy(err.as_bytes()).into_owned()),
+            Err(err) => {
+                let offset = err.utf8_error().valid_up_to();
+
+                let snippet: Vec<String> = err.as_bytes()[offset ..].iter()
+                    .take(8)
+                    .map(|b| format!("{:02x}", b))
+                    .collect();
+
+                Err(Error::new(ErrorKind::Serde, format!("Invalid UTF-8 in string at byte {} of {}: [{}]", offset, len, snippet.join(" "))))
+            },
+        }
     }
 }
 
@@ -813,21 +1608,21 @@ impl IgniteRead for Uuid {
     fn read(bytes: &mut Bytes) -> Result<Uuid> {
         check_flag(bytes, 10)?;
 
-        let mut msb = bytes.get_i64_le();
-        let mut lsb = bytes.get_i64_le();
+        let mut msb = i64::read(bytes)?;
+        let mut lsb = i64::read(bytes)?;
 
         let mut arr = [0u8; 16];
 
         for i in 0 .. 8 {
             arr[15 - i] = (lsb & 0xFF) as u8;
 
-            lsb = lsb >> 8;
+            lsb >>= 8;
         }
 
         for i in 8 .. 16 {
             arr[15 - i] = (msb & 0xFF) as u8;
 
-            msb = msb >> 8;
+            msb >>= 8;
         }
 
         Ok(Uuid::from_bytes(arr))
@@ -838,11 +1633,47 @@ impl IgniteRead for NaiveDateTime {
     fn read(bytes: &mut Bytes) -> Result<NaiveDateTime> {
         check_flag(bytes, 33)?;
 
-        let millis = bytes.get_i64_le();
-        let nanos = bytes.get_i32_le() as u32;
+        let millis = i64::read(bytes)?;
+        let nanos = i32::read(bytes)?;
+
+        if !(0 .. 1_000_000).contains(&nanos) {
+            return Err(Error::new(ErrorKind::Serde, format!("Invalid timestamp nanoseconds: {}", nanos)));
+        }
+
+        let secs = millis.div_euclid(1000);
+        let nanos = (millis.rem_euclid(1000) * 1_000_000) as u32 + nanos as u32;
 
-        // TODO: Expects seconds?
-        Ok(NaiveDateTime::from_timestamp(millis, nanos))
+        DateTime::from_timestamp(secs, nanos)
+            .map(|dt| dt.naive_utc())
+            .ok_or_else(|| Error::new(ErrorKind::Serde, format!("Invalid timestamp: {}", millis)))
+    }
+}
+
+// Java dates may carry a time of day, which is dropped.
+impl IgniteRead for NaiveDate {
+    fn read(bytes: &mut Bytes) -> Result<NaiveDate> {
+        check_flag(bytes, 11)?;
+
+        let millis = i64::read(bytes)?;
+
+        DateTime::from_timestamp_millis(millis)
+            .map(|dt| dt.date_naive())
+            .ok_or_else(|| Error::new(ErrorKind::Serde, format!("Invalid date: {}", millis)))
+    }
+}
+
+impl IgniteRead for NaiveTime {
+    fn read(bytes: &mut Bytes) -> Result<NaiveTime> {
+        check_flag(bytes, 36)?;
+
+        let millis = i64::read(bytes)?;
+
+        if !(0 .. 86_400_000).contains(&millis) {
+            return Err(Error::new(ErrorKind::Serde, format!("Invalid time: {}", millis)));
+        }
+
+        NaiveTime::from_num_seconds_from_midnight_opt((millis / 1000) as u32, (millis % 1000 * 1_000_000) as u32)
+            .ok_or_else(|| Error::new(ErrorKind::Serde, format!("Invalid time: {}", millis)))
     }
 }
 
@@ -850,13 +1681,19 @@ impl IgniteRead for BigDecimal {
     fn read(bytes: &mut Bytes) -> Result<Self> {
         check_flag(bytes, 30)?;
 
-        let scale = bytes.get_i32_le() as i64;
-        let len = bytes.get_i32_le() as usize;
-        let vec = bytes.slice(..len);
+        let scale = i32::read(bytes)? as i64;
+        let len = read_len(bytes)?;
+        let mut vec = bytes.slice(..len).to_vec();
 
         bytes.advance(len);
 
-        let int = BigInt::from_signed_bytes_le(vec.as_ref());
+        let negative = vec.first().map(|b| b & 0x80 != 0).unwrap_or(false);
+
+        if negative {
+            vec[0] &= 0x7F;
+        }
+
+        let int = BigInt::from_bytes_be(if negative { Sign::Minus } else { Sign::Plus }, vec.as_slice());
 
         Ok(BigDecimal::new(int, scale))
     }
@@ -880,7 +1717,7 @@ impl<T: IgniteRead + Nullable> IgniteRead for Option<T> {
 
 impl<T: IgniteRead> IgniteRead for Vec<T> {
     fn read(bytes: &mut Bytes) -> Result<Self> {
-        let len = bytes.get_i32_le() as usize;
+        let len = read_len(bytes)?;
 
         let mut vec = Vec::with_capacity(len);
 
@@ -901,7 +1738,37 @@ impl<T1: IgniteRead, T2: IgniteRead> IgniteRead for (T1, T2) {
     }
 }
 
+// Every element takes at least one byte, so the length can't exceed what's left in the buffer.
+fn read_len(bytes: &mut Bytes) -> Result<usize> {
+    if bytes.remaining() < 4 {
+        return Err(Error::new(ErrorKind::Serde, "Unexpected end of buffer.".to_string()));
+    }
+
+    let len = bytes.get_i32_le();
+
+    if len < 0 || len as usize > bytes.remaining() {
+        Err(Error::new(ErrorKind::Serde, format!("Invalid length: {} (remaining bytes: {})", len, bytes.remaining())))
+    }
+    else {
+        Ok(len as usize)
+    }
+}
+
+// Fails instead of letting `Buf::get_*` panic on a short buffer.
+fn ensure_remaining(bytes: &Bytes, len: usize) -> Result<()> {
+    if bytes.remaining() < len {
+        Err(Error::new(ErrorKind::Serde, format!("Out of bytes: {} needed, {} remaining.", len, bytes.remaining())))
+    }
+    else {
+        Ok(())
+    }
+}
+
 fn check_flag(bytes: &mut Bytes, expected: i8) -> Result<()> {
+    if !bytes.has_remaining() {
+        return Err(Error::new(ErrorKind::Serde, "Unexpected end of buffer.".to_string()));
+    }
+
     let flag = bytes.get_i8();
 
     if flag == expected {
@@ -911,3 +1778,761 @@ fn check_flag(bytes: &mut Bytes, expected: i8) -> Result<()> {
         Err(Error::new(ErrorKind::Serde, format!("Unexpected flag: {} != {}", flag, expected)))
     }
 }
+
+// === Tests
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, LinkedList};
+    use std::convert::{TryFrom, TryInto};
+
+    use bigdecimal::BigDecimal;
+    use bytes::{Bytes, BytesMut};
+    use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+    use num_bigint::BigInt;
+    use proptest::collection::vec;
+    use proptest::option;
+    use proptest::prelude::*;
+    use uuid::Uuid;
+
+    use crate::binary::{Value, BinaryObject, IgniteRead, IgniteWrite, with_lossy_utf8, field_id};
+    use crate::error::{ErrorKind, Result};
+
+    fn scalar() -> impl Strategy<Value = Value> {
+        prop_oneof![
+            any::<i8>().prop_map(Value::I8),
+            any::<i16>().prop_map(Value::I16),
+            any::<i32>().prop_map(Value::I32),
+            any::<i64>().prop_map(Value::I64),
+            any::<f32>().prop_map(Value::F32),
+            any::<f64>().prop_map(Value::F64),
+            bmp_char().prop_map(Value::Char),
+            any::<bool>().prop_map(Value::Bool),
+            any::<String>().prop_map(Value::String),
+            any::<u128>().prop_map(|v| Value::Uuid(Uuid::from_u128(v))),
+            timestamp().prop_map(Value::Timestamp),
+            timestamp().prop_map(|v| Value::Date(v.date())),
+            time().prop_map(Value::Time),
+            decimal().prop_map(Value::Decimal),
+            (any::<i32>(), any::<i32>()).prop_map(|(type_id, ordinal)| Value::Enum { type_id, ordinal }),
+            vec(any::<i8>(), 0 .. 16).prop_map(Value::I8Vec),
+            vec(any::<i16>(), 0 .. 16).prop_map(Value::I16Vec),
+            vec(any::<i32>(), 0 .. 16).prop_map(Value::I32Vec),
+            vec(any::<i64>(), 0 .. 16).prop_map(Value::I64Vec),
+            vec(any::<f32>(), 0 .. 16).prop_map(Value::F32Vec),
+            vec(any::<f64>(), 0 .. 16).prop_map(Value::F64Vec),
+            vec(bmp_char(), 0 .. 16).prop_map(Value::CharVec),
+            vec(any::<bool>(), 0 .. 16).prop_map(Value::BoolVec),
+            vec(any::<String>(), 0 .. 16).prop_map(Value::StringVec),
+            vec(any::<u128>(), 0 .. 16).prop_map(|v| Value::UuidVec(v.into_iter().map(Uuid::from_u128).collect())),
+            vec(timestamp(), 0 .. 16).prop_map(Value::TimestampVec),
+            vec(timestamp(), 0 .. 16).prop_map(|v| Value::DateVec(v.iter().map(NaiveDateTime::date).collect())),
+            vec(time(), 0 .. 16).prop_map(Value::TimeVec),
+            vec(option::of(decimal()), 0 .. 16).prop_map(Value::DecimalVec),
+            binary_object().prop_map(Value::BinaryObject),
+            Just(Value::Null),
+        ]
+    }
+
+    fn value() -> impl Strategy<Value = Value> {
+        scalar().prop_recursive(3, 64, 8, |inner| {
+            prop_oneof![
+                vec(inner.clone(), 0 .. 8).prop_map(Value::Vec),
+                (any::<i32>(), vec(inner.clone(), 0 .. 8)).prop_map(|(type_id, items)| Value::ObjectVec { type_id, items }),
+                vec(inner.clone(), 0 .. 8).prop_map(|v| Value::LinkedList(v.into_iter().collect::<LinkedList<Value>>())),
+                (any::<i8>().prop_filter("Known collection type", |t| !(1 ..= 4).contains(t)), vec(inner.clone(), 0 .. 8))
+                    .prop_map(|(type_byte, items)| Value::UserCollection { type_byte, items }),
+                vec(inner.clone(), 0 .. 8).prop_map(|v| Value::HashSet(v.into_iter().collect())),
+                vec(inner.clone(), 0 .. 8).prop_map(|v| Value::LinkedHashSet(v.into_iter().collect())),
+                vec((inner.clone(), inner.clone()), 0 .. 8).prop_map(|v| Value::HashMap(v.into_iter().collect())),
+                vec((inner.clone(), inner), 0 .. 8).prop_map(|v| Value::LinkedHashMap(v.into_iter().collect())),
+            ]
+        })
+    }
+
+    // Only characters that fit a single UTF-16 unit can be written.
+    fn bmp_char() -> impl Strategy<Value = char> {
+        any::<char>().prop_filter("BMP character", |c| c.len_utf16() == 1)
+    }
+
+    fn timestamp() -> impl Strategy<Value = NaiveDateTime> {
+        (-62_000_000_000i64 .. 250_000_000_000, 0u32 .. 1_000_000_000)
+            .prop_map(|(secs, nanos)| DateTime::from_timestamp(secs, nanos).unwrap().naive_utc())
+    }
+
+    fn time() -> impl Strategy<Value = NaiveTime> {
+        (0u32 .. 86_400_000).prop_map(|millis| NaiveTime::from_num_seconds_from_midnight_opt(millis / 1000, millis % 1000 * 1_000_000).unwrap())
+    }
+
+    fn decimal() -> impl Strategy<Value = BigDecimal> {
+        (any::<i128>(), -20i64 .. 20).prop_map(|(int, scale)| BigDecimal::new(BigInt::from(int), scale))
+    }
+
+    fn binary_object() -> impl Strategy<Value = BinaryObject> {
+        (any::<i16>(), any::<i32>(), any::<i32>(), vec(any::<u8>(), 8 .. 64)).prop_map(|(flags, type_id, hash_code, bytes)| {
+            BinaryObject { flags, type_id, hash_code, bytes: Bytes::from(bytes) }
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(value in value()) {
+            let read = Value::from_bytes(&value.to_bytes().unwrap()).unwrap();
+
+            prop_assert_eq!(read, value);
+        }
+
+        #[test]
+        fn test_serialized_size(value in value()) {
+            prop_assert_eq!(value.serialized_size(), value.to_bytes().unwrap().len());
+        }
+
+        #[test]
+        fn test_truncated(value in value()) {
+            let bytes = value.to_bytes().unwrap();
+
+            for len in 0 .. bytes.len() {
+                prop_assert!(Value::read(&mut Bytes::copy_from_slice(&bytes[.. len])).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_truncated_fixed() {
+        let value = Value::Vec(vec![Value::I64(1), Value::String("abc".to_string()), Value::Uuid(Uuid::from_u128(1))]);
+
+        let bytes = value.to_bytes().unwrap();
+
+        for len in 0 .. bytes.len() {
+            let err = Value::read(&mut Bytes::copy_from_slice(&bytes[.. len])).unwrap_err();
+
+            assert_eq!(err.kind, ErrorKind::Serde, "{}", len);
+        }
+
+        // Negative collection length.
+        let err = Value::from_bytes(&[24, 0xff, 0xff, 0xff, 0xff, 1]).unwrap_err();
+
+        assert_eq!(err.kind, ErrorKind::Serde);
+    }
+
+    #[test]
+    fn test_serialized_size_fixed() {
+        let values = vec![
+            Value::Char('a'),
+            Value::CharVec(vec!['a', 'b']),
+            Value::Timestamp(NaiveDateTime::default()),
+            Value::TimestampVec(vec![NaiveDateTime::default()]),
+            Value::Decimal(BigDecimal::new(BigInt::from(0), 0)),
+            Value::Decimal(BigDecimal::new(BigInt::from(-128), 2)),
+            Value::Decimal(BigDecimal::new(BigInt::from(255), 0)),
+            Value::DecimalVec(vec![None, Some(BigDecimal::new(BigInt::from(1 << 15), 3))]),
+        ];
+
+        for value in values {
+            assert_eq!(value.serialized_size(), value.to_bytes().unwrap().len(), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn test_decimal_encoding() {
+        let decimal = |int: i64, scale: i64| Value::Decimal(BigDecimal::new(BigInt::from(int), scale));
+
+        assert_eq!(decimal(1, 0).to_bytes().unwrap(), vec![30, 0, 0, 0, 0, 1, 0, 0, 0, 0x01]);
+        assert_eq!(decimal(-1, 0).to_bytes().unwrap(), vec![30, 0, 0, 0, 0, 1, 0, 0, 0, 0x81]);
+        assert_eq!(decimal(-128, 2).to_bytes().unwrap(), vec![30, 2, 0, 0, 0, 2, 0, 0, 0, 0x80, 0x80]);
+        assert_eq!(decimal(-12345, 2).to_bytes().unwrap(), vec![30, 2, 0, 0, 0, 2, 0, 0, 0, 0xB0, 0x39]);
+    }
+
+    #[test]
+    fn test_decimal_round_trip() {
+        for text in ["123.45", "-123.45", "0", "-128", "0.0001", "-98765432109876543210.123456789"] {
+            let value = Value::Decimal(text.parse().unwrap());
+
+            let read = Value::from_bytes(&value.to_bytes().unwrap());
+
+            assert_eq!(read, Ok(value), "{}", text);
+        }
+
+        // 123.45: scale 2, unscaled 12345 = 0x3039.
+        assert_eq!(Value::from_bytes(&[30, 2, 0, 0, 0, 2, 0, 0, 0, 0x30, 0x39]), Ok(Value::Decimal("123.45".parse().unwrap())));
+    }
+
+    #[test]
+    fn test_decimal_vec_with_nulls() {
+        let decimals = vec![
+            Some(BigDecimal::new(BigInt::from(-12345), 2)),
+            None,
+            Some(BigDecimal::new(BigInt::from(7), 5)),
+        ];
+
+        let bytes = Value::DecimalVec(decimals.clone()).to_bytes().unwrap();
+
+        match Value::from_bytes(&bytes).unwrap() {
+            Value::DecimalVec(read) => {
+                let parts = |v: &Vec<Option<BigDecimal>>| -> Vec<Option<(BigInt, i64)>> {
+                    v.iter().map(|d| d.as_ref().map(|d| d.as_bigint_and_exponent())).collect()
+                };
+
+                assert_eq!(parts(&read), parts(&decimals));
+            },
+            value => panic!("Unexpected value: {:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_timestamp_utc() {
+        let local = FixedOffset::east_opt(3 * 3600).unwrap()
+            .with_ymd_and_hms(2020, 1, 1, 12, 0, 0).unwrap();
+
+        let value = Value::from(local);
+
+        match &value {
+            Value::Timestamp(v) => assert_eq!(*v, NaiveDate::from_ymd_opt(2020, 1, 1).unwrap().and_hms_opt(9, 0, 0).unwrap()),
+            _ => panic!("Unexpected value: {:?}", value),
+        }
+
+        assert_eq!(value.to_utc(), Some(local.with_timezone(&Utc)));
+        assert_eq!(value.to_local(), Some(local.with_timezone(&Local)));
+        assert_eq!(value.to_bytes().unwrap(), Value::from(local.with_timezone(&Utc)).to_bytes().unwrap());
+        assert_eq!(Value::I32(1).to_utc(), None);
+    }
+
+    #[test]
+    fn test_from_bytes_trailing() {
+        let mut bytes = Value::I32(42).to_bytes().unwrap();
+
+        bytes.push(0);
+
+        assert!(Value::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_binary_object_does_not_retain_buffer() {
+        let object = Value::BinaryObject(BinaryObject {
+            flags: 0,
+            type_id: 1,
+            hash_code: 2,
+            bytes: Bytes::from(vec![1u8, 2, 3, 4, 5, 6, 7, 8]),
+        });
+
+        let mut buf = object.to_bytes().unwrap();
+
+        buf.resize(1024 * 1024, 0);
+
+        let mut bytes = Bytes::from(buf);
+
+        let range = bytes.as_ptr() as usize .. bytes.as_ptr() as usize + bytes.len();
+
+        match Value::read(&mut bytes).unwrap() {
+            Value::BinaryObject(object) => {
+                assert_eq!(object.bytes.as_ref(), &[1u8, 2, 3, 4, 5, 6, 7, 8]);
+                assert!(!range.contains(&(object.bytes.as_ptr() as usize)));
+            },
+            value => panic!("Unexpected value: {:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_user_collection() {
+        let bytes = vec![24, 2, 0, 0, 0, 42, 3, 1, 0, 0, 0, 1, 7];
+
+        match Value::from_bytes(&bytes).unwrap() {
+            Value::UserCollection { type_byte, items } => {
+                assert_eq!(type_byte, 42);
+                assert_eq!(items.len(), 2);
+            },
+            value => panic!("Unexpected value: {:?}", value),
+        }
+
+        assert_eq!(Value::from_bytes(&bytes).unwrap().to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_wrapped_data() {
+        let object = Value::BinaryObject(BinaryObject {
+            flags: 1,
+            type_id: 2,
+            hash_code: 3,
+            bytes: Bytes::from(vec![1u8, 2, 3, 4, 5, 6, 7, 8]),
+        });
+
+        let object_bytes = object.to_bytes().unwrap();
+
+        // Object preceded by some unrelated data, as in a nested field.
+        let mut array = vec![0u8; 3];
+
+        array.extend_from_slice(&object_bytes);
+
+        let mut bytes = vec![27u8];
+
+        bytes.extend_from_slice(&(array.len() as i32).to_le_bytes());
+        bytes.extend_from_slice(&array);
+        bytes.extend_from_slice(&3i32.to_le_bytes());
+
+        let value = Value::from_bytes(&bytes).unwrap();
+
+        assert_eq!(value.to_bytes().unwrap(), object_bytes);
+
+        let len = bytes.len();
+
+        bytes[len - 4] = 100;
+
+        assert!(Value::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let bytes = vec![9, 5, 0, 0, 0, b'a', b'b', 0xff, 0xfe, b'c'];
+
+        let err = Value::from_bytes(&bytes).unwrap_err();
+
+        assert_eq!(err.kind, ErrorKind::Serde);
+        assert_eq!(err.message, "Invalid UTF-8 in string at byte 2 of 5: [ff fe 63]");
+
+        let value = with_lossy_utf8(true, || Value::from_bytes(&bytes)).unwrap();
+
+        match value {
+            Value::String(v) => assert_eq!(v, "ab\u{fffd}\u{fffd}c"),
+            value => panic!("Unexpected value: {:?}", value),
+        }
+
+        assert!(Value::from_bytes(&bytes).is_err());
+    }
+
+    fn object(fields: &[(&str, Value)], compact: bool) -> BinaryObject {
+        let fields: Vec<(&str, Vec<u8>)> = fields.iter().map(|(name, value)| (*name, value.to_bytes().unwrap())).collect();
+
+        raw_object(&fields, compact)
+    }
+
+    // Fields are given in serialized form.
+    fn raw_object(fields: &[(&str, Vec<u8>)], compact: bool) -> BinaryObject {
+        let mut data = Vec::new();
+        let mut footer = Vec::new();
+
+        for (name, value) in fields {
+            if !compact {
+                footer.extend_from_slice(&field_id(name).to_le_bytes());
+            }
+
+            footer.extend_from_slice(&(24 + data.len() as i32).to_le_bytes());
+
+            data.extend_from_slice(value);
+        }
+
+        let mut bytes = Vec::new();
+
+        bytes.extend_from_slice(&42i32.to_le_bytes()); // Schema ID.
+        bytes.extend_from_slice(&(24 + data.len() as i32).to_le_bytes());
+        bytes.extend_from_slice(&data);
+        bytes.extend_from_slice(&footer);
+
+        BinaryObject {
+            flags: if compact { 0x23 } else { 0x03 },
+            type_id: 1,
+            hash_code: 0,
+            bytes: Bytes::from(bytes),
+        }
+    }
+
+    #[test]
+    fn test_field_id() {
+        assert_eq!(field_id("name"), 3373707);
+        assert_eq!(field_id("Name"), 3373707);
+    }
+
+    #[derive(IgniteRead, IgniteWrite, PartialEq, Debug)]
+    struct Account {
+        id: i32,
+        #[ignite(skip)]
+        cached_total: Option<i64>,
+        owner: String,
+    }
+
+    #[test]
+    fn test_derive_attributes() {
+        let account = Account { id: 1, cached_total: Some(100), owner: "John".to_string() };
+
+        let mut bytes = BytesMut::new();
+
+        account.write(&mut bytes).unwrap();
+
+        assert_eq!(bytes.len(), 4 + 1 + 4 + 4);
+
+        let read = Account::read(&mut bytes.freeze()).unwrap();
+
+        assert_eq!(read, Account { cached_total: None, ..account });
+    }
+
+    #[test]
+    fn test_binary_object_fields() {
+        let fields = [
+            ("id", Value::I32(1)),
+            ("name", Value::String("John".to_string())),
+            ("salary", Value::F64(1000.5)),
+            ("active", Value::Bool(true)),
+            ("age", Value::I64(42)),
+        ];
+
+        let object = object(&fields, false);
+
+        assert_eq!(object.schema_id(), 42);
+        assert!(matches!(object.field("name"), Ok(Some(Value::String(ref v))) if v == "John"));
+        assert!(matches!(object.field("missing"), Ok(None)));
+
+        let values = object.fields(&["age", "id", "name"], None).unwrap();
+
+        assert!(matches!(values.as_slice(), [Some(Value::I64(42)), Some(Value::I32(1)), Some(Value::String(v))] if v == "John"));
+
+        let object = self::object(&fields, true);
+
+        assert_eq!(object.field("name").unwrap_err().kind, ErrorKind::NotSupported);
+
+        let schema: Vec<i32> = fields.iter().map(|(name, _)| field_id(name)).collect();
+
+        let values = object.fields(&["salary", "active"], Some(&schema)).unwrap();
+
+        assert!(matches!(values.as_slice(), [Some(Value::F64(v)), Some(Value::Bool(true))] if *v == 1000.5));
+    }
+
+    #[test]
+    fn test_map_with_collection_values() -> Result<()> {
+        let inner: HashMap<Value, Value> = vec![(Value::String("a".to_string()), Value::I64(4))].into_iter().collect();
+
+        let map = Value::HashMap(vec![
+            (Value::I32(1), Value::Vec(vec![Value::I32(2), Value::String("a".to_string())])),
+            (Value::I32(3), Value::Vec(vec![])),
+            (Value::I32(5), Value::HashMap(inner)),
+        ].into_iter().collect());
+
+        let mut bytes = BytesMut::new();
+
+        map.write(&mut bytes)?;
+
+        assert_eq!(bytes.len(), map.serialized_size());
+        assert_eq!(Value::read(&mut bytes.freeze())?, map);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_decimal_conversions() {
+        let decimal = |int: i64, scale: i64| Value::Decimal(BigDecimal::new(BigInt::from(int), scale));
+
+        assert_eq!(i64::try_from(decimal(42, 0)), Ok(42));
+        assert_eq!(i64::try_from(decimal(4200, 2)), Ok(42));
+        assert_eq!(i64::try_from(decimal(4250, 2)).unwrap_err().kind, ErrorKind::Serde);
+        assert_eq!(i64::try_from(Value::I32(42)).unwrap_err().kind, ErrorKind::Serde);
+
+        assert_eq!(f64::try_from(decimal(42, 0)), Ok(42.0));
+        assert_eq!(f64::try_from(decimal(-1250, 2)), Ok(-12.5));
+
+        assert_eq!(BigDecimal::try_from(decimal(150, 2)).unwrap().to_string(), "1.50");
+        assert_eq!(BigDecimal::try_from(decimal(-5, 0)).unwrap().to_string(), "-5");
+    }
+
+    #[test]
+    fn test_eq_hash() {
+        use std::collections::{HashSet, HashMap};
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+        use linked_hash_set::LinkedHashSet;
+
+        let hash = |value: &Value| {
+            let mut hasher = DefaultHasher::new();
+
+            value.hash(&mut hasher);
+
+            hasher.finish()
+        };
+
+        assert_eq!(Value::F64(f64::NAN), Value::F64(f64::NAN));
+        assert_ne!(Value::F64(0.0), Value::F64(-0.0));
+        assert_ne!(Value::I32(1), Value::I64(1));
+        assert_eq!(hash(&Value::F32Vec(vec![f32::NAN])), hash(&Value::F32Vec(vec![f32::NAN])));
+
+        let items = || (0 .. 20).map(Value::I32);
+
+        let set = Value::LinkedHashSet(items().collect());
+        let reversed = Value::LinkedHashSet(items().rev().collect::<LinkedHashSet<Value>>());
+
+        assert_eq!(set, reversed);
+        assert_eq!(hash(&set), hash(&reversed));
+
+        let set: HashSet<Value> = vec![
+            Value::Vec(vec![Value::String("a".to_string())]),
+            Value::HashSet(items().collect()),
+            Value::F64(f64::NAN),
+        ].into_iter().collect();
+
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(&Value::F64(f64::NAN)));
+        assert!(set.contains(&Value::HashSet(items().rev().collect())));
+
+        let map: HashMap<Value, Value> = items().map(|k| (k.clone(), k)).collect();
+        let value = Value::HashMap(map);
+
+        assert_eq!(Value::from_bytes(&value.to_bytes().unwrap()), Ok(value));
+    }
+
+    #[test]
+    fn test_char_vec() {
+        let value = Value::CharVec(vec!['a', '\u{e9}', '\u{4e2d}']);
+
+        let bytes = value.to_bytes().unwrap();
+
+        assert_eq!(bytes, vec![18, 3, 0, 0, 0, 0x61, 0, 0xe9, 0, 0x2d, 0x4e]);
+        assert_eq!(Value::from_bytes(&bytes), Ok(value));
+        assert_eq!(Value::Char('\u{4e2d}').to_bytes().unwrap(), vec![7, 0x2d, 0x4e]);
+    }
+
+    #[test]
+    fn test_char_outside_bmp() {
+        // Takes two UTF-16 code units, which a Java `char` can't hold.
+        let err = Value::Char('\u{1f600}').to_bytes().unwrap_err();
+
+        assert_eq!(err.kind, ErrorKind::Serde);
+        assert_eq!(err.message, "Only characters in the Basic Multilingual Plane are supported.");
+
+        assert_eq!(Value::CharVec(vec!['a', '\u{1f600}']).to_bytes().unwrap_err().kind, ErrorKind::Serde);
+    }
+
+    #[test]
+    fn test_timestamp_round_trip() {
+        let timestamp = |millis: i64, nanos: u32| {
+            DateTime::from_timestamp_millis(millis).unwrap().naive_utc() + chrono::Duration::nanoseconds(nanos as i64)
+        };
+
+        // 2020-01-01T00:00:00.123Z, with and without sub-millisecond precision.
+        for value in [timestamp(1_577_836_800_123, 0), timestamp(1_577_836_800_123, 456_789), timestamp(-1, 999_999)] {
+            let value = Value::Timestamp(value);
+
+            assert_eq!(Value::from_bytes(&value.to_bytes().unwrap()), Ok(value));
+        }
+
+        let mut bytes = vec![33];
+
+        bytes.extend_from_slice(&1_577_836_800_123i64.to_le_bytes());
+        bytes.extend_from_slice(&456_789i32.to_le_bytes());
+
+        assert_eq!(Value::from_bytes(&bytes), Ok(Value::Timestamp(timestamp(1_577_836_800_123, 456_789))));
+        assert_eq!(Value::Timestamp(timestamp(1_577_836_800_123, 456_789)).to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_from() {
+        let date = NaiveDate::from_ymd_opt(2020, 1, 2).unwrap();
+        let uuid = Uuid::from_u128(1);
+
+        assert_eq!(Value::from(1i8), Value::I8(1));
+        assert_eq!(Value::from(1i16), Value::I16(1));
+        assert_eq!(Value::from(1), Value::I32(1));
+        assert_eq!(Value::from(1i64), Value::I64(1));
+        assert_eq!(Value::from(1.5f32), Value::F32(1.5));
+        assert_eq!(Value::from(1.5), Value::F64(1.5));
+        assert_eq!(Value::from(true), Value::Bool(true));
+        assert_eq!(Value::from('a'), Value::Char('a'));
+        assert_eq!(Value::from("hi".to_string()), Value::String("hi".to_string()));
+        assert_eq!(Value::from("hi"), Value::String("hi".to_string()));
+        assert_eq!(Value::from(uuid), Value::Uuid(uuid));
+        assert_eq!(Value::from(date.and_hms_opt(1, 2, 3).unwrap()), Value::Timestamp(date.and_hms_opt(1, 2, 3).unwrap()));
+        assert_eq!(Value::from(date), Value::Date(date));
+
+        assert_eq!(Value::from(vec![1i8]), Value::I8Vec(vec![1]));
+        assert_eq!(Value::from(vec![1, 2]), Value::I32Vec(vec![1, 2]));
+        assert_eq!(Value::from(vec![1.5]), Value::F64Vec(vec![1.5]));
+        assert_eq!(Value::from(vec!["hi".to_string()]), Value::StringVec(vec!["hi".to_string()]));
+        assert_eq!(Value::from(vec![uuid]), Value::UuidVec(vec![uuid]));
+        assert_eq!(Value::from(vec![Value::I32(1), Value::Null]), Value::Vec(vec![Value::I32(1), Value::Null]));
+
+        let pair: Value = (1, "x").into();
+
+        assert_eq!(pair, Value::Vec(vec![Value::I32(1), Value::String("x".to_string())]));
+    }
+
+    #[test]
+    fn test_try_from() {
+        let uuid = Uuid::from_u128(1);
+        let timestamp = NaiveDate::from_ymd_opt(2020, 1, 2).unwrap().and_hms_opt(1, 2, 3).unwrap();
+
+        assert_eq!(i8::try_from(Value::I8(1)), Ok(1));
+        assert_eq!(i16::try_from(Value::I16(1)), Ok(1));
+        assert_eq!(i32::try_from(Value::I32(1)), Ok(1));
+        assert_eq!(i64::try_from(Value::I64(1)), Ok(1));
+        assert_eq!(f32::try_from(Value::F32(1.5)), Ok(1.5));
+        assert_eq!(f64::try_from(Value::F64(1.5)), Ok(1.5));
+        assert_eq!(char::try_from(Value::Char('a')), Ok('a'));
+        assert_eq!(bool::try_from(Value::Bool(true)), Ok(true));
+        assert_eq!(String::try_from(Value::String("hi".to_string())), Ok("hi".to_string()));
+        assert_eq!(Uuid::try_from(Value::Uuid(uuid)), Ok(uuid));
+        assert_eq!(NaiveDateTime::try_from(Value::Timestamp(timestamp)), Ok(timestamp));
+        assert_eq!(<Vec<i32>>::try_from(Value::I32Vec(vec![1, 2])), Ok(vec![1, 2]));
+        assert_eq!(<Vec<String>>::try_from(Value::StringVec(vec!["hi".to_string()])), Ok(vec!["hi".to_string()]));
+        assert_eq!(<Vec<Value>>::try_from(Value::Vec(vec![Value::Null])), Ok(vec![Value::Null]));
+
+        let mismatch = |result: Result<()>| assert_eq!(result.unwrap_err().kind, ErrorKind::Serde);
+
+        mismatch(i8::try_from(Value::I16(1)).map(drop));
+        mismatch(i16::try_from(Value::I8(1)).map(drop));
+        mismatch(i32::try_from(Value::I64(1)).map(drop));
+        mismatch(i64::try_from(Value::I32(1)).map(drop));
+        mismatch(f32::try_from(Value::F64(1.5)).map(drop));
+        mismatch(f64::try_from(Value::F32(1.5)).map(drop));
+        mismatch(char::try_from(Value::String("a".to_string())).map(drop));
+        mismatch(bool::try_from(Value::I8(1)).map(drop));
+        mismatch(String::try_from(Value::Char('a')).map(drop));
+        mismatch(Uuid::try_from(Value::String(uuid.to_string())).map(drop));
+        mismatch(NaiveDateTime::try_from(Value::I64(0)).map(drop));
+        mismatch(<Vec<i32>>::try_from(Value::I64Vec(vec![1])).map(drop));
+        mismatch(<Vec<Value>>::try_from(Value::I32Vec(vec![1])).map(drop));
+
+        let n: i32 = Value::I32(7).try_into().unwrap();
+
+        assert_eq!(n, 7);
+    }
+
+    #[test]
+    fn test_date_time_round_trip() {
+        let date = NaiveDate::from_ymd_opt(2020, 1, 2).unwrap();
+        let time = NaiveTime::from_hms_milli_opt(23, 59, 59, 999).unwrap();
+
+        for value in [Value::Date(date), Value::Time(time), Value::DateVec(vec![date]), Value::TimeVec(vec![time])] {
+            assert_eq!(Value::from_bytes(&value.to_bytes().unwrap()), Ok(value));
+        }
+
+        let mut bytes = vec![11];
+
+        bytes.extend_from_slice(&1_577_923_200_000i64.to_le_bytes());
+
+        assert_eq!(Value::from_bytes(&bytes), Ok(Value::Date(date)));
+        assert_eq!(Value::Date(date).to_bytes().unwrap(), bytes);
+
+        let mut bytes = vec![36];
+
+        bytes.extend_from_slice(&86_399_999i64.to_le_bytes());
+
+        assert_eq!(Value::from_bytes(&bytes), Ok(Value::Time(time)));
+        assert_eq!(Value::Time(time).to_bytes().unwrap(), bytes);
+
+        let mut bytes = vec![36];
+
+        bytes.extend_from_slice(&86_400_000i64.to_le_bytes());
+
+        assert_eq!(Value::from_bytes(&bytes).unwrap_err().kind, ErrorKind::Serde);
+    }
+
+    #[test]
+    fn test_temporal_conversions() {
+        let timestamp = NaiveDate::from_ymd_opt(2020, 1, 2).unwrap().and_hms_milli_opt(23, 59, 59, 999).unwrap();
+
+        assert_eq!(NaiveDateTime::try_from(Value::Timestamp(timestamp)), Ok(timestamp));
+        assert_eq!(NaiveDate::try_from(Value::Timestamp(timestamp)), Ok(NaiveDate::from_ymd_opt(2020, 1, 2).unwrap()));
+        assert_eq!(NaiveDate::try_from(Value::Date(timestamp.date())), Ok(timestamp.date()));
+        assert_eq!(NaiveDateTime::try_from(Value::Date(timestamp.date())), Ok(timestamp.date().and_hms_opt(0, 0, 0).unwrap()));
+
+        assert_eq!(NaiveDateTime::try_from(Value::I64(0)).unwrap_err().kind, ErrorKind::Serde);
+        assert_eq!(NaiveDate::try_from(Value::String("2020-01-02".to_string())).unwrap_err().kind, ErrorKind::Serde);
+    }
+
+    #[test]
+    fn test_computed_hash_code() {
+        // Expected values are from `BinaryArrayIdentityResolver` on the JVM.
+        assert_eq!(object(&[("id", Value::I32(1))], false).computed_hash_code(), Ok(31429505));
+
+        let fields = [("id", Value::I32(-1)), ("name", Value::String("John".to_string()))];
+
+        assert_eq!(object(&fields, false).computed_hash_code(), Ok(1445061390));
+        assert_eq!(object(&fields, true).computed_hash_code(), Ok(1445061390));
+    }
+
+    #[test]
+    fn test_nested_object_field() {
+        let address = object(&[("city", Value::String("Paris".to_string()))], false);
+        let address_bytes = Value::BinaryObject(address.clone()).to_bytes().unwrap();
+
+        // Second field refers back to the first one.
+        let mut handle = vec![102];
+
+        handle.extend_from_slice(&(address_bytes.len() as i32).to_le_bytes());
+
+        let mut self_handle = vec![102];
+
+        self_handle.extend_from_slice(&(24 + address_bytes.len() as i32 + 5).to_le_bytes());
+
+        let person = raw_object(&[("home", address_bytes), ("work", handle), ("self", self_handle)], false);
+
+        let nested = match person.field("home") {
+            Ok(Some(Value::BinaryObject(nested))) => nested,
+            field => panic!("Unexpected field: {:?}", field),
+        };
+
+        assert_eq!(nested, address);
+        assert_eq!(nested.field("city"), Ok(Some(Value::String("Paris".to_string()))));
+        assert_eq!(person.field("work"), Ok(Some(Value::BinaryObject(address))));
+        assert_eq!(person.field("self"), Ok(Some(Value::BinaryObject(person.clone()))));
+    }
+
+    #[test]
+    fn test_tuple_conversions() {
+        let pair = Value::from((Value::I32(1), Value::String("x".to_string())));
+
+        assert_eq!(pair, Value::Vec(vec![Value::I32(1), Value::String("x".to_string())]));
+        assert_eq!(<(Value, Value)>::try_from(pair.clone()), Ok((Value::I32(1), Value::String("x".to_string()))));
+        assert!(<(Value, Value, Value)>::try_from(pair).is_err());
+
+        let triple = Value::from((Value::I64(7), Value::Bool(true), Utc.timestamp_millis_opt(0).unwrap()));
+
+        assert_eq!(Value::from_bytes(&triple.to_bytes().unwrap()), Ok(triple.clone()));
+
+        let (id, flag, timestamp): (i64, Value, NaiveDateTime) = TryFrom::try_from(triple).unwrap();
+
+        assert_eq!((id, flag, timestamp.and_utc().timestamp_millis()), (7, Value::Bool(true), 0));
+        assert!(<(i64, Value)>::try_from(Value::I32(1)).is_err());
+    }
+
+    #[test]
+    fn test_object_vec_with_null() {
+        let first = object(&[("id", Value::I32(1))], false);
+        let second = object(&[("id", Value::I32(2))], true);
+
+        let mut bytes = vec![23];
+
+        bytes.extend_from_slice(&(-1i32).to_le_bytes());
+        bytes.extend_from_slice(&3i32.to_le_bytes());
+        bytes.extend(Value::BinaryObject(first.clone()).to_bytes().unwrap());
+        bytes.push(101);
+        bytes.extend(Value::BinaryObject(second.clone()).to_bytes().unwrap());
+
+        let value = Value::ObjectVec { type_id: -1, items: vec![Value::BinaryObject(first), Value::Null, Value::BinaryObject(second)] };
+
+        assert_eq!(Value::from_bytes(&bytes), Ok(value.clone()));
+        assert_eq!(value.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_enum_round_trip() {
+        let value = Value::Enum { type_id: 12345, ordinal: 2 };
+
+        let mut bytes = vec![28];
+
+        bytes.extend_from_slice(&12345i32.to_le_bytes());
+        bytes.extend_from_slice(&2i32.to_le_bytes());
+
+        assert_eq!(value.to_bytes().unwrap(), bytes);
+        assert_eq!(Value::from_bytes(&bytes), Ok(value));
+    }
+
+    #[test]
+    fn test_enum_vec_round_trip() {
+        let value = Value::EnumVec {
+            type_id: 12345,
+            items: vec![Value::Enum { type_id: 12345, ordinal: 0 }, Value::Enum { type_id: 12345, ordinal: 1 }],
+        };
+
+        let bytes = value.to_bytes().unwrap();
+
+        assert_eq!(bytes[0], 29);
+        assert_eq!(bytes.len(), value.serialized_size());
+        assert_eq!(Value::from_bytes(&bytes), Ok(value));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::binary::Value;
+
+/// Callbacks for `Value::visit`. All methods do nothing by default.
+pub trait ValueVisitor {
+    /// Scalars, typed arrays (e.g. `I32Vec`), binary objects and nulls.
+    fn visit_scalar(&mut self, _value: &Value) {}
+
+    fn visit_collection_start(&mut self, _value: &Value, _len: usize) {}
+
+    fn visit_collection_end(&mut self, _value: &Value) {}
+
+    fn visit_map_start(&mut self, _value: &Value, _len: usize) {}
+
+    /// Called before the key and the value of each entry are visited.
+    fn visit_map_entry(&mut self, _key: &Value, _value: &Value) {}
+
+    fn visit_map_end(&mut self, _value: &Value) {}
+}
+
+impl Value {
+    pub fn visit<V: ValueVisitor>(&self, visitor: &mut V) {
+        match self {
+            Value::ObjectVec { items, .. } => visit_collection(self, items.len(), items.iter(), visitor),
+            Value::EnumVec { items, .. } => visit_collection(self, items.len(), items.iter(), visitor),
+            Value::Vec(v) => visit_collection(self, v.len(), v.iter(), visitor),
+            Value::LinkedList(v) => visit_collection(self, v.len(), v.iter(), visitor),
+            Value::HashSet(v) => visit_collection(self, v.len(), v.iter(), visitor),
+            Value::LinkedHashSet(v) => visit_collection(self, v.len(), v.iter(), visitor),
+            Value::UserCollection { items, .. } => visit_collection(self, items.len(), items.iter(), visitor),
+            Value::HashMap(v) => visit_map(self, v.len(), v.iter(), visitor),
+            Value::LinkedHashMap(v) => visit_map(self, v.len(), v.iter(), visitor),
+            _ => visitor.visit_scalar(self),
+        }
+    }
+}
+
+fn visit_collection<'a, V, I>(value: &Value, len: usize, items: I, visitor: &mut V)
+    where
+        V: ValueVisitor,
+        I: Iterator<Item = &'a Value>,
+{
+    visitor.visit_collection_start(value, len);
+
+    for item in items {
+        item.visit(visitor);
+    }
+
+    visitor.visit_collection_end(value);
+}
+
+fn visit_map<'a, V, I>(value: &Value, len: usize, entries: I, visitor: &mut V)
+    where
+        V: ValueVisitor,
+        I: Iterator<Item = (&'a Value, &'a Value)>,
+{
+    visitor.visit_map_start(value, len);
+
+    for (k, v) in entries {
+        visitor.visit_map_entry(k, v);
+
+        k.visit(visitor);
+        v.visit(visitor);
+    }
+
+    visitor.visit_map_end(value);
+}
+
+// === Tests
+
+#[cfg(test)]
+mod tests {
+    use std::collections::LinkedList;
+
+    use crate::binary::Value;
+    use crate::binary::visit::ValueVisitor;
+
+    struct LeafCounter {
+        leaves: usize,
+        depth: usize,
+        max_depth: usize,
+    }
+
+    impl ValueVisitor for LeafCounter {
+        fn visit_scalar(&mut self, _value: &Value) {
+            self.leaves += 1;
+        }
+
+        fn visit_collection_start(&mut self, _value: &Value, _len: usize) {
+            self.depth += 1;
+            self.max_depth = self.max_depth.max(self.depth);
+        }
+
+        fn visit_collection_end(&mut self, _value: &Value) {
+            self.depth -= 1;
+        }
+    }
+
+    #[test]
+    fn test_count_leaves() {
+        let mut list = LinkedList::new();
+
+        list.push_back(Value::String("a".to_string()));
+        list.push_back(Value::I32Vec(vec![1, 2, 3]));
+
+        let value = Value::Vec(vec![
+            Value::I32(1),
+            Value::LinkedList(list),
+            Value::Vec(vec![]),
+            Value::Bool(true),
+        ]);
+
+        let mut counter = LeafCounter { leaves: 0, depth: 0, max_depth: 0 };
+
+        value.visit(&mut counter);
+
+        assert_eq!(counter.leaves, 4);
+        assert_eq!(counter.depth, 0);
+        assert_eq!(counter.max_depth, 2);
+    }
+}
@@ -1,16 +1,63 @@
 use std::any::type_name;
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::marker::PhantomData;
+use std::time::Duration;
 
-use bytes::{BytesMut, Bytes, Buf};
+use bytes::{BytesMut, Bytes, BufMut};
 use num_traits::ToPrimitive;
+use bitflags::bitflags;
 
-use crate::binary::{Value, IgniteWrite, IgniteRead};
+use crate::binary::{Value, IgniteWrite, IgniteRead, Binary};
 use crate::error::{Result, ErrorKind, Error};
 use crate::network::Tcp;
 use crate::configuration::CacheConfiguration;
+use crate::transaction::{Transaction, TxConcurrency, TxIsolation};
+use crate::query::{SqlFieldsQuery, FieldsCursor, Row, ScanQuery, ScanCursor, SqlQuery, SqlCursor, FromRow};
 
-#[derive(ToPrimitive, IgniteWrite)]
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub(crate) enum CacheOp {
+    Get = 1000,
+    Put = 1001,
+    PutIfAbsent = 1002,
+    GetAll = 1003,
+    PutAll = 1004,
+    GetAndPut = 1005,
+    GetAndReplace = 1006,
+    GetAndRemove = 1007,
+    GetAndPutIfAbsent = 1008,
+    Replace = 1009,
+    ReplaceIfEquals = 1010,
+    ContainsKey = 1011,
+    ContainsKeys = 1012,
+    Clear = 1013,
+    ClearKey = 1014,
+    ClearKeys = 1015,
+    RemoveKey = 1016,
+    RemoveIfEquals = 1017,
+    RemoveKeys = 1018,
+    RemoveAll = 1019,
+    GetSize = 1020,
+    GetConfiguration = 1055,
+    Destroy = 1056,
+    QueryScan = 2000,
+    QuerySql = 2002,
+    QuerySqlFields = 2004,
+}
+
+impl CacheOp {
+    pub(crate) fn code(self) -> i16 {
+        self as i16
+    }
+
+    // Operations that don't change the cache, so running them twice is harmless.
+    pub(crate) fn is_read_only(self) -> bool {
+        matches!(self, CacheOp::Get | CacheOp::GetAll | CacheOp::ContainsKey | CacheOp::ContainsKeys | CacheOp::GetSize)
+    }
+}
+
+#[derive(ToPrimitive, IgniteWrite, PartialEq, Clone, Copy, Debug)]
 pub enum PeekMode {
     All = 0,
     Near = 1,
@@ -18,22 +65,74 @@ pub enum PeekMode {
     Backup = 3,
 }
 
+bitflags! {
+    #[derive(Clone, Copy, PartialEq, Eq, Debug)]
+    pub struct CacheFlags: u8 {
+        /// Server keeps values in binary form instead of deserializing them.
+        const KEEP_BINARY = 0x01;
+        /// Operation is part of a transaction.
+        const TRANSACTIONAL = 0x02;
+        /// Operation carries an expiry policy.
+        const WITH_EXPIRY_POLICY = 0x04;
+    }
+}
+
 pub struct Cache {
     name: String,
     tcp: Rc<RefCell<Tcp>>,
+    flags: CacheFlags,
+    peek_modes: Vec<PeekMode>,
 }
 
 impl Cache {
     pub(crate) fn new(name: String, tcp: Rc<RefCell<Tcp>>) -> Cache {
-        Cache { name, tcp }
+        let flags = if tcp.borrow().configuration.keep_binary { CacheFlags::KEEP_BINARY } else { CacheFlags::empty() };
+
+        Cache { name, tcp, flags, peek_modes: Vec::new() }
+    }
+
+    pub fn with_flags(&self, flags: CacheFlags) -> Cache {
+        Cache {
+            name: self.name.clone(),
+            tcp: self.tcp.clone(),
+            flags: self.flags | flags,
+            peek_modes: self.peek_modes.clone(),
+        }
+    }
+
+    pub fn without_flags(&self, flags: CacheFlags) -> Cache {
+        Cache {
+            name: self.name.clone(),
+            tcp: self.tcp.clone(),
+            flags: self.flags - flags,
+            peek_modes: self.peek_modes.clone(),
+        }
+    }
+
+    pub fn flags(&self) -> CacheFlags {
+        self.flags
+    }
+
+    /// View of the cache whose `peek_size` uses the given peek modes.
+    pub fn with_peek_modes(&self, peek_modes: &[PeekMode]) -> Cache {
+        Cache {
+            name: self.name.clone(),
+            tcp: self.tcp.clone(),
+            flags: self.flags,
+            peek_modes: peek_modes.to_vec(),
+        }
+    }
+
+    pub fn peek_modes(&self) -> &[PeekMode] {
+        &self.peek_modes
     }
 
     pub fn configuration(&self) -> Result<CacheConfiguration> {
         self.execute(
-            1055,
+            CacheOp::GetConfiguration,
             |_| { Ok(()) },
             |response| {
-                response.advance(4); // Ignore length.
+                i32::read(response)?; // Ignore length.
 
                 CacheConfiguration::read(response)
             }
@@ -41,8 +140,9 @@ impl Cache {
     }
 
     pub fn get(&self, key: &Value) -> Result<Option<Value>> {
-        self.execute(
-            1000,
+        self.execute_keyed(
+            CacheOp::Get,
+            key,
             |request| {
                 key.write(request)
             },
@@ -52,9 +152,36 @@ impl Cache {
         )
     }
 
+    pub fn get_field(&self, key: &Value, name: &str) -> Result<Option<Value>> {
+        Ok(self.get_fields(key, &[name])?.pop().flatten())
+    }
+
+    /// Fetches the object once and returns the fields in the requested order.
+    pub fn get_fields(&self, key: &Value, names: &[&str]) -> Result<Vec<Option<Value>>> {
+        let object = match self.get(key)? {
+            Some(Value::BinaryObject(object)) => object,
+            Some(value) => return Err(Error::new(ErrorKind::InvalidArgument, format!("Not a binary object: {:?}", value))),
+            None => return Ok(vec![None; names.len()]),
+        };
+
+        if object.has_compact_footer() {
+            let schema_id = object.schema_id();
+
+            let schema = Binary::new(self.tcp.clone()).get_type(object.type_id())?
+                .and_then(|t| t.schemas.into_iter().find(|schema| schema.id == schema_id))
+                .ok_or_else(|| Error::new(ErrorKind::Serde, format!("Unknown schema {} for type {}", schema_id, object.type_id())))?;
+
+            object.fields(names, Some(&schema.fields))
+        }
+        else {
+            object.fields(names, None)
+        }
+    }
+
     pub fn put(&self, key: &Value, value: &Value) -> Result<()> {
-        self.execute(
-            1001,
+        self.execute_keyed(
+            CacheOp::Put,
+            key,
             |request| {
                 key.write(request)?;
                 value.write(request)?;
@@ -66,8 +193,9 @@ impl Cache {
     }
 
     pub fn put_if_absent(&self, key: &Value, value: &Value) -> Result<bool> {
-        self.execute(
-            1002,
+        self.execute_keyed(
+            CacheOp::PutIfAbsent,
+            key,
             |request| {
                 key.write(request)?;
                 value.write(request)?;
@@ -80,11 +208,43 @@ impl Cache {
         )
     }
 
+    /// Returns whether each entry was inserted. Runs in a transaction like `get_and_put_all_if_absent`.
+    pub fn put_if_absent_all(&self, entries: &[(Value, Value)]) -> Result<Vec<(Value, bool)>> {
+        self.in_transaction(|| {
+            entries.iter()
+                .map(|(key, value)| Ok((key.clone(), self.put_if_absent(key, value)?)))
+                .collect()
+        })
+    }
+
     pub fn get_all(&self, keys: &[Value]) -> Result<Vec<(Value, Option<Value>)>> {
+        let mut entries = Vec::with_capacity(keys.len());
+
+        for chunk in keys.chunks(self.chunk_size()) {
+            entries.extend(self.get_all_chunk(chunk)?);
+        }
+
+        Ok(entries)
+    }
+
+    /// Like `get_all`, but only holds one chunk of results at a time. A failed chunk yields
+    /// a single error; the remaining chunks are still requested.
+    pub fn get_all_iter<'a>(&'a self, keys: &'a [Value]) -> impl Iterator<Item = Result<(Value, Option<Value>)>> + 'a {
+        keys.chunks(self.chunk_size()).flat_map(move |chunk| {
+            let (entries, err) = match self.get_all_chunk(chunk) {
+                Ok(entries) => (entries, None),
+                Err(err) => (Vec::new(), Some(Err(err))),
+            };
+
+            entries.into_iter().map(Ok).chain(err)
+        })
+    }
+
+    fn get_all_chunk(&self, chunk: &[Value]) -> Result<Vec<(Value, Option<Value>)>> {
         self.execute(
-            1003,
+            CacheOp::GetAll,
             |request| {
-                keys.write(request)
+                chunk.write(request)
             },
             |response| {
                 <Vec<(Value, Option<Value>)>>::read(response)
@@ -94,7 +254,7 @@ impl Cache {
 
     pub fn put_all(&self, entries: &[(Value, Value)]) -> Result<()> {
         self.execute(
-            1004,
+            CacheOp::PutAll,
             |request| {
                 entries.write(request)
             },
@@ -103,8 +263,9 @@ impl Cache {
     }
 
     pub fn get_and_put(&self, key: &Value, value: &Value) -> Result<Option<Value>> {
-        self.execute(
-            1005,
+        self.execute_keyed(
+            CacheOp::GetAndPut,
+            key,
             |request| {
                 key.write(request)?;
                 value.write(request)?;
@@ -118,8 +279,9 @@ impl Cache {
     }
 
     pub fn get_and_replace(&self, key: &Value, value: &Value) -> Result<Option<Value>> {
-        self.execute(
-            1006,
+        self.execute_keyed(
+            CacheOp::GetAndReplace,
+            key,
             |request| {
                 key.write(request)?;
                 value.write(request)?;
@@ -133,8 +295,9 @@ impl Cache {
     }
 
     pub fn get_and_remove(&self, key: &Value) -> Result<Option<Value>> {
-        self.execute(
-            1007,
+        self.execute_keyed(
+            CacheOp::GetAndRemove,
+            key,
             |request| {
                 key.write(request)
             },
@@ -145,8 +308,9 @@ impl Cache {
     }
 
     pub fn get_and_put_if_absent(&self, key: &Value, value: &Value) -> Result<Option<Value>> {
-        self.execute(
-            1008,
+        self.execute_keyed(
+            CacheOp::GetAndPutIfAbsent,
+            key,
             |request| {
                 key.write(request)?;
                 value.write(request)?;
@@ -159,9 +323,20 @@ impl Cache {
         )
     }
 
+    /// Returns existing values for present keys and `None` for keys that were written. Runs in a
+    /// pessimistic transaction, or in the one already open, so the batch is applied as a whole.
+    pub fn get_and_put_all_if_absent(&self, entries: &[(Value, Value)]) -> Result<Vec<(Value, Option<Value>)>> {
+        self.in_transaction(|| {
+            entries.iter()
+                .map(|(key, value)| Ok((key.clone(), self.get_and_put_if_absent(key, value)?)))
+                .collect()
+        })
+    }
+
     pub fn replace(&self, key: &Value, value: &Value) -> Result<bool> {
-        self.execute(
-            1009,
+        self.execute_keyed(
+            CacheOp::Replace,
+            key,
             |request| {
                 key.write(request)?;
                 value.write(request)?;
@@ -175,8 +350,9 @@ impl Cache {
     }
 
     pub fn replace_if_equals(&self, key: &Value, old_value: &Value, new_value: &Value) -> Result<bool> {
-        self.execute(
-            1010,
+        self.execute_keyed(
+            CacheOp::ReplaceIfEquals,
+            key,
             |request| {
                 key.write(request)?;
                 old_value.write(request)?;
@@ -190,9 +366,31 @@ impl Cache {
         )
     }
 
+    /// Atomically replaces the value with the one returned by `f`; returning `None` removes
+    /// the entry. Runs in a pessimistic transaction that locks the key, so the cache must be
+    /// transactional. Joins the transaction already open on the client, if any.
+    pub fn compute<F>(&self, key: &Value, f: F) -> Result<Option<Value>>
+        where
+            F: FnOnce(Option<&Value>) -> Option<Value>,
+    {
+        self.in_transaction(|| {
+            let current = self.get(key)?;
+            let new = f(current.as_ref());
+
+            match &new {
+                Some(new) => self.put(key, new)?,
+                None if current.is_some() => { self.remove_key(key)?; },
+                None => {},
+            }
+
+            Ok(new)
+        })
+    }
+
     pub fn contains_key(&self, key: &Value) -> Result<bool> {
-        self.execute(
-            1011,
+        self.execute_keyed(
+            CacheOp::ContainsKey,
+            key,
             |request| {
                 key.write(request)
             },
@@ -203,28 +401,48 @@ impl Cache {
     }
 
     pub fn contains_keys(&self, keys: &[Value]) -> Result<bool> {
-        self.execute(
-            1012,
-            |request| {
-                keys.write(request)
-            },
-            |response| {
-                bool::read(response)
+        for chunk in keys.chunks(self.chunk_size()) {
+            let contains = self.execute(
+                CacheOp::ContainsKeys,
+                |request| {
+                    chunk.write(request)
+                },
+                |response| {
+                    bool::read(response)
+                }
+            )?;
+
+            if !contains {
+                return Ok(false);
             }
-        )
+        }
+
+        Ok(true)
     }
 
     pub fn clear(&self) -> Result<()> {
         self.execute(
-            1013,
+            CacheOp::Clear,
             |_| { Ok(()) },
             |_| { Ok(()) }
         )
     }
 
+    /// Clears the cache and returns the number of entries it held. The protocol doesn't report
+    /// this, so the count is taken right before clearing and is not exact if the cache is
+    /// modified concurrently.
+    pub fn clear_counting(&self) -> Result<i64> {
+        let size = self.size(&[PeekMode::Primary])?;
+
+        self.clear()?;
+
+        Ok(size)
+    }
+
     pub fn clear_key(&self, key: &Value) -> Result<()> {
-        self.execute(
-            1014,
+        self.execute_keyed(
+            CacheOp::ClearKey,
+            key,
             |request| {
                 key.write(request)
             },
@@ -234,7 +452,7 @@ impl Cache {
 
     pub fn clear_keys(&self, keys: &[Value]) -> Result<()> {
         self.execute(
-            1015,
+            CacheOp::ClearKeys,
             |request| {
                 keys.write(request)
             },
@@ -243,8 +461,9 @@ impl Cache {
     }
 
     pub fn remove_key(&self, key: &Value) -> Result<bool> {
-        self.execute(
-            1016,
+        self.execute_keyed(
+            CacheOp::RemoveKey,
+            key,
             |request| {
                 key.write(request)
             },
@@ -255,8 +474,9 @@ impl Cache {
     }
 
     pub fn remove_if_equals(&self, key: &Value, old_value: &Value) -> Result<bool> {
-        self.execute(
-            1017,
+        self.execute_keyed(
+            CacheOp::RemoveIfEquals,
+            key,
             |request| {
                 key.write(request)?;
                 old_value.write(request)?;
@@ -270,26 +490,34 @@ impl Cache {
     }
 
     pub fn remove_keys(&self, keys: &[Value]) -> Result<()> {
-        self.execute(
-            1018,
-            |request| {
-                keys.write(request)
-            },
-            |_| { Ok(()) }
-        )
+        for chunk in keys.chunks(self.chunk_size()) {
+            self.execute(
+                CacheOp::RemoveKeys,
+                |request| {
+                    chunk.write(request)
+                },
+                |_| { Ok(()) }
+            )?;
+        }
+
+        Ok(())
     }
 
+    /// Sent as a single request over the main connection, even with partition awareness. The
+    /// receiving node removes the entries on every primary itself, so sending the request to each
+    /// node would repeat the whole removal rather than split it. The same holds for `clear`.
     pub fn remove_all(&self) -> Result<()> {
         self.execute(
-            1019,
+            CacheOp::RemoveAll,
             |_| { Ok(()) },
             |_| { Ok(()) }
         )
     }
 
+    /// Exact number of entries in the given peek modes, as reported on the wire.
     pub fn size(&self, peek_modes: &[PeekMode]) -> Result<i64> {
         self.execute(
-            1020,
+            CacheOp::GetSize,
             |request| {
                 peek_modes.write(request)
             },
@@ -299,9 +527,171 @@ impl Cache {
         )
     }
 
+    /// Same as `size`, optionally restricted to a single partition.
+    ///
+    /// The protocol has no partitioned size request, so a partition is counted
+    /// by scanning it: every entry of the partition is transferred to the client,
+    /// and the scan takes one of the `Configuration::max_open_cursors` permits
+    /// while it runs. Scans only see primary copies, so the only peek modes
+    /// allowed with a partition are `All` and `Primary`.
+    pub fn size_long(&self, peek_modes: &[PeekMode], partition: Option<i32>) -> Result<i64> {
+        let partition = match partition {
+            Some(partition) => partition,
+            None => return self.size(peek_modes),
+        };
+
+        if let Some(mode) = peek_modes.iter().find(|mode| !matches!(mode, PeekMode::All | PeekMode::Primary)) {
+            return Err(Error::new(ErrorKind::NotSupported, format!("Peek mode is not supported for a partition: {:?}", mode)));
+        }
+
+        let mut size = 0;
+
+        for entry in self.scan_partition(partition)? {
+            entry?;
+
+            size += 1;
+        }
+
+        Ok(size)
+    }
+
+    /// Same as `size` with the peek modes set by `with_peek_modes` (all entries by default).
+    pub fn peek_size(&self) -> Result<i64> {
+        self.size(&self.peek_modes)
+    }
+
+    /// Exact number of entries across all peek modes, checked to fit `usize`.
+    pub fn len(&self) -> Result<usize> {
+        let size = self.size(&[])?;
+
+        usize::try_from(size).map_err(|_| Error::new(ErrorKind::Serde, format!("Invalid cache size: {}", size)))
+    }
+
+    pub fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Cheap estimate of the number of entries: counts primary copies only.
+    ///
+    /// Skips backup and near cache entries, so it is faster than `size` on
+    /// large caches but may diverge from it while entries are being
+    /// rebalanced or when near caches hold entries of their own.
+    pub fn size_estimate(&self) -> Result<i64> {
+        self.size(&[PeekMode::Primary])
+    }
+
+    pub fn scan(&self, query: ScanQuery) -> Result<ScanCursor> {
+        let query = query.default_page_size(self.tcp.borrow().configuration.default_page_size);
+
+        let permit = self.tcp.borrow().acquire_cursor()?;
+
+        let cursor = self.execute(
+            CacheOp::QueryScan,
+            |request| {
+                query.write(request)
+            },
+            |response| {
+                ScanCursor::read(self.tcp.clone(), response)
+            }
+        )?;
+
+        Ok(cursor.with_permit(permit))
+    }
+
+    /// Shorthand for `scan` over all partitions, fetching `page_size` entries per page.
+    pub fn query_scan(&self, page_size: i32) -> Result<ScanCursor> {
+        if page_size <= 0 {
+            return Err(Error::new(ErrorKind::InvalidArgument, format!("Invalid page size: {}", page_size)));
+        }
+
+        self.scan(ScanQuery::new().page_size(page_size))
+    }
+
+    /// Scans a single partition. The partition must be in `0..partitions`; the upper bound is
+    /// checked against the partition map if partition awareness has one, and by the server otherwise.
+    pub fn scan_partition(&self, partition: i32) -> Result<ScanCursor> {
+        let partitions = self.tcp.borrow_mut().routing.as_mut().and_then(|routing| routing.partitions(self.id()));
+
+        if partition < 0 || partitions.is_some_and(|partitions| partition as usize >= partitions) {
+            return Err(Error::new(ErrorKind::InvalidArgument, format!("Invalid partition: {}", partition)));
+        }
+
+        self.scan(ScanQuery::new().partition(partition))
+    }
+
+    pub fn query(&self, query: SqlQuery) -> Result<SqlCursor> {
+        let query = query.default_page_size(self.tcp.borrow().configuration.default_page_size);
+
+        let permit = self.tcp.borrow().acquire_cursor()?;
+
+        let cursor = self.execute(
+            CacheOp::QuerySql,
+            |request| {
+                query.write(request)
+            },
+            |response| {
+                SqlCursor::read(self.tcp.clone(), response)
+            }
+        )?;
+
+        Ok(cursor.with_permit(permit))
+    }
+
+    /// Shorthand for `query` with default options.
+    pub fn query_sql(&self, type_name: &str, sql: &str, args: &[Value]) -> Result<SqlCursor> {
+        self.query(SqlQuery::new(type_name, sql).args(args))
+    }
+
+    pub fn query_fields(&self, query: SqlFieldsQuery) -> Result<FieldsCursor> {
+        let query = query.with_defaults(&self.tcp.borrow().configuration);
+
+        let permit = self.tcp.borrow().acquire_cursor()?;
+
+        let cursor = self.execute(
+            CacheOp::QuerySqlFields,
+            |request| {
+                query.write(request)
+            },
+            |response| {
+                FieldsCursor::read(self.tcp.clone(), query.include_field_names, response)
+            }
+        )?;
+
+        Ok(cursor.with_permit(permit))
+    }
+
+    /// Shorthand for `query_fields` with default options and column names included.
+    pub fn query_sql_fields(&self, sql: &str, args: &[Value]) -> Result<FieldsCursor> {
+        self.query_fields(SqlFieldsQuery::new(sql).args(args).include_field_names(true))
+    }
+
+    /// Runs a fields query and maps each row to `T`.
+    pub fn query_fields_into<T: FromRow>(&self, query: SqlFieldsQuery) -> Result<impl Iterator<Item = Result<T>>> {
+        let cursor = self.query_fields(query.include_field_names(true))?;
+
+        let columns = cursor.columns().to_vec();
+
+        Ok(cursor.map(move |row| T::from_row(row?, &columns)))
+    }
+
+    /// Runs a fields query and passes each row to `f`, fetching one page at a time. The cursor is closed
+    /// when all rows are processed or `f` returns an error.
+    pub fn query_fields_for_each<F>(&self, sql: &str, args: &[Value], mut f: F) -> Result<()>
+        where
+            F: FnMut(Row) -> Result<()>,
+    {
+        let cursor = self.query_fields(SqlFieldsQuery::new(sql).args(args))?;
+
+        for row in cursor {
+            f(row?)?;
+        }
+
+        Ok(())
+    }
+
     pub fn destroy(&self) -> Result<()> {
         self.tcp.borrow_mut().execute(
-            1056,
+            CacheOp::Destroy.code(),
             |request| {
                 self.id().write(request)
             },
@@ -309,18 +699,18 @@ impl Cache {
         )
     }
 
-    fn execute<R, F1, F2>(&self, operation_code: i16, request_writer: F1, response_reader: F2) -> Result<R>
+    fn execute<R, F1, F2>(&self, op: CacheOp, request_writer: F1, response_reader: F2) -> Result<R>
         where
             F1: Fn(&mut BytesMut) -> Result<()>,
             F2: Fn(&mut Bytes) -> Result<R>,
     {
-        self.tcp.borrow_mut().execute(
-            operation_code,
-            |request| {
-                self.id().write(request)?;
+        let tx_id = self.tcp.borrow().current_tx()?;
 
-                // Unused byte.
-                request.advance(1);
+        self.tcp.borrow_mut().execute_op(
+            op.code(),
+            op.is_read_only(),
+            |request| {
+                self.write_header(request, tx_id)?;
 
                 request_writer(request)
             },
@@ -328,16 +718,561 @@ impl Cache {
         )
     }
 
-    // TODO: Fails with overflow for some names
+    // Goes to the primary node of the key if partition awareness is enabled. Transactions are
+    // bound to the main connection, so operations in a transaction are never routed.
+    fn execute_keyed<R, F1, F2>(&self, op: CacheOp, key: &Value, request_writer: F1, response_reader: F2) -> Result<R>
+        where
+            F1: Fn(&mut BytesMut) -> Result<()>,
+            F2: Fn(&mut Bytes) -> Result<R>,
+    {
+        let routed = {
+            let mut tcp = self.tcp.borrow_mut();
+
+            match (tcp.current_tx()?, tcp.routing.as_mut()) {
+                (None, Some(routing)) => routing.execute(
+                    self.id(),
+                    key,
+                    op.code(),
+                    op.is_read_only(),
+                    |request| {
+                        self.write_header(request, None)?;
+
+                        request_writer(request)
+                    },
+                    &response_reader
+                ),
+                _ => None,
+            }
+        };
+
+        match routed {
+            Some(result) => result,
+            None => self.execute(op, request_writer, response_reader),
+        }
+    }
+
+    // Runs `f` in a new pessimistic transaction, or in the one already open on the connection.
+    // The new transaction is rolled back if `f` fails.
+    fn in_transaction<R, F: FnOnce() -> Result<R>>(&self, f: F) -> Result<R> {
+        if self.tcp.borrow().current_tx()?.is_some() {
+            return f();
+        }
+
+        let tx = Transaction::start(self.tcp.clone(), TxConcurrency::Pessimistic, TxIsolation::RepeatableRead, Duration::default(), None)?;
+
+        let result = f()?;
+
+        tx.commit()?;
+
+        Ok(result)
+    }
+
+    fn write_header(&self, request: &mut BytesMut, tx_id: Option<i32>) -> Result<()> {
+        self.id().write(request)?;
+
+        match tx_id {
+            Some(tx_id) => {
+                request.put_u8((self.flags | CacheFlags::TRANSACTIONAL).bits());
+
+                tx_id.write(request)
+            },
+            None => {
+                request.put_u8(self.flags.bits());
+
+                Ok(())
+            },
+        }
+    }
+
+    fn chunk_size(&self) -> usize {
+        self.tcp.borrow().configuration.chunk_size.max(1)
+    }
+
+    /// ID the server uses for this cache in logs and metrics.
+    pub fn cache_id(&self) -> i32 {
+        self.id()
+    }
+
     fn id(&self) -> i32 {
-        let mut hash = 0i64;
+        cache_id(&self.name)
+    }
+}
+
+/// `Cache` that converts keys and values to and from `Value`. Values that don't convert
+/// to `V` on read yield a `Serde` error.
+pub struct TypedCache<K, V> {
+    cache: Cache,
+    types: PhantomData<(K, V)>,
+}
+
+impl<K, V> TypedCache<K, V>
+    where
+        K: Clone + Into<Value> + TryFrom<Value>,
+        V: Clone + Into<Value> + TryFrom<Value>,
+        Error: From<K::Error> + From<V::Error>,
+{
+    pub fn new(cache: Cache) -> TypedCache<K, V> {
+        TypedCache { cache, types: PhantomData }
+    }
+
+    /// Untyped view of the same cache.
+    pub fn cache(&self) -> &Cache {
+        &self.cache
+    }
+
+    pub fn get(&self, key: &K) -> Result<Option<V>> {
+        convert(self.cache.get(&key.clone().into())?)
+    }
+
+    pub fn put(&self, key: &K, value: &V) -> Result<()> {
+        self.cache.put(&key.clone().into(), &value.clone().into())
+    }
+
+    pub fn put_if_absent(&self, key: &K, value: &V) -> Result<bool> {
+        self.cache.put_if_absent(&key.clone().into(), &value.clone().into())
+    }
+
+    pub fn get_all(&self, keys: &[K]) -> Result<Vec<(K, Option<V>)>> {
+        let keys: Vec<Value> = keys.iter().map(|key| key.clone().into()).collect();
+
+        self.cache.get_all(&keys)?
+            .into_iter()
+            .map(|(key, value)| Ok((K::try_from(key)?, convert(value)?)))
+            .collect()
+    }
+
+    pub fn put_all(&self, entries: &[(K, V)]) -> Result<()> {
+        let entries: Vec<(Value, Value)> = entries.iter()
+            .map(|(key, value)| (key.clone().into(), value.clone().into()))
+            .collect();
+
+        self.cache.put_all(&entries)
+    }
+
+    pub fn get_and_put(&self, key: &K, value: &V) -> Result<Option<V>> {
+        convert(self.cache.get_and_put(&key.clone().into(), &value.clone().into())?)
+    }
+
+    pub fn get_and_replace(&self, key: &K, value: &V) -> Result<Option<V>> {
+        convert(self.cache.get_and_replace(&key.clone().into(), &value.clone().into())?)
+    }
+
+    pub fn get_and_remove(&self, key: &K) -> Result<Option<V>> {
+        convert(self.cache.get_and_remove(&key.clone().into())?)
+    }
+
+    pub fn replace(&self, key: &K, value: &V) -> Result<bool> {
+        self.cache.replace(&key.clone().into(), &value.clone().into())
+    }
+
+    pub fn contains_key(&self, key: &K) -> Result<bool> {
+        self.cache.contains_key(&key.clone().into())
+    }
+
+    pub fn remove_key(&self, key: &K) -> Result<bool> {
+        self.cache.remove_key(&key.clone().into())
+    }
+}
+
+fn convert<V>(value: Option<Value>) -> Result<Option<V>>
+    where
+        V: TryFrom<Value>,
+        Error: From<V::Error>,
+{
+    Ok(value.map(V::try_from).transpose()?)
+}
+
+/// ID the server assigns to the cache with the given name: Java's `String.hashCode` of the name.
+pub fn cache_id(name: &str) -> i32 {
+    name.encode_utf16()
+        .fold(0i32, |hash, c| hash.wrapping_mul(31).wrapping_add(c as i32))
+}
+
+// === Tests
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    use bytes::BytesMut;
+
+    use crate::{Client, FeatureSet, VERSION_1_5_0};
+    use crate::binary::IgniteWrite;
+    use crate::binary::Value;
+    use crate::cache::{Cache, CacheFlags, CacheOp, PeekMode, TypedCache, cache_id};
+    use crate::configuration::Configuration;
+    use crate::error::ErrorKind;
+    use crate::mock::MockServer;
+    use crate::network::Tcp;
+    use crate::network::tests::{server, tcp, tcp_with_responses};
+    use crate::transaction::{Transaction, TxConcurrency, TxIsolation};
+
+    // Successful response in the 1.5.0 layout, with the request ID filled in by the server.
+    fn response(payload: &[u8]) -> Vec<u8> {
+        let mut response = BytesMut::new();
+
+        0i64.write(&mut response).unwrap();
+        0i16.write(&mut response).unwrap(); // Flags.
+        response.extend_from_slice(payload);
+
+        response.to_vec()
+    }
+
+    // Connection that supports transactions and records the op code and cache flags (0 for other
+    // operations) of each request.
+    fn tx_tcp(responses: Vec<Vec<u8>>, requests: Arc<Mutex<Vec<(i16, u8)>>>) -> Rc<RefCell<Tcp>> {
+        let mut tcp = tcp_with_responses(responses);
+
+        tcp.features = FeatureSet::new(VERSION_1_5_0, Vec::new());
+        tcp.configuration = Configuration::default().on_request(move |op, request| {
+            // Cache operations start with the cache ID and flags after the op code and request ID.
+            let flags = if (1000 .. 2000).contains(&op) { request[14] } else { 0 };
+
+            requests.lock().unwrap().push((op, flags));
+
+            Ok(())
+        });
+
+        Rc::new(RefCell::new(tcp))
+    }
+
+    #[test]
+    fn test_id() {
+        let id = |name: &str| Cache::new(name.to_string(), Rc::new(RefCell::new(tcp()))).id();
+
+        // Expected values are from `String.hashCode` on the JVM.
+        assert_eq!(id(""), 0);
+        assert_eq!(id("test-cache"), 623628935);
+        assert_eq!(id(&"a".repeat(200)), 469437568);
+        assert_eq!(id("\u{43a}\u{44d}\u{448}-\u{4e2d}\u{6587}"), 1962107506);
+        assert_eq!(id("\u{1f600}cache"), -1235908609);
+
+        let cache = Cache::new("test-cache".to_string(), Rc::new(RefCell::new(tcp())));
+
+        assert_eq!(cache.cache_id(), 623628935);
+        assert_eq!(cache_id("test-cache"), 623628935);
+    }
+
+    #[test]
+    fn test_with_peek_modes() {
+        let mut response = BytesMut::new();
+
+        0i64.write(&mut response).unwrap(); // Request ID.
+        0i32.write(&mut response).unwrap(); // Status.
+        7i64.write(&mut response).unwrap();
+
+        let mut tcp = tcp_with_responses(vec![response.to_vec(), response.to_vec()]);
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requests_clone = requests.clone();
+
+        tcp.configuration = Configuration::default().on_request(move |_, request| {
+            requests_clone.lock().unwrap().push(request.to_vec());
+
+            Ok(())
+        });
+
+        let cache = Cache::new("test-cache".to_string(), Rc::new(RefCell::new(tcp)));
+        let view = cache.with_peek_modes(&[PeekMode::Primary]);
+
+        assert_eq!(cache.peek_modes(), &[]);
+        assert_eq!(view.peek_modes(), &[PeekMode::Primary]);
+
+        assert_eq!(cache.size(&[PeekMode::Primary]), Ok(7));
+        assert_eq!(view.peek_size(), Ok(7));
+
+        let requests = requests.lock().unwrap();
+
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0][10 ..], requests[1][10 ..]); // Same apart from op code and request ID.
+    }
+
+    #[test]
+    fn test_keep_binary() {
+        let mut tcp = tcp();
+
+        tcp.configuration = Configuration::default().keep_binary(true);
+
+        let cache = Cache::new("test-cache".to_string(), Rc::new(RefCell::new(tcp)));
+
+        assert_eq!(cache.flags(), CacheFlags::KEEP_BINARY);
+        assert_eq!(cache.with_flags(CacheFlags::WITH_EXPIRY_POLICY).without_flags(CacheFlags::KEEP_BINARY).flags(), CacheFlags::WITH_EXPIRY_POLICY);
+
+        let mut request = BytesMut::new();
+
+        cache.write_header(&mut request, None).unwrap();
+
+        assert_eq!(request[4], 0x01);
+    }
 
-        for c in self.name.chars() {
-            let c = c as i64;
+    #[test]
+    fn test_with_flags() {
+        let cache = Cache::new("test-cache".to_string(), Rc::new(RefCell::new(tcp())));
 
-            hash = 31 * hash + c;
+        assert_eq!(cache.flags(), CacheFlags::empty());
+
+        let cache = cache
+            .with_flags(CacheFlags::KEEP_BINARY)
+            .with_flags(CacheFlags::WITH_EXPIRY_POLICY);
+
+        assert_eq!(cache.flags(), CacheFlags::KEEP_BINARY | CacheFlags::WITH_EXPIRY_POLICY);
+
+        let mut request = BytesMut::new();
+
+        cache.write_header(&mut request, None).unwrap();
+
+        let mut expected = cache.id().to_le_bytes().to_vec();
+
+        expected.push(0x05);
+
+        assert_eq!(request.to_vec(), expected);
+
+        let mut request = BytesMut::new();
+
+        cache.write_header(&mut request, Some(7)).unwrap();
+
+        let mut expected = cache.id().to_le_bytes().to_vec();
+
+        expected.push(0x07);
+        expected.extend_from_slice(&7i32.to_le_bytes());
+
+        assert_eq!(request.to_vec(), expected);
+    }
+
+    #[test]
+    fn test_cache_op_codes() {
+        let ops = [
+            (CacheOp::Get, 1000),
+            (CacheOp::Put, 1001),
+            (CacheOp::PutIfAbsent, 1002),
+            (CacheOp::GetAll, 1003),
+            (CacheOp::PutAll, 1004),
+            (CacheOp::GetAndPut, 1005),
+            (CacheOp::GetAndReplace, 1006),
+            (CacheOp::GetAndRemove, 1007),
+            (CacheOp::GetAndPutIfAbsent, 1008),
+            (CacheOp::Replace, 1009),
+            (CacheOp::ReplaceIfEquals, 1010),
+            (CacheOp::ContainsKey, 1011),
+            (CacheOp::ContainsKeys, 1012),
+            (CacheOp::Clear, 1013),
+            (CacheOp::ClearKey, 1014),
+            (CacheOp::ClearKeys, 1015),
+            (CacheOp::RemoveKey, 1016),
+            (CacheOp::RemoveIfEquals, 1017),
+            (CacheOp::RemoveKeys, 1018),
+            (CacheOp::RemoveAll, 1019),
+            (CacheOp::GetSize, 1020),
+            (CacheOp::GetConfiguration, 1055),
+            (CacheOp::Destroy, 1056),
+            (CacheOp::QueryScan, 2000),
+            (CacheOp::QuerySql, 2002),
+            (CacheOp::QuerySqlFields, 2004),
+        ];
+
+        for (op, code) in ops.iter() {
+            assert_eq!(op.code(), *code, "{:?}", op);
         }
+    }
+
+    #[test]
+    fn test_typed_cache() {
+        let server = MockServer::start();
+
+        let client = Client::start(server.configuration()).unwrap();
+
+        let cache: TypedCache<i32, String> = TypedCache::new(client.cache("test-cache"));
+
+        assert_eq!(cache.get(&1), Ok(None));
+        assert_eq!(cache.put(&1, &"one".to_string()), Ok(()));
+        assert_eq!(cache.get(&1), Ok(Some("one".to_string())));
+        assert_eq!(cache.contains_key(&1), Ok(true));
+
+        // Written through the untyped API with a different value type.
+        assert_eq!(cache.cache().put(&Value::I32(2), &Value::I64(2)), Ok(()));
+
+        assert_eq!(cache.get(&2).unwrap_err().kind, ErrorKind::Serde);
+    }
+
+    #[test]
+    fn test_compute() {
+        let mut current = BytesMut::new();
+
+        1i32.write(&mut current).unwrap(); // Transaction ID.
+
+        let mut value = BytesMut::new();
+
+        Some(Value::I64(1)).write(&mut value).unwrap();
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let tcp = tx_tcp(vec![response(&current), response(&value), response(&[]), response(&[])], requests.clone());
+
+        let cache = Cache::new("test-cache".to_string(), tcp.clone());
+
+        let result = cache.compute(&Value::I32(1), |value| match value {
+            Some(Value::I64(v)) => Some(Value::I64(v + 1)),
+            _ => Some(Value::I64(1)),
+        });
+
+        assert_eq!(result, Ok(Some(Value::I64(2))));
+        assert_eq!(tcp.borrow().tx_id, None);
+
+        let transactional = CacheFlags::TRANSACTIONAL.bits();
+
+        // Start, get, put and commit, with the key locked by the pessimistic transaction in between.
+        assert_eq!(*requests.lock().unwrap(), vec![(4000, 0), (1000, transactional), (1001, transactional), (4001, 0)]);
+    }
+
+    #[test]
+    fn test_transaction_lost_on_reconnect() {
+        let mut tx_id = BytesMut::new();
+
+        7i32.write(&mut tx_id).unwrap();
+
+        // The new connection negotiates protocol 1.1.0, so its responses have a status instead of flags.
+        let mut ok = BytesMut::new();
+
+        0i64.write(&mut ok).unwrap(); // Request ID.
+        0i32.write(&mut ok).unwrap(); // Status.
+
+        // Server drops the connection after the transaction starts.
+        let address = server(vec![vec![vec![1], response(&tx_id)], vec![vec![1], ok.to_vec()]]);
+
+        let mut tcp = Tcp::connect(Configuration::default().address(&address).reconnect(true)).unwrap();
+
+        tcp.features = FeatureSet::new(VERSION_1_5_0, Vec::new());
+
+        let tcp = Rc::new(RefCell::new(tcp));
+        let cache = Cache::new("test-cache".to_string(), tcp.clone());
+
+        let tx = Transaction::start(tcp.clone(), TxConcurrency::Pessimistic, TxIsolation::ReadCommitted, Duration::from_secs(1), None).unwrap();
+
+        // The request is not retried on the new connection, where it would run outside of the transaction.
+        assert_eq!(cache.put(&Value::I32(1), &Value::I32(1)).unwrap_err().kind, ErrorKind::Network);
+        assert_eq!(tcp.borrow().tx_id, None);
+
+        let err = cache.put(&Value::I32(1), &Value::I32(1)).unwrap_err();
+
+        assert_eq!(err.kind, ErrorKind::Network);
+        assert!(err.message.contains("Transaction 7 was lost"), "{}", err.message);
+
+        assert_eq!(tx.commit().unwrap_err().kind, ErrorKind::Network);
+
+        // Once the transaction is ended, operations run on the new connection.
+        assert_eq!(cache.put(&Value::I32(1), &Value::I32(1)), Ok(()));
+    }
+
+    #[test]
+    fn test_get_and_put_all_if_absent() {
+        let mut tx_id = BytesMut::new();
+
+        1i32.write(&mut tx_id).unwrap();
+
+        let existing = |value: Option<Value>| {
+            let mut payload = BytesMut::new();
+
+            value.write(&mut payload).unwrap();
+
+            response(&payload)
+        };
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        // Keys 0 and 2 already exist.
+        let tcp = tx_tcp(vec![
+            response(&tx_id),
+            existing(Some(Value::I32(0))),
+            existing(None),
+            existing(Some(Value::I32(2))),
+            existing(None),
+            response(&[]),
+        ], requests.clone());
+
+        let cache = Cache::new("test-cache".to_string(), tcp.clone());
+
+        let entries: Vec<(Value, Value)> = (0 .. 4).map(|i| (Value::I32(i), Value::I32(i * 10))).collect();
+
+        assert_eq!(cache.get_and_put_all_if_absent(&entries), Ok(vec![
+            (Value::I32(0), Some(Value::I32(0))),
+            (Value::I32(1), None),
+            (Value::I32(2), Some(Value::I32(2))),
+            (Value::I32(3), None),
+        ]));
+        assert_eq!(tcp.borrow().tx_id, None);
+
+        let transactional = CacheFlags::TRANSACTIONAL.bits();
+
+        let mut expected = vec![(4000, 0)];
+
+        expected.extend((0 .. 4).map(|_| (1008, transactional)));
+        expected.push((4001, 0));
+
+        assert_eq!(*requests.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_put_if_absent_all() {
+        let mut tx_id = BytesMut::new();
+
+        1i32.write(&mut tx_id).unwrap();
+
+        let inserted = |inserted: bool| response(&[inserted as u8]);
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        // Keys 1 and 3 already exist.
+        let tcp = tx_tcp(vec![response(&tx_id), inserted(true), inserted(false), inserted(true), inserted(false), response(&[])], requests.clone());
+
+        let cache = Cache::new("test-cache".to_string(), tcp.clone());
+
+        let entries: Vec<(Value, Value)> = (0 .. 4).map(|i| (Value::I32(i), Value::I32(i * 10))).collect();
+
+        assert_eq!(cache.put_if_absent_all(&entries), Ok(vec![
+            (Value::I32(0), true),
+            (Value::I32(1), false),
+            (Value::I32(2), true),
+            (Value::I32(3), false),
+        ]));
+
+        let transactional = CacheFlags::TRANSACTIONAL.bits();
+
+        let mut expected = vec![(4000, 0)];
+
+        expected.extend((0 .. 4).map(|_| (1002, transactional)));
+        expected.push((4001, 0));
+
+        assert_eq!(*requests.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_idle_timeout_in_transaction() {
+        let mut tx_id = BytesMut::new();
+
+        1i32.write(&mut tx_id).unwrap();
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let tcp = tx_tcp(vec![response(&tx_id), response(&[]), response(&[])], requests.clone());
+
+        tcp.borrow_mut().configuration.idle_timeout = Some(Duration::from_millis(10));
+
+        let cache = Cache::new("test-cache".to_string(), tcp.clone());
+
+        let tx = Transaction::start(tcp.clone(), TxConcurrency::Pessimistic, TxIsolation::ReadCommitted, Duration::from_secs(1), None).unwrap();
+
+        thread::sleep(Duration::from_millis(20));
+
+        // Sent over the same connection instead of reconnecting and losing the transaction.
+        assert_eq!(cache.put(&Value::I32(1), &Value::I32(1)), Ok(()));
+        assert_eq!(tx.commit(), Ok(()));
+
+        let transactional = CacheFlags::TRANSACTIONAL.bits();
 
-        hash as i32
+        assert_eq!(*requests.lock().unwrap(), vec![(4000, 0), (1001, transactional), (4001, 0)]);
     }
 }
@@ -0,0 +1,50 @@
+use bytes::{Bytes, BytesMut};
+use num_traits::{FromPrimitive, ToPrimitive};
+
+use crate::binary::{IgniteRead, IgniteWrite};
+use crate::error::{Result, Error, ErrorKind};
+
+#[derive(FromPrimitive, ToPrimitive, PartialEq, Clone, Copy, Debug)]
+pub enum ClusterState {
+    Inactive = 0,
+    Active = 1,
+    ActiveReadOnly = 2,
+}
+
+// Sent as a single byte, unlike the configuration enums.
+impl IgniteRead for ClusterState {
+    fn read(bytes: &mut Bytes) -> Result<ClusterState> {
+        let state = i8::read(bytes)?;
+
+        ClusterState::from_i8(state).ok_or_else(|| Error::new(ErrorKind::Serde, format!("Invalid cluster state: {}", state)))
+    }
+}
+
+impl IgniteWrite for ClusterState {
+    fn write(&self, bytes: &mut BytesMut) -> Result<()> {
+        let state = self.to_i8().ok_or_else(|| Error::new(ErrorKind::Serde, format!("Invalid cluster state: {:?}", self)))?;
+
+        state.write(bytes)
+    }
+}
+
+// === Tests
+
+#[cfg(test)]
+mod tests {
+    use bytes::{Bytes, BytesMut};
+
+    use crate::binary::{IgniteRead, IgniteWrite};
+    use crate::cluster::ClusterState;
+
+    #[test]
+    fn test_cluster_state() {
+        let mut bytes = BytesMut::new();
+
+        ClusterState::ActiveReadOnly.write(&mut bytes).unwrap();
+
+        assert_eq!(bytes.to_vec(), vec![2]);
+        assert_eq!(ClusterState::read(&mut Bytes::from(vec![1])), Ok(ClusterState::Active));
+        assert!(ClusterState::read(&mut Bytes::from(vec![3])).is_err());
+    }
+}
@@ -1,28 +1,89 @@
 use std::any::type_name;
+use std::time::Duration;
+use std::sync::Arc;
+#[cfg(any(unix, feature = "tls"))]
+use std::path::{Path, PathBuf};
 
 use bytes::{Bytes, BytesMut, BufMut};
 use num_traits::{FromPrimitive, ToPrimitive};
 
 use crate::error::{Result, ErrorKind, Error};
-use crate::binary::{IgniteRead, Value, IgniteWrite};
+use crate::VERSION_1_2_0;
+use crate::binary::{IgniteRead, Value, IgniteWrite, protocol_version};
 
+pub(crate) const DEFAULT_PAGE_SIZE: i32 = 1024;
+
+pub(crate) type RequestMiddleware = Arc<dyn Fn(i16, &mut BytesMut) -> Result<()> + Send + Sync>;
+pub(crate) type ResponseMiddleware = Arc<dyn Fn(i16, &mut Bytes) -> Result<()> + Send + Sync>;
+
+#[derive(Clone)]
 pub struct Configuration {
-    pub address: String,
+    pub addresses: Vec<String>,
     pub username: Option<String>,
     pub password: Option<String>,
+    pub chunk_size: usize,
+    pub default_page_size: i32,
+    pub idle_timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    pub read_timeout: Option<Duration>,
+    pub write_timeout: Option<Duration>,
+    pub max_open_cursors: Option<usize>,
+    pub lossy_utf8: bool,
+    pub default_sql_schema: Option<String>,
+    pub strict_status_codes: bool,
+    pub keep_binary: bool,
+    pub reconnect: bool,
+    pub pool_size: usize,
+    pub partition_awareness: bool,
+    #[cfg(unix)]
+    pub unix_socket: Option<PathBuf>,
+    #[cfg(feature = "tls")]
+    pub tls: Option<TlsConfiguration>,
+    pub(crate) request_middleware: Vec<RequestMiddleware>,
+    pub(crate) response_middleware: Vec<ResponseMiddleware>,
 }
 
-impl Configuration {
-    pub fn default() -> Configuration {
+impl Default for Configuration {
+    fn default() -> Configuration {
         Configuration {
-            address: "127.0.0.1:10800".to_string(),
+            addresses: vec!["127.0.0.1:10800".to_string()],
             username: None,
             password: None,
+            chunk_size: 1000,
+            default_page_size: DEFAULT_PAGE_SIZE,
+            idle_timeout: None,
+            connect_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
+            max_open_cursors: None,
+            lossy_utf8: false,
+            default_sql_schema: None,
+            strict_status_codes: false,
+            keep_binary: false,
+            reconnect: false,
+            pool_size: 4,
+            partition_awareness: false,
+            #[cfg(unix)]
+            unix_socket: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            request_middleware: Vec::new(),
+            response_middleware: Vec::new(),
         }
     }
+}
 
+impl Configuration {
+    /// Replaces the configured addresses with a single one.
     pub fn address(mut self, address: &str) -> Configuration {
-        self.address = address.to_string();
+        self.addresses = vec![address.to_string()];
+
+        self
+    }
+
+    /// Addresses to try in order until one of them accepts the connection.
+    pub fn addresses(mut self, addresses: &[&str]) -> Configuration {
+        self.addresses = addresses.iter().map(|address| address.to_string()).collect();
 
         self
     }
@@ -38,6 +99,201 @@ impl Configuration {
 
         self
     }
+
+    pub fn chunk_size(mut self, chunk_size: usize) -> Configuration {
+        self.chunk_size = chunk_size;
+
+        self
+    }
+
+    pub fn default_page_size(mut self, default_page_size: i32) -> Configuration {
+        self.default_page_size = default_page_size;
+
+        self
+    }
+
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Configuration {
+        self.idle_timeout = Some(idle_timeout);
+
+        self
+    }
+
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Configuration {
+        self.connect_timeout = Some(connect_timeout);
+
+        self
+    }
+
+    /// Operations fail with `ErrorKind::Network` if the server doesn't respond in time.
+    pub fn read_timeout(mut self, read_timeout: Duration) -> Configuration {
+        self.read_timeout = Some(read_timeout);
+
+        self
+    }
+
+    pub fn write_timeout(mut self, write_timeout: Duration) -> Configuration {
+        self.write_timeout = Some(write_timeout);
+
+        self
+    }
+
+    /// New queries fail with `ErrorKind::TooManyCursors` while this many cursors are open.
+    pub fn max_open_cursors(mut self, max_open_cursors: usize) -> Configuration {
+        self.max_open_cursors = Some(max_open_cursors);
+
+        self
+    }
+
+    /// Replace invalid UTF-8 in strings received from the server instead of failing.
+    pub fn lossy_utf8(mut self, lossy_utf8: bool) -> Configuration {
+        self.lossy_utf8 = lossy_utf8;
+
+        self
+    }
+
+    pub fn default_sql_schema(mut self, default_sql_schema: &str) -> Configuration {
+        self.default_sql_schema = Some(default_sql_schema.to_string());
+
+        self
+    }
+
+    /// Report status codes the client doesn't recognize as `ErrorKind::UnknownStatus` rather
+    /// than `ErrorKind::Ignite`. Defaults to `false`.
+    pub fn strict_status_codes(mut self, strict_status_codes: bool) -> Configuration {
+        self.strict_status_codes = strict_status_codes;
+
+        self
+    }
+
+    /// Sets `CacheFlags::KEEP_BINARY` on every cache handle, so the server never deserializes
+    /// objects. Defaults to `false`; use `Cache::without_flags` to opt out for a single cache.
+    pub fn keep_binary(mut self, keep_binary: bool) -> Configuration {
+        self.keep_binary = keep_binary;
+
+        self
+    }
+
+    /// Reconnect when the connection breaks. Read-only cache operations (e.g. `get`) are retried
+    /// once over the new connection; other operations fail, since the request may have reached the
+    /// server before the connection broke. An open transaction is lost with the connection, so its
+    /// operations are not retried. Defaults to `false`: the connection stays closed and later
+    /// operations fail.
+    pub fn reconnect(mut self, reconnect: bool) -> Configuration {
+        self.reconnect = reconnect;
+
+        self
+    }
+
+    /// Number of connections opened by `PooledClient`. Defaults to 4; `Client` always uses one.
+    pub fn pool_size(mut self, pool_size: usize) -> Configuration {
+        self.pool_size = pool_size;
+
+        self
+    }
+
+    /// Send keyed cache operations straight to the primary node of the key. Opens an extra
+    /// connection to each of `addresses`; operations fall back to the main connection for
+    /// servers older than protocol 1.4.0 and for keys whose partition can't be computed.
+    /// Defaults to `false`.
+    pub fn partition_awareness(mut self, partition_awareness: bool) -> Configuration {
+        self.partition_awareness = partition_awareness;
+
+        self
+    }
+
+    /// Connect over a Unix domain socket instead of `addresses`.
+    #[cfg(unix)]
+    pub fn unix_socket<P: AsRef<Path>>(mut self, path: P) -> Configuration {
+        self.unix_socket = Some(path.as_ref().to_path_buf());
+
+        self
+    }
+
+    /// Connect over TLS. Not used for Unix domain sockets.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, tls: TlsConfiguration) -> Configuration {
+        self.tls = Some(tls);
+
+        self
+    }
+
+    /// Adds a hook called with the op code and the full request before it is sent.
+    /// Hooks run in the order they were added; an error aborts the operation.
+    pub fn on_request<F>(mut self, middleware: F) -> Configuration
+        where
+            F: Fn(i16, &mut BytesMut) -> Result<()> + Send + Sync + 'static,
+    {
+        self.request_middleware.push(Arc::new(middleware));
+
+        self
+    }
+
+    /// Adds a hook called with the op code and the full response before it is parsed.
+    /// Hooks run in the order they were added; an error aborts the operation.
+    pub fn on_response<F>(mut self, middleware: F) -> Configuration
+        where
+            F: Fn(i16, &mut Bytes) -> Result<()> + Send + Sync + 'static,
+    {
+        self.response_middleware.push(Arc::new(middleware));
+
+        self
+    }
+
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.addresses.is_empty() {
+            return Err(Error::new(ErrorKind::Configuration, "At least one address is required".to_string()));
+        }
+
+        if self.pool_size == 0 {
+            return Err(Error::new(ErrorKind::Configuration, "Pool size must be positive".to_string()));
+        }
+
+        if self.default_page_size <= 0 {
+            return Err(Error::new(ErrorKind::Configuration, format!("Default page size must be positive: {}", self.default_page_size)));
+        }
+
+        for timeout in [self.connect_timeout, self.read_timeout, self.write_timeout].iter().flatten() {
+            if *timeout == Duration::from_secs(0) {
+                return Err(Error::new(ErrorKind::Configuration, "Timeouts must be non-zero".to_string()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Certificates are PEM files. Without a CA certificate the system trust store is used.
+#[cfg(feature = "tls")]
+#[derive(Default, Clone)]
+pub struct TlsConfiguration {
+    pub ca_cert: Option<PathBuf>,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    pub accept_invalid_certs: bool,
+}
+
+#[cfg(feature = "tls")]
+impl TlsConfiguration {
+    pub fn ca_cert<P: AsRef<Path>>(mut self, path: P) -> TlsConfiguration {
+        self.ca_cert = Some(path.as_ref().to_path_buf());
+
+        self
+    }
+
+    /// Client certificate and its PKCS #8 private key.
+    pub fn client_cert<P: AsRef<Path>>(mut self, cert: P, key: P) -> TlsConfiguration {
+        self.client_cert = Some(cert.as_ref().to_path_buf());
+        self.client_key = Some(key.as_ref().to_path_buf());
+
+        self
+    }
+
+    /// Skip certificate and host name validation. For testing only.
+    pub fn accept_invalid_certs(mut self, accept_invalid_certs: bool) -> TlsConfiguration {
+        self.accept_invalid_certs = accept_invalid_certs;
+
+        self
+    }
 }
 
 #[derive(FromPrimitive, ToPrimitive, IgniteRead, IgniteWrite)]
@@ -99,13 +355,14 @@ impl CacheKeyConfiguration {
     }
 }
 
-#[derive(IgniteRead, IgniteWrite)]
 pub struct QueryField {
     pub(crate) name: String,
     pub(crate) type_name: String,
     pub(crate) key_field: bool,
     pub(crate) not_null: bool,
     pub(crate) default_value: Option<Value>,
+    pub(crate) precision: i32,
+    pub(crate) scale: i32,
 }
 
 impl QueryField {
@@ -116,6 +373,8 @@ impl QueryField {
             key_field,
             not_null,
             default_value: None,
+            precision: -1,
+            scale: -1,
         }
     }
 
@@ -124,6 +383,59 @@ impl QueryField {
 
         self
     }
+
+    /// Sent to servers starting with protocol 1.2.0. Defaults to -1 (not set).
+    pub fn precision(mut self, precision: i32) -> QueryField {
+        self.precision = precision;
+
+        self
+    }
+
+    /// Sent to servers starting with protocol 1.2.0. Defaults to -1 (not set).
+    pub fn scale(mut self, scale: i32) -> QueryField {
+        self.scale = scale;
+
+        self
+    }
+}
+
+// Precision and scale are only exchanged starting with protocol 1.2.0.
+impl IgniteRead for QueryField {
+    fn read(bytes: &mut Bytes) -> Result<QueryField> {
+        let mut field = QueryField {
+            name: String::read(bytes)?,
+            type_name: String::read(bytes)?,
+            key_field: bool::read(bytes)?,
+            not_null: bool::read(bytes)?,
+            default_value: <Option<Value>>::read(bytes)?,
+            precision: -1,
+            scale: -1,
+        };
+
+        if protocol_version() >= VERSION_1_2_0 {
+            field.precision = i32::read(bytes)?;
+            field.scale = i32::read(bytes)?;
+        }
+
+        Ok(field)
+    }
+}
+
+impl IgniteWrite for QueryField {
+    fn write(&self, bytes: &mut BytesMut) -> Result<()> {
+        self.name.write(bytes)?;
+        self.type_name.write(bytes)?;
+        self.key_field.write(bytes)?;
+        self.not_null.write(bytes)?;
+        self.default_value.write(bytes)?;
+
+        if protocol_version() >= VERSION_1_2_0 {
+            self.precision.write(bytes)?;
+            self.scale.write(bytes)?;
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(IgniteRead, IgniteWrite)]
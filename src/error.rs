@@ -1,30 +1,68 @@
+use std::fmt;
+
 use crate::Version;
 
-pub(crate) type Result<T> = core::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
 
 #[derive(PartialEq, Debug)]
 pub enum ErrorKind {
     Network,
     Serde,
     Handshake { server_version: Version, client_version: Version },
+    Authentication,
     Ignite(i32),
+    /// Status code the client doesn't recognize. Only reported in strict mode.
+    UnknownStatus(i32),
+    NotSupported,
+    Configuration,
+    TooManyCursors,
+    InvalidArgument,
+    CursorClosed,
 }
 
 #[derive(PartialEq, Debug)]
 pub struct Error {
-    kind: ErrorKind,
-    message: String,
+    pub(crate) kind: ErrorKind,
+    pub(crate) message: String,
 }
 
 impl Error {
     pub(crate) fn new(kind: ErrorKind, message: String) -> Error {
         Error { kind, message }
     }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.message)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<std::convert::Infallible> for Error {
+    fn from(error: std::convert::Infallible) -> Error {
+        match error {}
+    }
 }
 
 impl From<std::io::Error> for Error {
     fn from(error: std::io::Error) -> Error {
-        Error { kind: ErrorKind::Network, message: error.to_string() }
+        // Socket timeouts report `WouldBlock` on Unix and `TimedOut` on Windows.
+        let message = match error.kind() {
+            std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock => format!("Timed out: {}", error),
+            _ => error.to_string(),
+        };
+
+        Error { kind: ErrorKind::Network, message }
     }
 }
 
@@ -33,3 +71,32 @@ impl From<std::string::FromUtf8Error> for Error {
         Error { kind: ErrorKind::Serde, message: error.to_string() }
     }
 }
+
+// === Tests
+
+#[cfg(test)]
+mod tests {
+    use crate::{VERSION, Version};
+    use crate::error::{Error, ErrorKind};
+
+    #[test]
+    fn test_public_api() {
+        let server_version = Version { major: 1, minor: 4, patch: 0 };
+
+        let error = Error::new(ErrorKind::Handshake { server_version, client_version: VERSION }, "Rejected".to_string());
+
+        match error.kind() {
+            ErrorKind::Handshake { server_version, client_version } => {
+                assert_eq!((server_version.major(), server_version.minor(), server_version.patch()), (1, 4, 0));
+                assert_eq!(client_version.to_string(), "1.7.0");
+            },
+            kind => panic!("Unexpected kind: {:?}", kind),
+        }
+
+        assert_eq!(error.message(), "Rejected");
+
+        let error: Box<dyn std::error::Error> = Box::new(Error::new(ErrorKind::Network, "Refused".to_string()));
+
+        assert_eq!(error.to_string(), "Network: Refused");
+    }
+}
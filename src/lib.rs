@@ -2,47 +2,278 @@
 extern crate num_derive;
 #[macro_use]
 extern crate binary_derive;
+extern crate self as ignite_client;
 
 mod configuration;
 mod binary;
 mod cache;
 mod error;
 mod network;
+mod query;
+mod cluster;
+mod transaction;
+mod pool;
+mod affinity;
+#[cfg(feature = "async")]
+mod async_client;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
 
-use std::net::TcpStream;
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::sync::atomic::Ordering;
+use std::net::TcpStream;
+use std::fmt;
+use std::time::Duration;
 
-use configuration::{Configuration, CacheConfiguration};
-use cache::Cache;
-use error::Result;
 use network::Tcp;
-use binary::{IgniteWrite, IgniteRead, Binary};
-
-#[derive(PartialEq, Debug)]
+use binary::{IgniteWrite, IgniteRead};
+
+pub use configuration::{Configuration, CacheConfiguration, AtomicityMode, CacheMode, QueryEntity, QueryField};
+#[cfg(feature = "tls")]
+pub use configuration::TlsConfiguration;
+pub use cache::{Cache, TypedCache, CacheFlags, PeekMode, cache_id};
+pub use error::{ErrorKind, Error, Result};
+pub use binary::{Binary, Value, BinaryObject, BinaryObjectBuilder, ValueVisitor};
+pub use network::ConnectionState;
+pub use cluster::ClusterState;
+pub use transaction::{Transaction, TxConcurrency, TxIsolation};
+pub use pool::{PooledClient, PooledCache};
+pub use query::{SqlFieldsQuery, StatementType, FieldsCursor, Row, QueryResult, ScanQuery, ScanCursor, SqlQuery, SqlCursor, FromRow, RowReader};
+pub use binary_derive::FromRow;
+#[cfg(feature = "async")]
+pub use async_client::{AsyncClient, AsyncCache};
+
+#[derive(PartialEq, PartialOrd, Clone, Copy, Debug)]
 pub struct Version {
     major: i16,
     minor: i16,
     patch: i16,
 }
 
-pub const VERSION: Version = Version { major: 1, minor: 1, patch: 0 };
+#[derive(PartialEq, PartialOrd, Clone, Copy, Debug)]
+pub struct AffinityTopologyVersion {
+    major: i64,
+    minor: i32,
+}
+
+impl Version {
+    pub fn major(&self) -> i16 {
+        self.major
+    }
+
+    pub fn minor(&self) -> i16 {
+        self.minor
+    }
+
+    pub fn patch(&self) -> i16 {
+        self.patch
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl AffinityTopologyVersion {
+    pub fn major(&self) -> i64 {
+        self.major
+    }
+
+    pub fn minor(&self) -> i32 {
+        self.minor
+    }
+}
+
+/// Newest protocol version the client proposes. Servers that only speak an older version,
+/// down to 1.1.0, get the handshake again with their version.
+pub const VERSION: Version = VERSION_1_7_0;
+
+const VERSION_1_1_0: Version = Version { major: 1, minor: 1, patch: 0 };
+const VERSION_1_2_0: Version = Version { major: 1, minor: 2, patch: 0 };
+const VERSION_1_4_0: Version = Version { major: 1, minor: 4, patch: 0 };
+const VERSION_1_5_0: Version = Version { major: 1, minor: 5, patch: 0 };
+const VERSION_1_6_0: Version = Version { major: 1, minor: 6, patch: 0 };
+const VERSION_1_7_0: Version = Version { major: 1, minor: 7, patch: 0 };
+
+#[derive(Clone, Copy, Debug)]
+pub enum Feature {
+    PartitionAwareness,
+    Transactions,
+    ExpiryPolicy,
+    UserAttributes,
+    ExecuteTaskByName,
+    ClusterApi,
+    ClusterGroups,
+}
+
+/// Features supported by the connected server. Some are implied by the negotiated
+/// protocol version, others come from the bitmask exchanged starting with protocol 1.7.0.
+#[derive(PartialEq, Clone, Debug)]
+pub struct FeatureSet {
+    version: Version,
+    bitmask: Vec<u8>,
+}
+
+impl FeatureSet {
+    pub(crate) fn new(version: Version, bitmask: Vec<u8>) -> FeatureSet {
+        FeatureSet { version, bitmask }
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn supports(&self, feature: Feature) -> bool {
+        match feature {
+            Feature::PartitionAwareness => self.version >= VERSION_1_4_0,
+            Feature::Transactions => self.version >= VERSION_1_5_0,
+            Feature::ExpiryPolicy => self.version >= VERSION_1_6_0,
+            Feature::UserAttributes => self.bit(0),
+            Feature::ExecuteTaskByName => self.bit(1),
+            Feature::ClusterApi => self.bit(2),
+            Feature::ClusterGroups => self.bit(4),
+        }
+    }
+
+    pub fn supports_partition_awareness(&self) -> bool {
+        self.supports(Feature::PartitionAwareness)
+    }
+
+    pub fn supports_transactions(&self) -> bool {
+        self.supports(Feature::Transactions)
+    }
+
+    pub fn supports_expiry_policy(&self) -> bool {
+        self.supports(Feature::ExpiryPolicy)
+    }
+
+    pub fn supports_cluster_api(&self) -> bool {
+        self.supports(Feature::ClusterApi)
+    }
+
+    pub fn require(&self, feature: Feature) -> Result<()> {
+        if self.supports(feature) {
+            Ok(())
+        }
+        else {
+            let Version { major, minor, patch } = self.version;
+
+            Err(Error::new(
+                ErrorKind::NotSupported,
+                format!("{:?} is not supported by server (protocol version {}.{}.{}).", feature, major, minor, patch)
+            ))
+        }
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        self.bitmask.get(index / 8)
+            .map(|byte| byte & (1 << (index % 8)) != 0)
+            .unwrap_or(false)
+    }
+}
 
 pub struct Client {
     tcp: Rc<RefCell<Tcp>>,
 }
 
+/// Snapshot of the client state for troubleshooting, see `Client::debug_state`.
+#[derive(Clone, Debug)]
+pub struct ClientDebug {
+    pub addresses: Vec<String>,
+    /// Address of the current connection; `None` for streams passed to `Client::from_stream`.
+    pub endpoint: Option<String>,
+    pub state: ConnectionState,
+    pub features: FeatureSet,
+    pub affinity_version: Option<AffinityTopologyVersion>,
+    pub open_cursors: usize,
+    pub transaction_id: Option<i32>,
+    /// Always 1 for `Client`, see `PooledClient::debug_state`.
+    pub pool_size: usize,
+    pub connections_in_use: usize,
+    /// Nodes with a connection for keyed operations, 0 unless `Configuration::partition_awareness` is set.
+    pub partition_aware_nodes: usize,
+    pub partition_map_version: Option<AffinityTopologyVersion>,
+    /// Time since the partition map was last fetched.
+    pub partition_map_age: Option<Duration>,
+}
+
 impl Client {
     pub fn start(configuration: Configuration) -> Result<Client> {
-        let stream = TcpStream::connect(&configuration.address)?;
+        configuration.validate()?;
+
+        let tcp = Rc::new(RefCell::new(Tcp::connect(configuration)?));
 
-        let tcp = Rc::new(RefCell::new(Tcp { stream }));
+        Ok(Client { tcp })
+    }
 
-        tcp.borrow_mut().handshake(&configuration)?;
+    /// Starts a client over a stream the caller has already connected. Reconnects still
+    /// use `Configuration::addresses`. The stream can't be reopened to retry the handshake,
+    /// so it uses protocol 1.1.0, without the features of newer versions such as transactions.
+    pub fn from_stream(stream: TcpStream, configuration: Configuration) -> Result<Client> {
+        configuration.validate()?;
+
+        network::set_timeouts(&stream, &configuration)?;
+
+        let tcp = Rc::new(RefCell::new(Tcp::connect_with(Box::new(stream), configuration)?));
 
         Ok(Client { tcp })
     }
 
+    /// Negotiated in the handshake of the current connection. Operations that need a missing
+    /// feature fail with `ErrorKind::NotSupported` before anything is sent.
+    pub fn server_features(&self) -> FeatureSet {
+        self.tcp.borrow().features.clone()
+    }
+
+    pub fn affinity_version(&self) -> Option<AffinityTopologyVersion> {
+        self.tcp.borrow().affinity_version
+    }
+
+    /// The client is busy while it reconnects, so this never returns `Reconnecting`; see
+    /// `PooledClient::connection_state`.
+    pub fn connection_state(&self) -> ConnectionState {
+        self.tcp.borrow().state
+    }
+
+    /// Major topology version from the last response that reported it, or from the last partition
+    /// map fetch if that is newer. 0 if neither did yet.
+    pub fn topology_version(&self) -> i64 {
+        let tcp = self.tcp.borrow();
+
+        let map_version = tcp.routing.as_ref().and_then(|routing| routing.version());
+
+        let version = match (tcp.affinity_version, map_version) {
+            (Some(version), Some(map_version)) if map_version > version => Some(map_version),
+            (None, map_version) => map_version,
+            (version, _) => version,
+        };
+
+        version.map_or(0, |version| version.major)
+    }
+
+    /// Doesn't send anything to the server.
+    pub fn debug_state(&self) -> ClientDebug {
+        let tcp = self.tcp.borrow();
+
+        ClientDebug {
+            addresses: tcp.configuration.addresses.clone(),
+            endpoint: tcp.endpoint.clone(),
+            state: tcp.state,
+            features: tcp.features.clone(),
+            affinity_version: tcp.affinity_version,
+            open_cursors: tcp.open_cursors.load(Ordering::Relaxed),
+            transaction_id: tcp.tx_id,
+            pool_size: 1,
+            connections_in_use: 0,
+            partition_aware_nodes: tcp.routing.as_ref().map_or(0, |routing| routing.node_count()),
+            partition_map_version: tcp.routing.as_ref().and_then(|routing| routing.version()),
+            partition_map_age: tcp.routing.as_ref().and_then(|routing| routing.age()),
+        }
+    }
+
     pub fn binary(&self) -> Binary {
         Binary::new(self.tcp.clone())
     }
@@ -57,6 +288,11 @@ impl Client {
         )
     }
 
+    /// Handles for all caches, fetched in a single request.
+    pub fn caches(&self) -> Result<Vec<Cache>> {
+        Ok(self.cache_names()?.iter().map(|name| self.cache(name)).collect())
+    }
+
     pub fn create_cache(&self, name: &str) -> Result<Cache> {
         self.tcp.borrow_mut().execute(
             1051,
@@ -105,6 +341,67 @@ impl Client {
         Ok(Cache::new(configuration.name, self.tcp.clone()))
     }
 
+    /// Runs a statement that is not tied to a cache (e.g. DDL). Schema defaults to
+    /// `Configuration::default_sql_schema`, or `PUBLIC` if that is not set.
+    pub fn sql(&self, schema: Option<&str>, statement: &str, args: &[Value]) -> Result<QueryResult> {
+        let mut query = SqlFieldsQuery::new(statement)
+            .args(args)
+            .include_field_names(true)
+            .with_defaults(&self.tcp.borrow().configuration);
+
+        if let Some(schema) = schema {
+            query = query.schema(schema);
+        }
+
+        let query = query.default_schema(Some("PUBLIC"));
+
+        let flags = if self.tcp.borrow().configuration.keep_binary { CacheFlags::KEEP_BINARY } else { CacheFlags::empty() };
+
+        let permit = self.tcp.borrow().acquire_cursor()?;
+
+        let cursor = self.tcp.borrow_mut().execute(
+            2004,
+            |request| {
+                0i32.write(request)?; // No cache ID.
+                (flags.bits() as i8).write(request)?;
+
+                query.write(request)
+            },
+            |response| {
+                FieldsCursor::read(self.tcp.clone(), true, response)
+            }
+        )?;
+
+        cursor.with_permit(permit).collect_result()
+    }
+
+    pub fn cluster_state(&self) -> Result<ClusterState> {
+        self.tcp.borrow().features.require(Feature::ClusterApi)?;
+
+        self.tcp.borrow_mut().execute(
+            5000,
+            |_| { Ok(()) },
+            ClusterState::read
+        )
+    }
+
+    pub fn set_cluster_state(&self, state: ClusterState) -> Result<()> {
+        self.tcp.borrow().features.require(Feature::ClusterApi)?;
+
+        self.tcp.borrow_mut().execute(
+            5001,
+            |request| {
+                state.write(request)
+            },
+            |_| { Ok(()) }
+        )
+    }
+
+    /// Starts a transaction on the client connection. Only one can be open at a time.
+    pub fn start_transaction(&self, concurrency: TxConcurrency, isolation: TxIsolation, timeout: Duration, label: Option<&str>) -> Result<Transaction> {
+        Transaction::start(self.tcp.clone(), concurrency, isolation, timeout, label)
+    }
+
     pub fn cache(&self, name: &str) -> Cache {
         Cache::new(name.to_string(), self.tcp.clone())
     }
@@ -114,11 +411,25 @@ impl Client {
 
 #[cfg(test)]
 mod tests {
-    use crate::{Configuration, Client};
-    use crate::binary::Value;
-    use crate::cache::{Cache, PeekMode};
+    use std::thread;
+    use std::convert::TryFrom;
+    use std::time::Duration;
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use std::net::TcpStream;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use bytes::{Bytes, BytesMut};
+
+    use crate::{Configuration, Client, ConnectionState, ClusterState, BinaryObjectBuilder, TxConcurrency, TxIsolation, FeatureSet, Feature, SqlFieldsQuery, StatementType, ScanQuery, ScanCursor, FromRow, Row, VERSION, VERSION_1_1_0, VERSION_1_2_0, VERSION_1_4_0};
+    use crate::error::{Result, ErrorKind, Error};
+    use crate::binary::{Value, IgniteRead, IgniteWrite, with_protocol_version};
+    use crate::network::tests::{tcp_with_responses, server};
+    use crate::cache::{Cache, PeekMode, cache_id};
+    use crate::mock::MockServer;
     use uuid::Uuid;
-    use crate::configuration::CacheConfiguration;
+    use crate::configuration::{CacheConfiguration, AtomicityMode, CacheMode, QueryEntity, QueryField};
 
     #[test]
     fn test_put_get_i8() {
@@ -221,6 +532,81 @@ mod tests {
         assert_eq!(cache.get_all(keys.as_slice()), Ok(entries));
     }
 
+    #[test]
+    fn test_get_all_chunked() {
+        let get_all_requests = Arc::new(AtomicUsize::new(0));
+        let counter = get_all_requests.clone();
+
+        let config = Configuration::default().chunk_size(1000).on_request(move |op, _| {
+            if op == 1003 {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+
+            Ok(())
+        });
+
+        let cache = cache_with(config);
+
+        let entries: Vec<(Value, Value)> = (0 .. 5000)
+            .map(|i| (Value::I32(i), Value::I32(i)))
+            .collect();
+
+        let keys: Vec<Value> = (0 .. 5000)
+            .map(Value::I32)
+            .collect();
+
+        assert_eq!(cache.put_all(entries.as_slice()), Ok(()));
+        assert_eq!(cache.contains_keys(keys.as_slice()), Ok(true));
+
+        let result = cache.get_all(keys.as_slice())
+            .expect("Failed to execute get_all() operation.");
+
+        assert_eq!(result.len(), 5000);
+        assert_eq!(get_all_requests.load(Ordering::Relaxed), 5);
+
+        for (i, (key, value)) in result.into_iter().enumerate() {
+            match &key {
+                Value::I32(k) => assert_eq!(*k as usize / 1000, i / 1000), // Chunk order is preserved.
+                _ => panic!("Unexpected key: {:?}", key),
+            }
+
+            assert_eq!(value, Some(key));
+        }
+
+        assert_eq!(cache.remove_keys(keys.as_slice()), Ok(()));
+        assert_eq!(cache.size(&[]), Ok(0));
+    }
+
+    #[test]
+    fn test_get_all_iter() {
+        let cache = cache_with(Configuration::default().chunk_size(1000));
+
+        let entries: Vec<(Value, Value)> = (0 .. 10000)
+            .map(|i| (Value::I32(i), Value::I32(i * 2)))
+            .collect();
+
+        let keys: Vec<Value> = (0 .. 10000)
+            .map(Value::I32)
+            .collect();
+
+        assert_eq!(cache.put_all(entries.as_slice()), Ok(()));
+
+        let mut count = 0;
+        let mut sum = 0i64;
+
+        for entry in cache.get_all_iter(keys.as_slice()) {
+            match entry.expect("Failed to fetch chunk.") {
+                (Value::I32(k), Some(Value::I32(v))) if v == k * 2 => sum += v as i64,
+                entry => panic!("Unexpected entry: {:?}", entry),
+            }
+
+            count += 1;
+        }
+
+        assert_eq!(count, 10000);
+        assert_eq!(sum, 9999 * 10000);
+    }
+
     #[test]
     fn test_put_all() {
         let cache = cache();
@@ -289,6 +675,71 @@ mod tests {
         assert_eq!(cache.get(&Value::I32(42)), Ok(Some(Value::I32(1))));
     }
 
+    #[test]
+    fn test_size_estimate() {
+        let cache = cache();
+
+        assert_eq!(cache.is_empty(), Ok(true));
+
+        for i in 0 .. 10 {
+            assert_eq!(cache.put(&Value::I32(i), &Value::I32(i)), Ok(()));
+        }
+
+        // On a small cache without backups or near caches the estimate is exact.
+        // On large, rebalancing caches it may diverge from `size`.
+        assert_eq!(cache.size(&[]), Ok(10));
+        assert_eq!(cache.len(), Ok(10));
+        assert_eq!(cache.size_estimate(), Ok(10));
+    }
+
+    #[test]
+    fn test_put_if_absent_all() {
+        let cache = tx_cache();
+
+        assert_eq!(cache.put(&Value::I32(1), &Value::I32(1)), Ok(()));
+        assert_eq!(cache.put(&Value::I32(3), &Value::I32(3)), Ok(()));
+
+        let entries: Vec<(Value, Value)> = (0 .. 4).map(|i| (Value::I32(i), Value::I32(i * 10))).collect();
+
+        assert_eq!(cache.put_if_absent_all(&entries), Ok(vec![
+            (Value::I32(0), true),
+            (Value::I32(1), false),
+            (Value::I32(2), true),
+            (Value::I32(3), false),
+        ]));
+
+        assert_eq!(cache.get(&Value::I32(1)), Ok(Some(Value::I32(1))));
+        assert_eq!(cache.get(&Value::I32(2)), Ok(Some(Value::I32(20))));
+    }
+
+    #[test]
+    fn test_get_and_put_all_if_absent() {
+        let cache = tx_cache();
+
+        for i in 0 .. 5 {
+            assert_eq!(cache.put(&Value::I32(i), &Value::I32(i)), Ok(()));
+        }
+
+        let entries: Vec<(Value, Value)> = (0 .. 10).map(|i| (Value::I32(i), Value::I32(i * 10))).collect();
+
+        let result = cache.get_and_put_all_if_absent(&entries).expect("Failed to put.");
+
+        for (i, (key, value)) in result.into_iter().enumerate() {
+            let i = i as i32;
+
+            assert_eq!(key, Value::I32(i));
+
+            if i < 5 {
+                assert_eq!(value, Some(Value::I32(i)));
+                assert_eq!(cache.get(&key), Ok(Some(Value::I32(i))));
+            }
+            else {
+                assert_eq!(value, None);
+                assert_eq!(cache.get(&key), Ok(Some(Value::I32(i * 10))));
+            }
+        }
+    }
+
     #[test]
     fn test_replace() {
         let cache = cache();
@@ -317,6 +768,36 @@ mod tests {
         assert_eq!(cache.get(&Value::I32(42)), Ok(Some(Value::I32(2))));
     }
 
+    #[test]
+    fn test_compute() {
+        let cache = tx_cache();
+
+        let threads: Vec<_> = (0 .. 2)
+            .map(|_| {
+                thread::spawn(|| {
+                    let cache = client().cache("test-tx-cache");
+
+                    for _ in 0 .. 100 {
+                        cache.compute(&Value::I32(42), |value| {
+                            match value {
+                                Some(Value::I64(v)) => Some(Value::I64(v + 1)),
+                                _ => Some(Value::I64(1)),
+                            }
+                        }).expect("Failed to compute value.");
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().expect("Failed to join thread.");
+        }
+
+        assert_eq!(cache.get(&Value::I32(42)), Ok(Some(Value::I64(200))));
+        assert_eq!(cache.compute(&Value::I32(42), |_| None), Ok(None));
+        assert_eq!(cache.get(&Value::I32(42)), Ok(None));
+    }
+
     #[test]
     fn test_contains_key() {
         let cache = cache();
@@ -347,6 +828,19 @@ mod tests {
         assert_eq!(cache.contains_keys(keys.as_slice()), Ok(true));
     }
 
+    #[test]
+    fn test_clear_counting() {
+        let cache = cache();
+
+        for i in 0 .. 10 {
+            assert_eq!(cache.put(&Value::I32(i), &Value::I32(i)), Ok(()));
+        }
+
+        assert_eq!(cache.clear_counting(), Ok(10));
+        assert_eq!(cache.size(&[]), Ok(0));
+        assert_eq!(cache.clear_counting(), Ok(0));
+    }
+
     #[test]
     fn test_clear_key() {
         let cache = cache();
@@ -498,6 +992,104 @@ mod tests {
         assert_eq!(cache.size(&[PeekMode::Primary]), Ok(2));
     }
 
+    #[test]
+    fn test_size_long() {
+        let cache = cache();
+
+        for i in 0 .. 100 {
+            assert_eq!(cache.put(&Value::I32(i), &Value::I32(i)), Ok(()));
+        }
+
+        assert_eq!(cache.size_long(&[], None), Ok(100));
+
+        // Default number of partitions.
+        let sizes: Vec<i64> = (0 .. 1024)
+            .map(|partition| cache.size_long(&[PeekMode::Primary], Some(partition)).expect("Failed to get size."))
+            .collect();
+
+        assert!(sizes.iter().all(|size| *size < 100));
+        assert_eq!(sizes.iter().sum::<i64>(), 100);
+
+        assert_eq!(cache.size_long(&[PeekMode::Backup], Some(0)).err().unwrap().kind, ErrorKind::NotSupported);
+        assert!(cache.size_long(&[], Some(-1)).is_err());
+    }
+
+    #[test]
+    fn test_query_sql_fields() {
+        let cache = cache();
+
+        let execute = |sql: &str, args: &[Value]| {
+            cache.query_sql_fields(sql, args).expect("Failed to execute query.").count()
+        };
+
+        execute("DROP TABLE IF EXISTS sql_fields_test", &[]);
+        execute("CREATE TABLE sql_fields_test (id INT PRIMARY KEY, name VARCHAR)", &[]);
+
+        for i in 0 .. 3 {
+            execute("INSERT INTO sql_fields_test (id, name) VALUES (?, ?)", &[Value::I32(i), Value::String(format!("name-{}", i))]);
+        }
+
+        let cursor = cache.query_sql_fields("SELECT id, name FROM sql_fields_test ORDER BY id", &[]).expect("Failed to execute query.");
+
+        assert_eq!(cursor.columns(), &["ID".to_string(), "NAME".to_string()]);
+
+        let rows: Result<Vec<Row>> = cursor.collect();
+
+        assert_eq!(rows, Ok((0 .. 3).map(|i| vec![Some(Value::I32(i)), Some(Value::String(format!("name-{}", i)))]).collect()));
+
+        execute("DROP TABLE sql_fields_test", &[]);
+    }
+
+    #[test]
+    fn test_query_fields_into() {
+        #[derive(FromRow, PartialEq, Debug)]
+        struct Range {
+            x: i64,
+            #[sql(column = "square")]
+            square: i64,
+        }
+
+        let cache = cache();
+
+        let rows: Result<Vec<Range>> = cache.query_fields_into(
+            SqlFieldsQuery::new("SELECT x, x * x AS square FROM system_range(1, 3)")
+        ).unwrap().collect();
+
+        assert_eq!(rows, Ok(vec![
+            Range { x: 1, square: 1 },
+            Range { x: 2, square: 4 },
+            Range { x: 3, square: 9 },
+        ]));
+    }
+
+    #[test]
+    fn test_query_fields_for_each() {
+        let cache = cache();
+
+        let mut sum = 0;
+        let mut rows = 0;
+
+        cache.query_fields_for_each("SELECT x FROM system_range(1, ?)", &[Value::I32(3000)], |row| {
+            match row.as_slice() {
+                [Some(Value::I64(x))] => sum += x,
+                _ => panic!("Unexpected row: {:?}", row),
+            }
+
+            rows += 1;
+
+            Ok(())
+        }).expect("Failed to execute query.");
+
+        assert_eq!(rows, 3000);
+        assert_eq!(sum, 3000 * 3001 / 2);
+
+        let result = cache.query_fields_for_each("SELECT x FROM system_range(1, 3000)", &[], |_| {
+            Err(Error::new(ErrorKind::Serde, "Callback failure.".to_string()))
+        });
+
+        assert_eq!(result.unwrap_err().message, "Callback failure.".to_string());
+    }
+
     #[test]
     fn test_cache_names() {
         let client = client();
@@ -514,6 +1106,51 @@ mod tests {
         assert_eq!(names, expected_names);
     }
 
+    #[test]
+    fn test_caches() {
+        let server = MockServer::start();
+
+        let mut names = BytesMut::new();
+
+        vec!["first".to_string(), "second".to_string()].write(&mut names).unwrap();
+
+        server.respond(1050, names.to_vec());
+
+        let client = Client::start(server.configuration()).unwrap();
+
+        let caches = client.caches().expect("Failed to get caches.");
+
+        assert_eq!(caches.iter().map(Cache::cache_id).collect::<Vec<i32>>(), vec![cache_id("first"), cache_id("second")]);
+
+        for (i, cache) in caches.iter().enumerate() {
+            assert_eq!(cache.put(&Value::I32(1), &Value::I32(i as i32)), Ok(()));
+        }
+
+        assert_eq!(client.cache("first").get(&Value::I32(1)), Ok(Some(Value::I32(0))));
+        assert_eq!(caches[1].get(&Value::I32(1)), Ok(Some(Value::I32(1))));
+    }
+
+    #[test]
+    fn test_debug_state() {
+        let server = MockServer::start();
+
+        let bad = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+
+        let client = Client::start(Configuration::default().addresses(&[&bad, server.address()])).unwrap();
+
+        let state = client.debug_state();
+
+        assert_eq!(state.addresses, vec![bad, server.address().to_string()]);
+        assert_eq!(state.endpoint, Some(server.address().to_string()));
+        assert_eq!(state.state, ConnectionState::Connected);
+        assert_eq!(state.features.version(), VERSION);
+        assert_eq!(state.affinity_version, None);
+        assert_eq!(state.open_cursors, 0);
+        assert_eq!(state.transaction_id, None);
+        assert_eq!(state.pool_size, 1);
+        assert_eq!(state.connections_in_use, 0);
+    }
+
     #[test]
     fn test_create_cache() {
         let client = client();
@@ -610,6 +1247,28 @@ mod tests {
             .contains(&"new-cache".to_string()));
     }
 
+    #[test]
+    fn test_create_cache_with_replicated_mode() {
+        let client = client();
+
+        let configuration = CacheConfiguration::default("replicated-cache")
+            .backups(2)
+            .mode(CacheMode::Replicated);
+
+        let cache = client.create_cache_with_configuration(configuration)
+            .expect("Failed to create cache.");
+
+        let config = cache.configuration()
+            .expect("Failed to get cache configuration.");
+
+        assert_eq!(config.name, "replicated-cache".to_string());
+        assert_eq!(config.backups, 2);
+        assert!(matches!(config.mode, CacheMode::Replicated));
+
+        cache.destroy()
+            .expect("Failed to destroy cache.");
+    }
+
     #[test]
     fn test_get_configuration() {
         let cache = cache();
@@ -622,17 +1281,460 @@ mod tests {
         // TODO: Check other parameters.
     }
 
+    #[test]
+    fn test_server_features() {
+        let mut handshake = BytesMut::new();
+
+        1i8.write(&mut handshake).unwrap();
+        Value::I8Vec(vec![0b0001_0001]).write(&mut handshake).unwrap(); // User attributes and cluster groups.
+        Uuid::nil().write(&mut handshake).unwrap();
+
+        let address = server(vec![vec![handshake.to_vec()]]);
+
+        let client = Client::start(Configuration::default().address(&address)).unwrap();
+
+        let features = client.server_features();
+
+        assert_eq!(features.version(), VERSION);
+        assert!(features.supports_partition_awareness());
+        assert!(features.supports_transactions());
+        assert!(features.supports(Feature::UserAttributes));
+        assert!(features.supports(Feature::ClusterGroups));
+        assert!(!features.supports_cluster_api());
+
+        // Rejected before anything is sent.
+        assert_eq!(client.cluster_state().unwrap_err().kind, ErrorKind::NotSupported);
+    }
+
+    #[test]
+    fn test_query_field_versions() {
+        let field = QueryField::new("price", "java.math.BigDecimal", false, false).precision(10).scale(2);
+
+        let write = |version| {
+            let mut bytes = BytesMut::new();
+
+            with_protocol_version(version, || field.write(&mut bytes)).unwrap();
+
+            bytes.freeze()
+        };
+
+        let old = write(VERSION_1_1_0);
+        let new = write(VERSION_1_2_0);
+
+        assert_eq!(new.len(), old.len() + 8);
+
+        let read = with_protocol_version(VERSION_1_2_0, || QueryField::read(&mut new.clone())).unwrap();
+
+        assert_eq!((read.precision, read.scale), (10, 2));
+
+        let read = with_protocol_version(VERSION_1_1_0, || QueryField::read(&mut old.clone())).unwrap();
+
+        assert_eq!((read.precision, read.scale), (-1, -1));
+    }
+
+    #[test]
+    fn test_feature_set() {
+        let features = FeatureSet::new(VERSION_1_4_0, vec![0b0000_0101]);
+
+        assert!(features.supports_partition_awareness());
+        assert!(!features.supports_transactions());
+        assert!(features.supports(Feature::UserAttributes));
+        assert!(!features.supports(Feature::ExecuteTaskByName));
+        assert!(features.supports_cluster_api());
+        assert!(!features.supports(Feature::ClusterGroups));
+
+        assert!(features.require(Feature::PartitionAwareness).is_ok());
+        assert_eq!(features.require(Feature::Transactions).unwrap_err().kind, ErrorKind::NotSupported);
+    }
+
+    #[test]
+    fn test_type_name() {
+        let binary = client().binary();
+
+        assert_eq!(binary.type_name(654321), Ok(None));
+        assert_eq!(binary.register_type_name(654321, "org.test.RegisteredType"), Ok(()));
+        assert_eq!(binary.type_name(654321), Ok(Some("org.test.RegisteredType".to_string())));
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let client = client();
+
+        let binary = client.binary();
+
+        assert_eq!(binary.register_type_name(123456, "org.test.ReconnectType"), Ok(()));
+
+        client.tcp.borrow_mut().reconnect().expect("Failed to reconnect.");
+
+        assert_eq!(binary.type_name(123456), Ok(Some("org.test.ReconnectType".to_string())));
+        assert_eq!(client.tcp.borrow().type_names.get(&123456), Some(&"org.test.ReconnectType".to_string()));
+    }
+
+    #[test]
+    #[ignore = "Requires a multi-node cluster."]
+    fn test_scan_local() {
+        let cache = cache();
+
+        let entries: Vec<(Value, Value)> = (0 .. 1000).map(|i| (Value::I32(i), Value::I32(i))).collect();
+
+        assert_eq!(cache.put_all(&entries), Ok(()));
+
+        let count = |query: ScanQuery| cache.scan(query).expect("Failed to scan.").count();
+
+        let local = count(ScanQuery::new().local(true));
+
+        assert!(local > 0);
+        assert!(local < count(ScanQuery::new()));
+    }
+
+    #[test]
+    fn test_transaction_rollback() {
+        let client = client();
+
+        let configuration = CacheConfiguration::default("tx-cache").atomicity_mode(AtomicityMode::Transactional);
+
+        let cache = client.get_or_create_cache_with_configuration(configuration)
+            .expect("Failed to create cache.");
+
+        assert_eq!(cache.remove_all(), Ok(()));
+
+        let tx = client.start_transaction(TxConcurrency::Pessimistic, TxIsolation::RepeatableRead, Duration::from_secs(5), Some("rollback-test"))
+            .expect("Failed to start transaction.");
+
+        assert!(client.start_transaction(TxConcurrency::Optimistic, TxIsolation::Serializable, Duration::from_secs(5), None).is_err());
+
+        assert_eq!(cache.put(&Value::I32(1), &Value::I32(1)), Ok(()));
+        assert_eq!(cache.get(&Value::I32(1)), Ok(Some(Value::I32(1))));
+        assert_eq!(tx.rollback(), Ok(()));
+        assert_eq!(cache.get(&Value::I32(1)), Ok(None));
+
+        let tx = client.start_transaction(TxConcurrency::Optimistic, TxIsolation::Serializable, Duration::from_secs(5), None)
+            .expect("Failed to start transaction.");
+
+        assert_eq!(cache.put(&Value::I32(2), &Value::I32(2)), Ok(()));
+        assert_eq!(tx.commit(), Ok(()));
+        assert_eq!(cache.get(&Value::I32(2)), Ok(Some(Value::I32(2))));
+
+        cache.destroy()
+            .expect("Failed to destroy cache.");
+    }
+
+    #[test]
+    fn test_cluster_state() {
+        let client = client();
+        let cache = cache();
+
+        assert_eq!(client.cluster_state(), Ok(ClusterState::Active));
+        assert_eq!(client.set_cluster_state(ClusterState::ActiveReadOnly), Ok(()));
+        assert_eq!(client.cluster_state(), Ok(ClusterState::ActiveReadOnly));
+
+        let err = cache.put(&Value::I32(1), &Value::I32(1)).unwrap_err();
+
+        assert_eq!(err.kind, ErrorKind::Ignite(1));
+        assert!(err.message.contains("read-only"), "Unexpected message: {}", err.message);
+
+        assert_eq!(client.set_cluster_state(ClusterState::Active), Ok(()));
+        assert_eq!(cache.put(&Value::I32(1), &Value::I32(1)), Ok(()));
+    }
+
+    #[test]
+    fn test_tuple_keys() {
+        let cache = cache();
+
+        let pair = Value::from((Value::I32(1), Value::String("x".to_string())));
+        let triple = Value::from((Value::I32(1), Value::String("x".to_string()), Value::Bool(true)));
+
+        assert_eq!(cache.put(&pair, &Value::I32(2)), Ok(()));
+        assert_eq!(cache.put(&triple, &Value::I32(3)), Ok(()));
+
+        assert_eq!(cache.get(&Value::from((Value::I32(1), Value::String("x".to_string())))), Ok(Some(Value::I32(2))));
+        assert_eq!(cache.get(&triple), Ok(Some(Value::I32(3))));
+
+        let keys: Result<Vec<(Value, Value)>> = cache.query_scan(10).expect("Failed to scan.").collect();
+
+        let keys: Vec<Value> = keys.expect("Failed to scan.").into_iter().map(|(key, _)| key).collect();
+
+        assert!(keys.contains(&pair) && keys.contains(&triple));
+        assert_eq!(<(Value, Value)>::try_from(pair), Ok((Value::I32(1), Value::String("x".to_string()))));
+    }
+
+    #[test]
+    fn test_binary_object_builder() {
+        let cache = cache();
+
+        let object = BinaryObjectBuilder::new("Person")
+            .set_field("id", Value::I32(1))
+            .set_field("name", Value::String("John".to_string()))
+            .build()
+            .expect("Failed to build object.");
+
+        assert_eq!(cache.put(&Value::I32(1), &Value::BinaryObject(object)), Ok(()));
+
+        match cache.get(&Value::I32(1)) {
+            Ok(Some(Value::BinaryObject(object))) => {
+                assert_eq!(object.field("id"), Ok(Some(Value::I32(1))));
+                assert_eq!(object.field("name"), Ok(Some(Value::String("John".to_string()))));
+            },
+            value => panic!("Unexpected value: {:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_query_scan() {
+        let cache = cache();
+
+        let entries: Vec<(Value, Value)> = (0 .. 1000).map(|i| (Value::I32(i), Value::I32(i))).collect();
+
+        assert_eq!(cache.put_all(&entries), Ok(()));
+
+        let scanned: Result<Vec<(Value, Value)>> = cache.query_scan(64).expect("Failed to scan.").collect();
+
+        let mut scanned = scanned.expect("Failed to fetch page.");
+
+        scanned.sort_by_key(|entry| match entry.0 {
+            Value::I32(key) => key,
+            _ => panic!("Unexpected entry: {:?}", entry),
+        });
+
+        assert_eq!(scanned, entries);
+        assert!(cache.query_scan(0).is_err());
+    }
+
+    #[test]
+    fn test_query_sql() {
+        let client = client();
+
+        let entity = QueryEntity::new("java.lang.Integer", "java.lang.String", "Names")
+            .key_field_name("id")
+            .value_field_name("name")
+            .field(QueryField::new("id", "java.lang.Integer", true, true))
+            .field(QueryField::new("name", "java.lang.String", false, false));
+
+        let cache = client.get_or_create_cache_with_configuration(CacheConfiguration::default("sql-cache").query_entity(entity))
+            .expect("Failed to create cache.");
+
+        let entries: Vec<(Value, Value)> = (0 .. 10).map(|i| (Value::I32(i), Value::String(format!("name-{}", i)))).collect();
+
+        assert_eq!(cache.put_all(&entries), Ok(()));
+
+        let found: Result<Vec<(Value, Value)>> = cache.query_sql("String", "name = ?", &[Value::String("name-3".to_string())])
+            .expect("Failed to execute query.")
+            .collect();
+
+        assert_eq!(found, Ok(vec![(Value::I32(3), Value::String("name-3".to_string()))]));
+
+        cache.destroy()
+            .expect("Failed to destroy cache.");
+    }
+
+    #[test]
+    fn test_scan_partition() {
+        let cache = cache_with(Configuration::default().default_page_size(10));
+
+        let entries: Vec<(Value, Value)> = (0 .. 100).map(|i| (Value::I32(i), Value::I32(i * 2))).collect();
+
+        assert_eq!(cache.put_all(&entries), Ok(()));
+
+        let keys = |cursor: ScanCursor| -> Vec<i32> {
+            cursor.map(|entry| match entry.expect("Failed to scan.") {
+                (Value::I32(k), Value::I32(v)) if v == k * 2 => k,
+                entry => panic!("Unexpected entry: {:?}", entry),
+            }).collect()
+        };
+
+        let all = keys(cache.scan(ScanQuery::new()).expect("Failed to scan."));
+
+        assert_eq!(all.len(), 100);
+
+        let partition = keys(cache.scan_partition(0).expect("Failed to scan."));
+
+        assert!(partition.len() < all.len());
+        assert!(partition.iter().all(|k| all.contains(k)));
+
+        assert!(cache.scan_partition(-1).is_err());
+        assert!(cache.scan_partition(100000).is_err());
+    }
+
+    #[test]
+    fn test_statement_type() {
+        let cache = cache();
+
+        let query = SqlFieldsQuery::new("SELECT x FROM system_range(1, 3)").statement_type(StatementType::Select);
+
+        assert_eq!(cache.query_fields(query).expect("Failed to execute query.").count(), 3);
+
+        let query = SqlFieldsQuery::new("DELETE FROM missing_table").statement_type(StatementType::Select);
+
+        assert!(matches!(cache.query_fields(query).err().unwrap().kind, ErrorKind::Ignite(_)));
+    }
+
+    #[test]
+    fn test_max_open_cursors() {
+        let cache = cache_with(Configuration::default().max_open_cursors(1));
+
+        let query = || SqlFieldsQuery::new("SELECT x FROM system_range(1, 10)").page_size(1);
+
+        let cursor = cache.query_fields(query()).expect("Failed to execute query.");
+
+        assert_eq!(cache.query_fields(query()).err().unwrap().kind, ErrorKind::TooManyCursors);
+
+        drop(cursor);
+
+        assert_eq!(cache.query_fields(query()).expect("Failed to execute query.").count(), 10);
+    }
+
+    #[test]
+    fn test_sql() {
+        let client = client();
+
+        client.sql(None, "DROP TABLE IF EXISTS sql_test", &[]).expect("Failed to drop table.");
+        client.sql(None, "CREATE TABLE sql_test (id INT PRIMARY KEY, name VARCHAR)", &[]).expect("Failed to create table.");
+
+        for i in 0 .. 3 {
+            client.sql(Some("PUBLIC"), "INSERT INTO sql_test (id, name) VALUES (?, ?)", &[Value::I32(i), Value::String(format!("name-{}", i))])
+                .expect("Failed to insert.");
+        }
+
+        let result = client.sql(None, "SELECT id, name FROM sql_test ORDER BY id", &[]).expect("Failed to select.");
+
+        assert_eq!(result.columns, vec!["ID".to_string(), "NAME".to_string()]);
+        assert_eq!(result.rows.len(), 3);
+
+        match result.rows[2].as_slice() {
+            [Some(Value::I32(2)), Some(Value::String(name))] => assert_eq!(name, "name-2"),
+            row => panic!("Unexpected row: {:?}", row),
+        }
+
+        client.sql(None, "DROP TABLE sql_test", &[]).expect("Failed to drop table.");
+    }
+
+    #[test]
+    fn test_idle_timeout() {
+        let client = client_with(Configuration::default().idle_timeout(Duration::from_millis(100)));
+
+        let cache = client.cache("test-cache");
+
+        let local_addr = || format!("{:?}", client.tcp.borrow().stream);
+
+        assert_eq!(cache.put(&Value::I32(1), &Value::I32(1)), Ok(()));
+
+        let addr = local_addr();
+
+        assert_eq!(cache.get(&Value::I32(1)), Ok(Some(Value::I32(1))));
+        assert_eq!(local_addr(), addr);
+
+        thread::sleep(Duration::from_millis(200));
+
+        assert_eq!(cache.get(&Value::I32(1)), Ok(Some(Value::I32(1))));
+        assert_ne!(local_addr(), addr);
+    }
+
+    #[test]
+    fn test_read_cache_names() {
+        let mut bytes = Bytes::from(vec![0u8, 0, 0, 0]);
+
+        assert_eq!(<Vec<String>>::read(&mut bytes), Ok(Vec::new()));
+
+        let mut bytes = Bytes::from(vec![0xffu8, 0xff, 0xff, 0x7f, 9, 0, 0, 0, 0]);
+
+        assert_eq!(<Vec<String>>::read(&mut bytes).unwrap_err().kind, ErrorKind::Serde);
+
+        let mut bytes = Bytes::from(vec![2u8, 0, 0, 0, 9, 1, 0, 0, 0, b'a', 101]);
+
+        assert_eq!(<Vec<String>>::read(&mut bytes).unwrap_err().kind, ErrorKind::Serde);
+    }
+
+    #[test]
+    fn test_topology_version() {
+        let mut response = BytesMut::new();
+
+        0i64.write(&mut response).unwrap(); // Request ID.
+        2i16.write(&mut response).unwrap(); // Flags.
+        5i64.write(&mut response).unwrap();
+        1i32.write(&mut response).unwrap();
+        0i32.write(&mut response).unwrap(); // No caches.
+
+        let mut tcp = tcp_with_responses(vec![response.to_vec()]);
+
+        tcp.features = FeatureSet::new(VERSION_1_4_0, Vec::new());
+
+        let client = Client { tcp: Rc::new(RefCell::new(tcp)) };
+
+        assert_eq!(client.topology_version(), 0);
+        assert_eq!(client.cache_names(), Ok(Vec::new()));
+        assert_eq!(client.topology_version(), 5);
+    }
+
+    #[test]
+    fn test_from_stream() {
+        let mut response = BytesMut::new();
+
+        0i64.write(&mut response).unwrap(); // Request ID.
+        0i32.write(&mut response).unwrap(); // Status.
+        vec!["cache".to_string()].write(&mut response).unwrap();
+
+        let address = server(vec![vec![vec![1u8], response.to_vec()]]);
+
+        let stream = TcpStream::connect(&address).unwrap();
+
+        let client = Client::from_stream(stream, Configuration::default()).unwrap();
+
+        assert_eq!(client.connection_state(), ConnectionState::Connected);
+        assert_eq!(client.cache_names(), Ok(vec!["cache".to_string()]));
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    #[ignore = "Requires a TLS-enabled node and its CA in IGNITE_TLS_CA."]
+    fn test_tls() {
+        let ca = std::env::var("IGNITE_TLS_CA").expect("IGNITE_TLS_CA is not set.");
+
+        let client = client_with(Configuration::default().tls(crate::TlsConfiguration::default().ca_cert(ca)));
+
+        let cache = client.cache("test-cache");
+
+        assert_eq!(cache.put(&Value::I32(1), &Value::I32(1)), Ok(()));
+        assert_eq!(cache.get(&Value::I32(1)), Ok(Some(Value::I32(1))));
+    }
+
+    #[test]
+    fn test_invalid_default_page_size() {
+        let result = Client::start(Configuration::default().default_page_size(0));
+
+        assert_eq!(result.err().unwrap().kind, ErrorKind::Configuration);
+    }
+
     fn client() -> Client {
         let config = Configuration::default();
             // .username("ignite")
             // .password("ignite");
 
+        client_with(config)
+    }
+
+    fn client_with(config: Configuration) -> Client {
         Client::start(config)
             .expect("Failed to create a client.")
     }
 
     fn cache() -> Cache {
-        let client = client();
+        cache_with(Configuration::default())
+    }
+
+    // `compute` and the bulk conditional writes need a transactional cache.
+    fn tx_cache() -> Cache {
+        let config = CacheConfiguration::default("test-tx-cache").atomicity_mode(AtomicityMode::Transactional);
+
+        let cache = client().get_or_create_cache_with_configuration(config)
+            .expect("Failed to create a cache.");
+
+        assert_eq!(cache.remove_all(), Ok(()));
+
+        cache
+    }
+
+    fn cache_with(config: Configuration) -> Cache {
+        let client = client_with(config);
 
         let cache = client.cache("test-cache");
 
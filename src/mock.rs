@@ -0,0 +1,387 @@
+//! In-process server for tests that don't need a real cluster. It accepts the handshake and
+//! keeps an in-memory store for a few cache operations. Other operations fail unless a
+//! response is scripted for them.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use bytes::{Buf, Bytes, BytesMut};
+use uuid::Uuid;
+
+use crate::{AffinityTopologyVersion, Version, VERSION, VERSION_1_4_0, VERSION_1_7_0};
+use crate::binary::{IgniteRead, IgniteWrite, Value};
+use crate::cache::CacheOp;
+use crate::configuration::Configuration;
+use crate::error::{Error, ErrorKind, Result};
+use crate::network::read_message;
+
+const STATUS_FAILED: i32 = 1;
+
+const FLAG_ERROR: i16 = 1;
+const FLAG_AFFINITY_TOPOLOGY_CHANGED: i16 = 2;
+
+enum Response {
+    Success(Vec<u8>),
+    Failure(i32, String),
+    Disconnect,
+}
+
+struct State {
+    entries: HashMap<(i32, Value), Value>,
+    scripted: HashMap<i16, VecDeque<Response>>,
+    // Requests received by op code, including unanswered ones.
+    requests: HashMap<i16, usize>,
+    // Accepted handshakes.
+    handshakes: usize,
+    handshake_delay: Duration,
+    node_id: Uuid,
+    // Newest protocol version accepted in the handshake.
+    max_version: Version,
+    topology: Option<AffinityTopologyVersion>,
+}
+
+pub struct MockServer {
+    address: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockServer {
+    /// Starts the server on a random loopback port. It keeps running until the process exits.
+    pub fn start() -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind mock server.");
+        let address = listener.local_addr().expect("Failed to bind mock server.").to_string();
+
+        let state = Arc::new(Mutex::new(State {
+            entries: HashMap::new(),
+            scripted: HashMap::new(),
+            requests: HashMap::new(),
+            handshakes: 0,
+            handshake_delay: Duration::default(),
+            // Derived from the port, so that servers in the same test have different IDs.
+            node_id: Uuid::from_u128(listener.local_addr().expect("Failed to bind mock server.").port() as u128),
+            max_version: VERSION,
+            topology: None,
+        }));
+        let server_state = state.clone();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    // Connection errors only affect the client that caused them.
+                    Ok(stream) => {
+                        let state = server_state.clone();
+
+                        thread::spawn(move || { let _ = serve(stream, &state); });
+                    },
+                    Err(_) => return,
+                }
+            }
+        });
+
+        MockServer { address, state }
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    pub fn configuration(&self) -> Configuration {
+        Configuration::default().address(&self.address)
+    }
+
+    /// Node ID reported in handshakes over protocol 1.4.0.
+    pub fn node_id(&self) -> Uuid {
+        self.state.lock().unwrap().node_id
+    }
+
+    /// Rejects handshakes that propose a newer protocol version. Defaults to `VERSION`.
+    pub fn max_version(&self, version: Version) {
+        self.state.lock().unwrap().max_version = version;
+    }
+
+    /// Reported in the next response on each connection over protocol 1.4.0.
+    pub fn topology_version(&self, major: i64, minor: i32) {
+        self.state.lock().unwrap().topology = Some(AffinityTopologyVersion { major, minor });
+    }
+
+    /// Answers the next request with `op_code` with a successful response carrying `payload`.
+    pub fn respond(&self, op_code: i16, payload: Vec<u8>) {
+        self.script(op_code, Response::Success(payload));
+    }
+
+    /// Answers the next request with `op_code` with an error.
+    pub fn fail(&self, op_code: i16, status: i32, message: &str) {
+        self.script(op_code, Response::Failure(status, message.to_string()));
+    }
+
+    /// Closes the connection instead of answering the next request with `op_code`.
+    pub fn disconnect(&self, op_code: i16) {
+        self.script(op_code, Response::Disconnect);
+    }
+
+    /// Number of requests with `op_code` received so far on all connections.
+    pub fn request_count(&self, op_code: i16) -> usize {
+        self.state.lock().unwrap().requests.get(&op_code).copied().unwrap_or(0)
+    }
+
+    /// Answers handshakes after `delay`, e.g. to catch a client while it reconnects.
+    pub fn delay_handshakes(&self, delay: Duration) {
+        self.state.lock().unwrap().handshake_delay = delay;
+    }
+
+    /// Number of handshakes accepted so far, i.e. of connections opened by clients.
+    pub fn handshake_count(&self) -> usize {
+        self.state.lock().unwrap().handshakes
+    }
+
+    fn script(&self, op_code: i16, response: Response) {
+        self.state.lock().unwrap().scripted.entry(op_code).or_default().push_back(response);
+    }
+}
+
+fn serve(mut stream: TcpStream, state: &Mutex<State>) -> Result<()> {
+    let version = handshake(&mut stream, state)?;
+
+    // Last topology version reported on this connection.
+    let mut topology = None;
+
+    loop {
+        let mut request = read_message(&mut stream)?;
+
+        let op_code = request.get_i16_le();
+        let request_id = request.get_i64_le();
+
+        let (response, current) = {
+            let mut state = state.lock().unwrap();
+
+            (handle(op_code, &mut request, &mut state), state.topology)
+        };
+
+        let mut bytes = BytesMut::new();
+
+        request_id.write(&mut bytes)?;
+
+        // Starting with 1.4.0, the status is replaced with flags, followed by the status on failure.
+        if version >= VERSION_1_4_0 {
+            let changed = current.filter(|_| current != topology);
+
+            let mut flags = 0;
+
+            if let Response::Failure(..) = response {
+                flags |= FLAG_ERROR;
+            }
+
+            if changed.is_some() {
+                flags |= FLAG_AFFINITY_TOPOLOGY_CHANGED;
+            }
+
+            flags.write(&mut bytes)?;
+
+            if let Some(changed) = changed {
+                changed.major.write(&mut bytes)?;
+                changed.minor.write(&mut bytes)?;
+            }
+
+            topology = current;
+        }
+
+        match response {
+            Response::Success(payload) => {
+                if version < VERSION_1_4_0 {
+                    0i32.write(&mut bytes)?;
+                }
+
+                bytes.extend_from_slice(&payload);
+            },
+            Response::Failure(status, message) => {
+                status.write(&mut bytes)?;
+                message.write(&mut bytes)?;
+            },
+            Response::Disconnect => return Ok(()),
+        }
+
+        write_message(&mut stream, &bytes)?;
+    }
+}
+
+// Returns the negotiated version.
+fn handshake(stream: &mut TcpStream, state: &Mutex<State>) -> Result<Version> {
+    let mut request = read_message(stream)?;
+
+    request.get_i8(); // Handshake code.
+
+    let version = Version { major: request.get_i16_le(), minor: request.get_i16_le(), patch: request.get_i16_le() };
+
+    let (max_version, node_id, delay) = {
+        let mut state = state.lock().unwrap();
+
+        if version <= state.max_version {
+            state.handshakes += 1;
+        }
+
+        (state.max_version, state.node_id, state.handshake_delay)
+    };
+
+    thread::sleep(delay);
+
+    let mut response = BytesMut::new();
+
+    if version > max_version {
+        0i8.write(&mut response)?;
+        max_version.major.write(&mut response)?;
+        max_version.minor.write(&mut response)?;
+        max_version.patch.write(&mut response)?;
+        format!("Unsupported version: {}", version).write(&mut response)?;
+
+        write_message(stream, &response)?;
+
+        return Err(Error::new(ErrorKind::Network, "Handshake rejected".to_string()));
+    }
+
+    1i8.write(&mut response)?;
+
+    if version >= VERSION_1_7_0 {
+        Value::I8Vec(Vec::new()).write(&mut response)?; // No optional features.
+    }
+
+    if version >= VERSION_1_4_0 {
+        node_id.write(&mut response)?;
+    }
+
+    write_message(stream, &response)?;
+
+    Ok(version)
+}
+
+fn handle(op_code: i16, request: &mut Bytes, state: &mut State) -> Response {
+    *state.requests.entry(op_code).or_default() += 1;
+
+    if let Some(response) = state.scripted.get_mut(&op_code).and_then(VecDeque::pop_front) {
+        return response;
+    }
+
+    match cache_op(op_code, request, state) {
+        Ok(Some(payload)) => Response::Success(payload),
+        Ok(None) => Response::Failure(STATUS_FAILED, format!("Unsupported operation: {}", op_code)),
+        Err(err) => Response::Failure(STATUS_FAILED, err.message),
+    }
+}
+
+fn cache_op(op_code: i16, request: &mut Bytes, state: &mut State) -> Result<Option<Vec<u8>>> {
+    let op = |op: CacheOp| op.code() == op_code;
+
+    let supported = [CacheOp::Get, CacheOp::Put, CacheOp::ContainsKey, CacheOp::RemoveKey, CacheOp::RemoveAll, CacheOp::Clear, CacheOp::GetSize];
+
+    if !supported.iter().any(|supported| op(*supported)) {
+        return Ok(None);
+    }
+
+    let cache_id = i32::read(request)?;
+
+    request.get_u8(); // Flags.
+
+    let mut response = BytesMut::new();
+
+    if op(CacheOp::Get) {
+        let key = Value::read(request)?;
+
+        state.entries.get(&(cache_id, key)).cloned().write(&mut response)?;
+    }
+    else if op(CacheOp::Put) {
+        let key = Value::read(request)?;
+        let value = Value::read(request)?;
+
+        state.entries.insert((cache_id, key), value);
+    }
+    else if op(CacheOp::ContainsKey) {
+        let key = Value::read(request)?;
+
+        state.entries.contains_key(&(cache_id, key)).write(&mut response)?;
+    }
+    else if op(CacheOp::RemoveKey) {
+        let key = Value::read(request)?;
+
+        state.entries.remove(&(cache_id, key)).is_some().write(&mut response)?;
+    }
+    else if op(CacheOp::RemoveAll) || op(CacheOp::Clear) {
+        state.entries.retain(|(id, _), _| *id != cache_id);
+    }
+    else {
+        // Peek modes are ignored.
+        let size = state.entries.keys().filter(|(id, _)| *id == cache_id).count();
+
+        (size as i64).write(&mut response)?;
+    }
+
+    Ok(Some(response.to_vec()))
+}
+
+// A single write, so Nagle's algorithm doesn't hold back the payload until the length is acknowledged.
+fn write_message(stream: &mut TcpStream, msg: &[u8]) -> Result<()> {
+    let mut bytes = Vec::with_capacity(4 + msg.len());
+
+    bytes.extend_from_slice(&(msg.len() as i32).to_le_bytes());
+    bytes.extend_from_slice(msg);
+
+    stream.write_all(&bytes)?;
+
+    Ok(())
+}
+
+// === Tests
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+
+    use crate::{Client, ErrorKind};
+    use crate::binary::{IgniteWrite, Value};
+    use crate::mock::MockServer;
+
+    #[test]
+    fn test_put_get() {
+        let server = MockServer::start();
+
+        let client = Client::start(server.configuration()).unwrap();
+
+        let cache = client.cache("test-cache");
+        let other = client.cache("other-cache");
+
+        assert_eq!(cache.get(&Value::I32(1)), Ok(None));
+        assert_eq!(cache.put(&Value::I32(1), &Value::String("one".to_string())), Ok(()));
+        assert_eq!(cache.get(&Value::I32(1)), Ok(Some(Value::String("one".to_string()))));
+        assert_eq!(cache.contains_key(&Value::I32(1)), Ok(true));
+        assert_eq!(other.get(&Value::I32(1)), Ok(None));
+        assert_eq!(cache.size(&[]), Ok(1));
+        assert_eq!(cache.remove_key(&Value::I32(1)), Ok(true));
+        assert_eq!(cache.get(&Value::I32(1)), Ok(None));
+    }
+
+    #[test]
+    fn test_scripted_responses() {
+        let server = MockServer::start();
+
+        let mut payload = BytesMut::new();
+
+        vec!["cache".to_string()].write(&mut payload).unwrap();
+
+        server.respond(1050, payload.to_vec());
+        server.fail(1000, 1010, "Scripted failure");
+
+        let client = Client::start(server.configuration()).unwrap();
+
+        assert_eq!(client.cache_names(), Ok(vec!["cache".to_string()]));
+
+        let err = client.cache("cache").get(&Value::I32(1)).unwrap_err();
+
+        assert_eq!(err.kind, ErrorKind::Ignite(1010));
+        assert_eq!(err.message, "Scripted failure");
+
+        assert_eq!(client.cache("cache").get(&Value::I32(1)), Ok(None));
+        assert_eq!(client.cache_names().unwrap_err().kind, ErrorKind::Ignite(1));
+    }
+}
@@ -1,103 +1,1165 @@
-use std::net::TcpStream;
-use std::io::{Write, Read};
+use std::net::{TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::io::{self, Write, Read};
+use std::fmt::Debug;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use bytes::{BytesMut, Bytes, Buf, BufMut};
+use uuid::Uuid;
 
 use crate::error::{Result, ErrorKind, Error};
-use crate::{VERSION, Version};
-use crate::binary::IgniteWrite;
+use crate::{VERSION, VERSION_1_1_0, VERSION_1_4_0, VERSION_1_7_0, Version, Feature, FeatureSet, AffinityTopologyVersion};
+use crate::binary::{Value, IgniteWrite, IgniteRead, with_lossy_utf8, with_protocol_version};
 use crate::configuration::Configuration;
+#[cfg(feature = "tls")]
+use crate::configuration::TlsConfiguration;
+use crate::query::CursorPermit;
+use crate::affinity::Routing;
+
+/// Connection state as seen by the client. Operations on a `Closed` connection try to reconnect first
+/// if `Configuration::reconnect` is set, and fail otherwise.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ConnectionState {
+    Connected,
+    Reconnecting,
+    Closed,
+}
+
+// Response header flags, starting with protocol 1.4.0.
+const FLAG_ERROR: i16 = 1;
+const FLAG_AFFINITY_TOPOLOGY_CHANGED: i16 = 2;
+const FLAG_NOTIFICATION: i16 = 4;
+
+const STATUS_AUTH_FAILED: i32 = 2000;
+
+// Error codes from the protocol's `ClientStatus` table. Anything else is reported as unknown in strict mode.
+const KNOWN_STATUSES: [i32; 13] = [
+    1,    // Failed.
+    2,    // Invalid op code.
+    10,   // Invalid node state.
+    100,  // Functionality disabled.
+    1000, // Cache does not exist.
+    1001, // Cache exists.
+    1010, // Too many cursors.
+    1011, // Resource does not exist.
+    1012, // Security violation.
+    1020, // Transaction limit exceeded.
+    1021, // Transaction not found.
+    1030, // Too many compute tasks.
+    STATUS_AUTH_FAILED,
+];
+
+/// Transport the protocol runs over: a TCP socket, or a Unix domain socket on Unix platforms.
+pub(crate) trait Stream: Read + Write + Debug + Send {}
+
+impl<T: Read + Write + Debug + Send> Stream for T {}
+
+fn open_stream(configuration: &Configuration, address: &str) -> Result<Box<dyn Stream>> {
+    #[cfg(unix)]
+    {
+        if let Some(path) = &configuration.unix_socket {
+            let stream = UnixStream::connect(path)?;
+
+            stream.set_read_timeout(configuration.read_timeout)?;
+            stream.set_write_timeout(configuration.write_timeout)?;
+
+            return Ok(Box::new(stream));
+        }
+    }
+
+    let stream = match configuration.connect_timeout {
+        Some(timeout) => connect_timeout(address, timeout)?,
+        None => TcpStream::connect(address)?,
+    };
+
+    set_timeouts(&stream, configuration)?;
+
+    #[cfg(feature = "tls")]
+    {
+        if let Some(tls) = &configuration.tls {
+            return Ok(Box::new(tls_stream(stream, address, tls)?));
+        }
+    }
+
+    Ok(Box::new(stream))
+}
+
+#[cfg(feature = "tls")]
+fn tls_stream(stream: TcpStream, address: &str, tls: &TlsConfiguration) -> Result<native_tls::TlsStream<TcpStream>> {
+    let tls_error = |err: &dyn std::fmt::Display| Error::new(ErrorKind::Network, format!("TLS error: {}", err));
+
+    let mut builder = native_tls::TlsConnector::builder();
+
+    if let Some(path) = &tls.ca_cert {
+        let cert = native_tls::Certificate::from_pem(&std::fs::read(path)?).map_err(|err| tls_error(&err))?;
+
+        builder.add_root_certificate(cert);
+    }
+
+    if let (Some(cert), Some(key)) = (&tls.client_cert, &tls.client_key) {
+        let identity = native_tls::Identity::from_pkcs8(&std::fs::read(cert)?, &std::fs::read(key)?).map_err(|err| tls_error(&err))?;
+
+        builder.identity(identity);
+    }
+
+    builder.danger_accept_invalid_certs(tls.accept_invalid_certs);
+    builder.danger_accept_invalid_hostnames(tls.accept_invalid_certs);
+
+    let connector = builder.build().map_err(|err| tls_error(&err))?;
+
+    // Host name part of `host:port`, used for certificate validation.
+    let domain = address.rsplit_once(':').map(|(host, _)| host).unwrap_or(address);
+
+    connector.connect(domain, stream).map_err(|err| tls_error(&err))
+}
+
+// Tries each resolved address in turn, like `TcpStream::connect`.
+fn connect_timeout(address: &str, timeout: Duration) -> Result<TcpStream> {
+    let mut last_error = None;
+
+    for addr in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_error = Some(err),
+        }
+    }
+
+    match last_error {
+        Some(err) => Err(err.into()),
+        None => Err(Error::new(ErrorKind::Network, format!("Address resolved to nothing: {}", address))),
+    }
+}
+
+pub(crate) fn set_timeouts(stream: &TcpStream, configuration: &Configuration) -> Result<()> {
+    stream.set_read_timeout(configuration.read_timeout)?;
+    stream.set_write_timeout(configuration.write_timeout)?;
+
+    Ok(())
+}
 
 pub(crate) struct Tcp {
-    pub(crate) stream: TcpStream,
+    pub(crate) stream: Box<dyn Stream>,
+    pub(crate) configuration: Configuration,
+    pub(crate) features: FeatureSet,
+    // Oldest protocol version accepted when the server rejects `VERSION`.
+    pub(crate) min_version: Version,
+    // Session state replayed on reconnect. Open cursors are not restored, and an open transaction
+    // is lost with the connection (see `lost_tx_id`).
+    pub(crate) type_names: HashMap<i32, String>,
+    pub(crate) last_activity: Instant,
+    pub(crate) affinity_version: Option<AffinityTopologyVersion>,
+    pub(crate) open_cursors: Arc<AtomicUsize>,
+    // Connections that are reconnecting right now. Shared by the connections of a pool, so that it
+    // can be read while they are checked out.
+    pub(crate) reconnecting: Arc<AtomicUsize>,
+    pub(crate) state: ConnectionState,
+    // Transaction that cache operations on this connection take part in.
+    pub(crate) tx_id: Option<i32>,
+    // Transaction that was open when the connection was replaced. The new session doesn't know it,
+    // so cache operations fail instead of running outside of it until the transaction is ended.
+    pub(crate) lost_tx_id: Option<i32>,
+    // Address of the current connection; reconnects try it first.
+    pub(crate) endpoint: Option<String>,
+    // ID of the last request sent. Never reset, so IDs stay unique across reconnects.
+    pub(crate) request_id: i64,
+    // Reported in the handshake starting with protocol 1.4.0.
+    pub(crate) node_id: Option<Uuid>,
+    // Connections to individual nodes for keyed operations, if partition awareness is enabled.
+    pub(crate) routing: Option<Routing>,
 }
 
 impl Tcp {
-    pub(crate) fn handshake(&mut self, config: &Configuration) -> Result<()> {
-        let mut request = BytesMut::with_capacity(8);
+    pub(crate) fn connect(configuration: Configuration) -> Result<Tcp> {
+        // Replaced as soon as one of the endpoints accepts the handshake.
+        let mut tcp = Tcp::new(Box::new(io::Cursor::new(Vec::new())), configuration);
+
+        tcp.open()?;
+
+        if tcp.configuration.partition_awareness {
+            tcp.routing = Some(Routing::connect(&tcp.configuration));
+        }
+
+        Ok(tcp)
+    }
+
+    // Connection to a single address over protocol 1.4.0 or newer, so that the server reports its node ID.
+    pub(crate) fn connect_node(configuration: &Configuration, address: &str) -> Result<Tcp> {
+        // Routing decides which failed requests are run again, so node connections never retry.
+        let mut tcp = Tcp::new(Box::new(io::Cursor::new(Vec::new())), configuration.clone().address(address).reconnect(false));
+
+        tcp.min_version = VERSION_1_4_0;
+
+        tcp.open()?;
+
+        tcp.features.require(Feature::PartitionAwareness)?;
+
+        Ok(tcp)
+    }
+
+    // Performs the handshake over an already open stream. The stream can't be reopened to retry
+    // the handshake with an older version, so it proposes 1.1.0, which every server accepts.
+    pub(crate) fn connect_with(stream: Box<dyn Stream>, configuration: Configuration) -> Result<Tcp> {
+        let mut tcp = Tcp::new(stream, configuration);
+
+        tcp.handshake(VERSION_1_1_0)?;
+
+        Ok(tcp)
+    }
+
+    fn new(stream: Box<dyn Stream>, configuration: Configuration) -> Tcp {
+        Tcp {
+            stream,
+            configuration,
+            // Replaced with the negotiated one in the handshake.
+            features: FeatureSet::new(VERSION_1_1_0, Vec::new()),
+            min_version: VERSION_1_1_0,
+            type_names: HashMap::new(),
+            last_activity: Instant::now(),
+            affinity_version: None,
+            open_cursors: Arc::new(AtomicUsize::new(0)),
+            state: ConnectionState::Connected,
+            reconnecting: Arc::new(AtomicUsize::new(0)),
+            tx_id: None,
+            lost_tx_id: None,
+            endpoint: None,
+            request_id: 0,
+            node_id: None,
+            routing: None,
+        }
+    }
+
+    // Tries the endpoints in order, starting with the last connected one, until a handshake succeeds.
+    fn open(&mut self) -> Result<()> {
+        let mut endpoints = self.configuration.addresses.clone();
+
+        if let Some(endpoint) = &self.endpoint {
+            if let Some(pos) = endpoints.iter().position(|address| address == endpoint) {
+                let endpoint = endpoints.remove(pos);
+
+                endpoints.insert(0, endpoint);
+            }
+        }
+
+        // The socket path doesn't depend on the address.
+        #[cfg(unix)]
+        {
+            if self.configuration.unix_socket.is_some() {
+                endpoints.truncate(1);
+            }
+        }
+
+        let mut last_error = Error::new(ErrorKind::Configuration, "At least one address is required".to_string());
+
+        for endpoint in endpoints {
+            let result = match self.open_endpoint(&endpoint, VERSION) {
+                // The server closes the connection after rejecting the version, so the retry opens a new one.
+                Err(Error { kind: ErrorKind::Handshake { server_version, .. }, .. })
+                    if server_version >= self.min_version && server_version < VERSION => self.open_endpoint(&endpoint, server_version),
+                result => result,
+            };
+
+            match result {
+                Ok(()) => {
+                    self.endpoint = Some(endpoint);
+
+                    return Ok(());
+                },
+                Err(err) => last_error = err,
+            }
+        }
+
+        Err(last_error)
+    }
+
+    fn open_endpoint(&mut self, endpoint: &str, version: Version) -> Result<()> {
+        self.stream = open_stream(&self.configuration, endpoint)?;
+
+        self.handshake(version)
+    }
+
+    // Opens a new connection and replays registered type names on it.
+    pub(crate) fn reconnect(&mut self) -> Result<()> {
+        self.state = ConnectionState::Reconnecting;
+
+        if let Some(tx_id) = self.tx_id.take() {
+            self.lost_tx_id = Some(tx_id);
+        }
+
+        self.reconnecting.fetch_add(1, Ordering::SeqCst);
+
+        let result = self.restore();
+
+        self.reconnecting.fetch_sub(1, Ordering::SeqCst);
 
-        request.put_i8(1);
-        request.put_i16_le(VERSION.major);
-        request.put_i16_le(VERSION.minor);
-        request.put_i16_le(VERSION.patch);
-        request.put_i8(2);
+        self.state = if result.is_ok() { ConnectionState::Connected } else { ConnectionState::Closed };
 
-        if let Some(username) = config.username.clone() {
-            username.write(&mut request)?;
+        result
+    }
+
+    fn restore(&mut self) -> Result<()> {
+        self.open()?;
+
+        let type_names: Vec<(i32, String)> = self.type_names.iter()
+            .map(|(type_id, type_name)| (*type_id, type_name.clone()))
+            .collect();
 
-            config.password.clone().write(&mut request)?;
+        for (type_id, type_name) in type_names {
+            self.send_request(
+                3001,
+                |request| {
+                    0i8.write(request)?;
+                    type_id.write(request)?;
+                    type_name.write(request)?;
+
+                    Ok(())
+                },
+                |_| { Ok(()) }
+            )?;
         }
 
+        Ok(())
+    }
+
+    pub(crate) fn handshake(&mut self, version: Version) -> Result<()> {
+        let request = handshake_request(&self.configuration, version)?;
+
         let mut response = self.send(&request)?;
 
-        let success = response.get_u8();
+        let success = i8::read(&mut response)?;
 
         if success == 1 {
+            self.features = FeatureSet::new(version, read_feature_bitmask(&mut response, version)?);
+
+            if version >= VERSION_1_4_0 {
+                self.node_id = Some(Uuid::read(&mut response)?);
+            }
+
             Ok(())
         }
         else {
-            let major = response.get_i16_le();
-            let minor = response.get_i16_le();
-            let patch = response.get_i16_le();
+            Err(read_handshake_error(&mut response, version)?)
+        }
+    }
 
-            let kind = ErrorKind::Handshake {server_version: Version { major, minor, patch }, client_version: VERSION };
+    // Transaction for the next cache operation. Fails if it was lost with the previous connection.
+    pub(crate) fn current_tx(&self) -> Result<Option<i32>> {
+        match self.lost_tx_id {
+            Some(tx_id) => Err(lost_transaction(tx_id)),
+            None => Ok(self.tx_id),
+        }
+    }
+
+    pub(crate) fn acquire_cursor(&self) -> Result<CursorPermit> {
+        CursorPermit::acquire(&self.open_cursors, self.configuration.max_open_cursors)
+    }
+
+    pub(crate) fn execute<R, F1, F2>(&mut self, operation_code: i16, request_writer: F1, response_reader: F2) -> Result<R>
+        where
+            F1: Fn(&mut BytesMut) -> Result<()>,
+            F2: Fn(&mut Bytes) -> Result<R>,
+    {
+        self.execute_op(operation_code, false, request_writer, response_reader)
+    }
+
+    // Same as `execute`, but a `read_only` operation is retried once if the connection breaks
+    // and `Configuration::reconnect` is set.
+    pub(crate) fn execute_op<R, F1, F2>(&mut self, operation_code: i16, read_only: bool, request_writer: F1, response_reader: F2) -> Result<R>
+        where
+            F1: Fn(&mut BytesMut) -> Result<()>,
+            F2: Fn(&mut Bytes) -> Result<R>,
+    {
+        if self.state != ConnectionState::Connected {
+            if !self.configuration.reconnect {
+                return Err(Error::new(ErrorKind::Network, "Connection is closed.".to_string()));
+            }
+
+            self.reconnect()?;
+        }
+
+        // Idle connections may have been dropped by a firewall, so don't trust them. Unless a
+        // transaction is open: a new connection would lose it, while the old one may still work.
+        if let (Some(idle_timeout), None) = (self.configuration.idle_timeout, self.tx_id) {
+            if self.last_activity.elapsed() >= idle_timeout {
+                self.reconnect()?;
+            }
+        }
+
+        let result = self.send_request(operation_code, &request_writer, &response_reader);
 
-            let message: Option<String> = crate::binary::IgniteRead::read(&mut response)?;
+        // Unless reconnects are enabled, the connection stays closed. Writes are not retried, since
+        // the request may have reached the server, and neither are transactional requests: the
+        // transaction didn't survive the old connection.
+        if let Err(Error { kind: ErrorKind::Network, .. }) = result {
+            if !self.configuration.reconnect {
+                self.state = ConnectionState::Closed;
+            }
+            else {
+                let retry = read_only && self.tx_id.is_none();
 
-            Err(Error::new(kind, message.unwrap_or("Handshake unexpected failure".to_string())))
+                if self.reconnect().is_ok() && retry {
+                    return self.send_request(operation_code, request_writer, response_reader);
+                }
+            }
         }
+
+        result
     }
 
-    pub(crate) fn execute<R, F1, F2>(&mut self, operation_code: i16, request_writer: F1, response_reader: F2) -> Result<R>
+    fn send_request<R, F1, F2>(&mut self, operation_code: i16, request_writer: F1, response_reader: F2) -> Result<R>
         where
             F1: Fn(&mut BytesMut) -> Result<()>,
             F2: Fn(&mut Bytes) -> Result<R>,
     {
+        self.request_id = self.request_id.wrapping_add(1);
+
+        let request_id = self.request_id;
+
         let mut request = BytesMut::with_capacity(1024);
 
         request.put_i16_le(operation_code);
-        request.put_i64_le(0); // Request ID.
+        request.put_i64_le(request_id);
 
-        request_writer(&mut request)?;
+        with_protocol_version(self.features.version(), || request_writer(&mut request))?;
+
+        for middleware in &self.configuration.request_middleware {
+            middleware(operation_code, &mut request)?;
+        }
 
         let mut response = self.send(&request)?;
 
-        assert_eq!(response.get_i64_le(), 0); // Request ID.
+        for middleware in &self.configuration.response_middleware {
+            middleware(operation_code, &mut response)?;
+        }
+
+        self.read_response(request_id, &mut response, response_reader)
+    }
+
+    fn read_response<R, F>(&mut self, request_id: i64, response: &mut Bytes, response_reader: F) -> Result<R>
+        where
+            F: Fn(&mut Bytes) -> Result<R>,
+    {
+        let (status, affinity_version) = read_response_header(request_id, response, self.features.version())?;
 
-        let status = response.get_i32_le();
+        if affinity_version.is_some() {
+            self.affinity_version = affinity_version;
+        }
 
         if status == 0 {
-            response_reader(&mut response)
+            let version = self.features.version();
+
+            with_lossy_utf8(self.configuration.lossy_utf8, || with_protocol_version(version, || response_reader(response)))
         }
         else {
-            let message = String::from_utf8(response.to_vec())?;
-
-            Err(Error::new(ErrorKind::Ignite(status), message))
+            Err(read_error(status, response, self.configuration.strict_status_codes))
         }
     }
 
     fn send(&mut self, msg: &BytesMut) -> Result<Bytes> {
         // Write.
 
-        let len = msg.len() as i32;
-        let len = len.to_le_bytes();
+        // A single write, so Nagle's algorithm doesn't hold back the payload until the length is acknowledged.
+        let mut frame = Vec::with_capacity(4 + msg.len());
+
+        frame.extend_from_slice(&(msg.len() as i32).to_le_bytes());
+        frame.extend_from_slice(msg.as_ref());
 
-        self.stream.write_all(&len)?;
-        self.stream.write_all(msg.as_ref())?;
+        self.stream.write_all(&frame)?;
         self.stream.flush()?;
 
         // Read.
 
-        let mut len = [0u8; 4];
+        let msg = read_message(&mut self.stream)?;
+
+        self.last_activity = Instant::now();
+
+        Ok(msg)
+    }
+}
+
+pub(crate) fn lost_transaction(tx_id: i32) -> Error {
+    Error::new(ErrorKind::Network, format!("Transaction {} was lost when the connection was reestablished.", tx_id))
+}
+
+// Checks the response ID and returns the status along with the new affinity version, if the server reported one.
+pub(crate) fn read_response_header(request_id: i64, response: &mut Bytes, version: Version) -> Result<(i32, Option<AffinityTopologyVersion>)> {
+    let response_id = i64::read(response)?;
+
+    if response_id != request_id {
+        return Err(Error::new(ErrorKind::Network, format!("Response ID {} doesn't match request ID {}.", response_id, request_id)));
+    }
+
+    if version < VERSION_1_4_0 {
+        return Ok((i32::read(response)?, None));
+    }
+
+    let flags = i16::read(response)?;
+
+    if flags & FLAG_NOTIFICATION != 0 {
+        // The client never subscribes to server notifications.
+        return Err(Error::new(ErrorKind::NotSupported, "Unexpected server notification".to_string()));
+    }
+
+    let mut affinity_version = None;
+
+    if flags & FLAG_AFFINITY_TOPOLOGY_CHANGED != 0 {
+        let major = i64::read(response)?;
+        let minor = i32::read(response)?;
+
+        affinity_version = Some(AffinityTopologyVersion { major, minor });
+    }
+
+    let status = if flags & FLAG_ERROR != 0 { i32::read(response)? } else { 0 };
+
+    Ok((status, affinity_version))
+}
+
+// Credentials are sent only with a username; the server expects both fields or neither.
+pub(crate) fn handshake_request(config: &Configuration, version: Version) -> Result<BytesMut> {
+    let mut request = BytesMut::with_capacity(8);
+
+    request.put_i8(1);
+    request.put_i16_le(version.major);
+    request.put_i16_le(version.minor);
+    request.put_i16_le(version.patch);
+    request.put_i8(2);
+
+    // Optional features the client supports; none so far.
+    if version >= VERSION_1_7_0 {
+        Value::I8Vec(Vec::new()).write(&mut request)?;
+    }
+
+    if let Some(username) = &config.username {
+        username.write(&mut request)?;
+        config.password.write(&mut request)?;
+    }
+
+    Ok(request)
+}
+
+// Exchanged starting with protocol 1.7.0. Older servers only have the features implied by the version.
+fn read_feature_bitmask(response: &mut Bytes, version: Version) -> Result<Vec<u8>> {
+    if version < VERSION_1_7_0 {
+        return Ok(Vec::new());
+    }
+
+    match <Option<Value>>::read(response)? {
+        Some(Value::I8Vec(bitmask)) => Ok(bitmask.into_iter().map(|byte| byte as u8).collect()),
+        None => Ok(Vec::new()),
+        Some(value) => Err(Error::new(ErrorKind::Serde, format!("Invalid feature bitmask: {:?}", value))),
+    }
+}
+
+// `read_exact` keeps reading until the buffer is full, so fragmented messages are reassembled.
+pub(crate) fn read_message<R: Read>(reader: &mut R) -> Result<Bytes> {
+    let mut len = [0u8; 4];
+
+    reader.read_exact(&mut len)?;
+
+    let len = i32::from_le_bytes(len);
+
+    if len < 0 {
+        return Err(Error::new(ErrorKind::Network, format!("Invalid message length: {}", len)));
+    }
+
+    let mut msg = vec![0u8; len as usize];
+
+    reader.read_exact(&mut msg)?;
+
+    Ok(Bytes::from(msg))
+}
+
+// Version triple and message, followed by a status code if the server sends one.
+pub(crate) fn read_handshake_error(response: &mut Bytes, client_version: Version) -> Result<Error> {
+    let major = i16::read(response)?;
+    let minor = i16::read(response)?;
+    let patch = i16::read(response)?;
+
+    let message = <Option<String>>::read(response)?
+        .unwrap_or_else(|| "Handshake unexpected failure".to_string());
+
+    let status = if response.remaining() >= 4 { response.get_i32_le() } else { 0 };
+
+    if status == STATUS_AUTH_FAILED {
+        Ok(Error::new(ErrorKind::Authentication, message))
+    }
+    else {
+        Ok(Error::new(ErrorKind::Handshake { server_version: Version { major, minor, patch }, client_version }, message))
+    }
+}
+
+pub(crate) fn read_error(status: i32, response: &mut Bytes, strict: bool) -> Error {
+    let message = match <Option<String>>::read(response) {
+        Ok(Some(message)) => message,
+        Ok(None) => format!("Ignite error: {}", status),
+        Err(err) => format!("Ignite error: {} (failed to read message: {})", status, err.message),
+    };
+
+    if strict && !KNOWN_STATUSES.contains(&status) {
+        Error::new(ErrorKind::UnknownStatus(status), format!("Unknown status {}: {}", status, message))
+    }
+    else {
+        Error::new(ErrorKind::Ignite(status), message)
+    }
+}
+
+// === Tests
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::net::{TcpListener, TcpStream};
+    use std::io::{self, Read, Write};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use std::sync::{Arc, Mutex};
+
+    use bytes::{Buf, Bytes, BytesMut};
+    use uuid::Uuid;
+
+    use crate::{VERSION, VERSION_1_1_0, VERSION_1_4_0, Version, FeatureSet, AffinityTopologyVersion};
+    use crate::binary::{Value, IgniteWrite, IgniteRead};
+    use crate::configuration::Configuration;
+    use crate::error::{ErrorKind, Error};
+    use crate::network::{Tcp, ConnectionState, read_error, read_handshake_error, read_message, handshake_request};
+
+    // Yields at most one byte per read.
+    struct OneByteReader {
+        bytes: Vec<u8>,
+        pos: usize,
+    }
+
+    impl Read for OneByteReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.pos == self.bytes.len() || buf.is_empty() {
+                return Ok(0);
+            }
+
+            buf[0] = self.bytes[self.pos];
+
+            self.pos += 1;
+
+            Ok(1)
+        }
+    }
+
+    // Connection that is never used for IO.
+    pub(crate) fn tcp() -> Tcp {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        tcp_with_stream(stream)
+    }
+
+    // Connection to a server that answers each request with the next response (without the length prefix).
+    pub(crate) fn tcp_with_responses(responses: Vec<Vec<u8>>) -> Tcp {
+        let stream = TcpStream::connect(server(vec![responses])).unwrap();
+
+        tcp_with_stream(stream)
+    }
+
+    // Server that accepts one connection per entry and answers its requests in order. Responses
+    // starting with a zero request ID get the ID of the request they answer.
+    //
+    // A `[1]` handshake response plays a server that only speaks protocol 1.1.0: newer versions
+    // are rejected, and the retry on a new connection is accepted as part of the same entry.
+    pub(crate) fn server(connections: Vec<Vec<Vec<u8>>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            for responses in connections {
+                let (mut server, _) = listener.accept().unwrap();
+
+                for mut response in responses {
+                    let mut request = read_message(&mut server).unwrap();
+
+                    if response == [1] && request[.. 7] != [1, 1, 0, 1, 0, 0, 0] {
+                        let rejection = handshake_rejection(VERSION_1_1_0);
+
+                        server.write_all(&(rejection.len() as i32).to_le_bytes()).unwrap();
+                        server.write_all(&rejection).unwrap();
+
+                        server = listener.accept().unwrap().0;
+                        request = read_message(&mut server).unwrap();
+                    }
+
+                    // Handshake responses start with a non-zero byte.
+                    if request.len() >= 10 && response.len() >= 8 && response[.. 8] == [0; 8] {
+                        response[.. 8].copy_from_slice(&request[2 .. 10]);
+                    }
+
+                    server.write_all(&(response.len() as i32).to_le_bytes()).unwrap();
+                    server.write_all(&response).unwrap();
+                }
+            }
+        });
+
+        address
+    }
+
+    fn handshake_rejection(server_version: Version) -> Vec<u8> {
+        let mut bytes = BytesMut::new();
+
+        0i8.write(&mut bytes).unwrap();
+        server_version.major.write(&mut bytes).unwrap();
+        server_version.minor.write(&mut bytes).unwrap();
+        server_version.patch.write(&mut bytes).unwrap();
+        "Unsupported version".to_string().write(&mut bytes).unwrap();
+
+        bytes.to_vec()
+    }
+
+    fn tcp_with_stream(stream: TcpStream) -> Tcp {
+        Tcp::new(Box::new(stream), Configuration::default())
+    }
+
+    #[test]
+    fn test_read_error() {
+        let message = "class org.apache.ignite.IgniteException: Failed\n\tat Foo.bar(Foo.java:42)\n\tat Foo.baz(Foo.java:7)";
+
+        let mut bytes = BytesMut::new();
+
+        message.to_string().write(&mut bytes).unwrap();
+
+        let err = read_error(1, &mut Bytes::from(bytes.to_vec()), false);
+
+        assert_eq!(err.kind, ErrorKind::Ignite(1));
+        assert_eq!(err.message, message);
+
+        let err = read_error(2, &mut Bytes::from(vec![101u8]), false);
+
+        assert_eq!(err.kind, ErrorKind::Ignite(2));
+        assert_eq!(err.message, "Ignite error: 2");
+    }
+
+    #[test]
+    fn test_strict_status_codes() {
+        let mut bytes = BytesMut::new();
+
+        "New failure".to_string().write(&mut bytes).unwrap();
+
+        let err = read_error(1234, &mut Bytes::from(bytes.to_vec()), false);
+
+        assert_eq!(err.kind, ErrorKind::Ignite(1234));
+        assert_eq!(err.message, "New failure");
+
+        let err = read_error(1234, &mut Bytes::from(bytes.to_vec()), true);
+
+        assert_eq!(err.kind, ErrorKind::UnknownStatus(1234));
+        assert_eq!(err.message, "Unknown status 1234: New failure");
+
+        let err = read_error(1010, &mut Bytes::from(bytes.to_vec()), true);
+
+        assert_eq!(err.kind, ErrorKind::Ignite(1010));
+        assert_eq!(err.message, "New failure");
+
+        for status in [10, 100] {
+            assert_eq!(read_error(status, &mut Bytes::from(bytes.to_vec()), true).kind, ErrorKind::Ignite(status));
+        }
+
+        assert_eq!(read_error(1040, &mut Bytes::from(bytes.to_vec()), true).kind, ErrorKind::UnknownStatus(1040));
+    }
+
+    #[test]
+    fn test_affinity_version() {
+        let mut tcp = tcp();
+
+        tcp.features = FeatureSet::new(VERSION_1_4_0, Vec::new());
+
+        let mut bytes = BytesMut::new();
+
+        0i64.write(&mut bytes).unwrap(); // Request ID.
+        2i16.write(&mut bytes).unwrap(); // Flags.
+        7i64.write(&mut bytes).unwrap();
+        3i32.write(&mut bytes).unwrap();
+        42i32.write(&mut bytes).unwrap(); // Payload.
+
+        let value = tcp.read_response(0, &mut Bytes::from(bytes.to_vec()), i32::read);
+
+        assert_eq!(value, Ok(42));
+        assert_eq!(tcp.affinity_version, Some(AffinityTopologyVersion { major: 7, minor: 3 }));
+
+        let mut bytes = BytesMut::new();
+
+        0i64.write(&mut bytes).unwrap(); // Request ID.
+        1i16.write(&mut bytes).unwrap(); // Flags.
+        1i32.write(&mut bytes).unwrap(); // Status.
+        "Failure".to_string().write(&mut bytes).unwrap();
+
+        let value = tcp.read_response(0, &mut Bytes::from(bytes.to_vec()), i32::read);
+
+        assert_eq!(value.unwrap_err().message, "Failure");
+        assert_eq!(tcp.affinity_version, Some(AffinityTopologyVersion { major: 7, minor: 3 }));
+    }
+
+    #[test]
+    fn test_read_handshake_error() {
+        let mut bytes = BytesMut::new();
+
+        1i16.write(&mut bytes).unwrap();
+        0i16.write(&mut bytes).unwrap();
+        0i16.write(&mut bytes).unwrap();
+        "Unsupported version.".to_string().write(&mut bytes).unwrap();
+        1001i32.write(&mut bytes).unwrap();
+
+        let err = read_handshake_error(&mut Bytes::from(bytes.to_vec()), VERSION).unwrap();
+
+        assert_eq!(err.kind, ErrorKind::Handshake { server_version: Version { major: 1, minor: 0, patch: 0 }, client_version: VERSION });
+        assert_eq!(err.message, "Unsupported version.");
+
+        let mut bytes = BytesMut::new();
+
+        1i16.write(&mut bytes).unwrap();
+        1i16.write(&mut bytes).unwrap();
+        0i16.write(&mut bytes).unwrap();
+        "The user name or password is incorrect".to_string().write(&mut bytes).unwrap();
+        2000i32.write(&mut bytes).unwrap();
+
+        let err = read_handshake_error(&mut Bytes::from(bytes.to_vec()), VERSION).unwrap();
+
+        assert_eq!(err.kind, ErrorKind::Authentication);
+        assert_eq!(err.message, "The user name or password is incorrect");
+    }
+
+    #[test]
+    fn test_read_message_fragmented() {
+        let payload: Vec<u8> = (0 .. 100).collect();
+
+        let mut bytes = (payload.len() as i32).to_le_bytes().to_vec();
+
+        bytes.extend_from_slice(&payload);
+        bytes.push(42); // Start of the next message.
+
+        let mut reader = OneByteReader { bytes, pos: 0 };
+
+        assert_eq!(read_message(&mut reader).unwrap().to_vec(), payload);
+        assert_eq!(reader.pos, 104);
+
+        let mut reader = OneByteReader { bytes: vec![10, 0, 0, 0, 1, 2], pos: 0 };
+
+        assert_eq!(read_message(&mut reader).unwrap_err().kind, ErrorKind::Network);
+
+        let mut reader = OneByteReader { bytes: vec![0xff, 0xff, 0xff, 0xff], pos: 0 };
+
+        assert_eq!(read_message(&mut reader).unwrap_err().kind, ErrorKind::Network);
+    }
+
+    #[test]
+    fn test_connection_state() {
+        let handshake = vec![1u8];
+
+        let address = server(vec![vec![handshake.clone()], vec![handshake]]);
+
+        let mut tcp = Tcp::connect(Configuration::default().address(&address)).unwrap();
+
+        assert_eq!(tcp.state, ConnectionState::Connected);
+
+        assert!(tcp.reconnect().is_ok());
+        assert_eq!(tcp.state, ConnectionState::Connected);
+
+        // No more connections are accepted.
+        assert!(tcp.reconnect().is_err());
+        assert_eq!(tcp.state, ConnectionState::Closed);
+
+        let result = tcp.execute(1050, |_| Ok(()), |_| Ok(()));
+
+        assert_eq!(result.unwrap_err().kind, ErrorKind::Network);
+        assert_eq!(tcp.state, ConnectionState::Closed);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket() {
+        use std::os::unix::net::UnixListener;
+
+        let path = std::env::temp_dir().join(format!("ignite-client-test-{}.sock", std::process::id()));
+
+        let _ = std::fs::remove_file(&path);
+
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let server = thread::spawn(move || {
+            let (mut server, _) = listener.accept().unwrap();
+
+            let mut handshake = BytesMut::new();
+
+            1i8.write(&mut handshake).unwrap();
+            Value::I8Vec(Vec::new()).write(&mut handshake).unwrap(); // Features.
+            Uuid::nil().write(&mut handshake).unwrap(); // Node ID.
+
+            let mut response = 1i64.to_le_bytes().to_vec(); // Request ID.
+
+            response.extend_from_slice(&0i16.to_le_bytes()); // Flags.
+            response.extend_from_slice(&42i32.to_le_bytes());
+
+            for response in [handshake.to_vec(), response] {
+                read_message(&mut server).unwrap();
+
+                server.write_all(&(response.len() as i32).to_le_bytes()).unwrap();
+                server.write_all(&response).unwrap();
+            }
+        });
+
+        let mut tcp = Tcp::connect(Configuration::default().unix_socket(&path)).unwrap();
+
+        assert_eq!(tcp.execute(1050, |_| Ok(()), i32::read), Ok(42));
+
+        server.join().unwrap();
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_handshake_request() {
+        let header = vec![1, 1, 0, 1, 0, 0, 0, 2];
+
+        let request = handshake_request(&Configuration::default().password("secret"), VERSION_1_1_0).unwrap();
+
+        assert_eq!(request.to_vec(), header);
+
+        let request = handshake_request(&Configuration::default().username("user"), VERSION_1_1_0).unwrap();
+
+        let mut expected = BytesMut::from(header.as_slice());
+
+        "user".to_string().write(&mut expected).unwrap();
+        expected.extend_from_slice(&[101]); // Null password.
+
+        assert_eq!(request, expected);
+
+        let request = handshake_request(&Configuration::default().username("user").password("secret"), VERSION_1_1_0).unwrap();
+
+        let mut expected = BytesMut::from(header.as_slice());
+
+        "user".to_string().write(&mut expected).unwrap();
+        "secret".to_string().write(&mut expected).unwrap();
+
+        assert_eq!(request, expected);
+
+        // Empty client feature bitmask.
+        let request = handshake_request(&Configuration::default(), VERSION).unwrap();
+
+        assert_eq!(request.to_vec(), vec![1, 1, 0, 7, 0, 0, 0, 2, 12, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_read_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        let config = Configuration::default()
+            .address(&listener.local_addr().unwrap().to_string())
+            .read_timeout(Duration::from_millis(100));
+
+        let start = Instant::now();
+
+        // The handshake is accepted by the OS but never answered.
+        let err = Tcp::connect(config).err().unwrap();
+
+        assert_eq!(err.kind, ErrorKind::Network);
+        assert!(err.message.starts_with("Timed out"), "{}", err.message);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_connect_timeout() {
+        // Non-routable address that drops packets (or fails right away without a route).
+        let config = Configuration::default()
+            .address("10.255.255.1:10800")
+            .connect_timeout(Duration::from_millis(200));
+
+        let start = Instant::now();
+
+        assert_eq!(Tcp::connect(config).err().unwrap().kind, ErrorKind::Network);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_retry_on_reconnect() {
+        let mut ok = 0i64.to_le_bytes().to_vec(); // Request ID.
+
+        ok.extend_from_slice(&0i32.to_le_bytes());
+
+        let response = |value: i32| {
+            let mut response = ok.clone();
+
+            response.extend_from_slice(&value.to_le_bytes());
+
+            response
+        };
+
+        // Server drops the connection after the first request.
+        let connections = |second: i32| vec![vec![vec![1], response(1)], vec![vec![1], response(second)]];
+
+        let mut tcp = Tcp::connect(Configuration::default().address(&server(connections(2))).reconnect(true)).unwrap();
+
+        assert_eq!(tcp.execute_op(1000, true, |_| Ok(()), i32::read), Ok(1));
+        assert_eq!(tcp.execute_op(1000, true, |_| Ok(()), i32::read), Ok(2));
+
+        // Writes are not retried, but the next request goes to the new connection.
+        let mut tcp = Tcp::connect(Configuration::default().address(&server(connections(2))).reconnect(true)).unwrap();
+
+        assert_eq!(tcp.execute(1001, |_| Ok(()), i32::read), Ok(1));
+        assert_eq!(tcp.execute(1001, |_| Ok(()), i32::read).unwrap_err().kind, ErrorKind::Network);
+        assert_eq!(tcp.execute(1001, |_| Ok(()), i32::read), Ok(2));
+
+        // Without retries, the connection stays closed.
+        let mut tcp = Tcp::connect(Configuration::default().address(&server(connections(3)))).unwrap();
+
+        assert_eq!(tcp.execute(1000, |_| Ok(()), i32::read), Ok(1));
+        assert_eq!(tcp.execute(1000, |_| Ok(()), i32::read).unwrap_err().kind, ErrorKind::Network);
+        assert_eq!(tcp.state, ConnectionState::Closed);
+        assert_eq!(tcp.execute(1000, |_| Ok(()), i32::read).unwrap_err().kind, ErrorKind::Network);
+    }
+
+    #[test]
+    fn test_failover() {
+        // Nothing listens on a port that was just released.
+        let bad = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let first = server(vec![vec![vec![1]], vec![vec![1]]]);
+        let second = server(vec![vec![vec![1]]]);
+
+        let mut tcp = Tcp::connect(Configuration::default().addresses(&[&bad, &first, &second])).unwrap();
+
+        assert_eq!(tcp.endpoint, Some(first.clone()));
+
+        // Reconnects start with the current endpoint.
+        tcp.endpoint = Some(second.clone());
+
+        assert_eq!(tcp.reconnect(), Ok(()));
+        assert_eq!(tcp.endpoint, Some(second));
+
+        assert_eq!(Tcp::connect(Configuration::default().addresses(&[&bad])).err().unwrap().kind, ErrorKind::Network);
+        assert_eq!(Tcp::connect(Configuration::default().addresses(&[])).err().unwrap().kind, ErrorKind::Configuration);
+    }
+
+    #[cfg(feature = "tls")]
+    #[test]
+    fn test_tls_plain_server() {
+        use crate::configuration::TlsConfiguration;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        // Plain server that closes the connection instead of negotiating TLS.
+        thread::spawn(move || drop(listener.accept().unwrap()));
+
+        let err = Tcp::connect(Configuration::default().address(&address).tls(TlsConfiguration::default())).err().unwrap();
+
+        assert_eq!(err.kind, ErrorKind::Network);
+        assert!(err.message.starts_with("TLS error"), "{}", err.message);
+    }
+
+    #[test]
+    fn test_middleware() {
+        let ok = vec![0u8; 12]; // Request ID and status.
+
+        let mut tcp = tcp_with_responses(vec![ok.clone(), ok]);
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let responses = Arc::new(Mutex::new(Vec::new()));
+
+        let requests_clone = requests.clone();
+        let responses_clone = responses.clone();
+
+        tcp.configuration = Configuration::default()
+            .on_request(move |op, request| {
+                requests_clone.lock().unwrap().push(op);
+
+                if op == 1000 {
+                    return Err(Error::new(ErrorKind::InvalidArgument, "Rejected".to_string()));
+                }
+
+                assert_eq!(request.len(), 10); // Op code and request ID.
+
+                Ok(())
+            })
+            .on_response(move |op, response| {
+                responses_clone.lock().unwrap().push((op, response.len()));
+
+                Ok(())
+            });
+
+        assert_eq!(tcp.execute(1050, |_| Ok(()), |_| Ok(())), Ok(()));
+        assert_eq!(tcp.execute(1000, |_| Ok(()), |_| Ok(())).unwrap_err().kind, ErrorKind::InvalidArgument);
+        assert_eq!(tcp.execute(1051, |_| Ok(()), |_| Ok(())), Ok(()));
+
+        assert_eq!(*requests.lock().unwrap(), vec![1050, 1000, 1051]);
+        assert_eq!(*responses.lock().unwrap(), vec![(1050, 12), (1051, 12)]);
+    }
+
+    #[test]
+    fn test_request_ids() {
+        let ok = vec![0u8; 12]; // Request ID (echoed by the server) and status.
+
+        let mut wrong_id = 99i64.to_le_bytes().to_vec();
+
+        wrong_id.extend_from_slice(&0i32.to_le_bytes());
+
+        let mut tcp = tcp_with_responses(vec![ok.clone(), ok, wrong_id]);
+
+        let ids = Arc::new(Mutex::new(Vec::new()));
+        let ids_clone = ids.clone();
+
+        tcp.configuration = Configuration::default().on_request(move |_, request| {
+            ids_clone.lock().unwrap().push((&request[2 .. 10]).get_i64_le());
+
+            Ok(())
+        });
+
+        assert_eq!(tcp.execute(1050, |_| Ok(()), |_| Ok(())), Ok(()));
+        assert_eq!(tcp.execute(1050, |_| Ok(()), |_| Ok(())), Ok(()));
+
+        let err = tcp.execute(1050, |_| Ok(()), |_| Ok(())).unwrap_err();
+
+        assert_eq!(err.kind, ErrorKind::Network);
+        assert_eq!(err.message, "Response ID 99 doesn't match request ID 3.");
+        assert_eq!(*ids.lock().unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_read_response_header() {
+        let response = |header: &[u8], payload: &[u8]| {
+            let mut bytes = 0i64.to_le_bytes().to_vec(); // Request ID.
+
+            bytes.extend_from_slice(header);
+            bytes.extend_from_slice(payload);
+
+            Bytes::from(bytes)
+        };
+
+        let mut error = BytesMut::new();
+
+        "Failure".to_string().write(&mut error).unwrap();
+
+        // Legacy header: status only.
+        let mut tcp = tcp();
+
+        assert_eq!(tcp.read_response(0, &mut response(&0i32.to_le_bytes(), &42i32.to_le_bytes()), i32::read), Ok(42));
+
+        let err = tcp.read_response(0, &mut response(&1010i32.to_le_bytes(), &error), i32::read).unwrap_err();
+
+        assert_eq!(err.kind, ErrorKind::Ignite(1010));
+        assert_eq!(err.message, "Failure");
+
+        // Flags header: status only follows the error flag.
+        tcp.features = FeatureSet::new(VERSION_1_4_0, Vec::new());
+
+        assert_eq!(tcp.read_response(0, &mut response(&0i16.to_le_bytes(), &42i32.to_le_bytes()), i32::read), Ok(42));
+
+        let mut header = 1i16.to_le_bytes().to_vec();
 
-        self.stream.read_exact(&mut len)?;
+        header.extend_from_slice(&1010i32.to_le_bytes());
 
-        let len = Bytes::from(len.to_vec()).get_i32_le();
+        let err = tcp.read_response(0, &mut response(&header, &error), i32::read).unwrap_err();
 
-        let mut msg = vec![0u8; len as usize];
+        assert_eq!(err.kind, ErrorKind::Ignite(1010));
+        assert_eq!(err.message, "Failure");
+        assert_eq!(tcp.affinity_version, None);
 
-        self.stream.read_exact(&mut msg)?;
+        let err = tcp.read_response(0, &mut response(&4i16.to_le_bytes(), &[]), i32::read).unwrap_err();
 
-        Ok(Bytes::from(msg))
+        assert_eq!(err.kind, ErrorKind::NotSupported);
     }
 }
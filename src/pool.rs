@@ -0,0 +1,356 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex, MutexGuard, Condvar};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::{Client, ClientDebug};
+use crate::binary::Value;
+use crate::cache::PeekMode;
+use crate::configuration::Configuration;
+use crate::error::Result;
+use crate::network::{Tcp, ConnectionState};
+use crate::query::QueryResult;
+
+struct Pool {
+    configuration: Configuration,
+    connections: Mutex<Connections>,
+    available: Condvar,
+    // Shared with every connection of the pool.
+    reconnecting: Arc<AtomicUsize>,
+}
+
+struct Connections {
+    idle: Vec<Tcp>,
+    // Idle and checked out connections. Lower than the pool size if a connection was lost.
+    open: usize,
+}
+
+impl Pool {
+    fn lock(&self) -> MutexGuard<'_, Connections> {
+        // Connections are only pushed and popped under the lock, so a poisoned lock holds valid state.
+        self.connections.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn checkout(&self) -> Result<Tcp> {
+        let mut connections = self.lock();
+
+        loop {
+            if let Some(tcp) = connections.idle.pop() {
+                return Ok(tcp);
+            }
+
+            if connections.open < self.configuration.pool_size {
+                connections.open += 1;
+
+                drop(connections);
+
+                return connect(&self.configuration, &self.reconnecting).inspect_err(|_| self.release(None));
+            }
+
+            connections = self.available.wait(connections).unwrap_or_else(|err| err.into_inner());
+        }
+    }
+
+    // `None` if the connection was lost, so a new one can be opened in its place.
+    fn release(&self, tcp: Option<Tcp>) {
+        let mut connections = self.lock();
+
+        match tcp {
+            Some(tcp) => connections.idle.push(tcp),
+            None => connections.open -= 1,
+        }
+
+        self.available.notify_one();
+    }
+
+    // `R` must not hold the connection (e.g. a `Cache` or an open cursor), or the connection can't
+    // be returned to the pool and is closed instead.
+    fn run<R, F>(&self, f: F) -> Result<R>
+        where
+            F: FnOnce(&Client) -> Result<R>,
+    {
+        let mut checkout = Checkout { pool: self, tcp: Some(self.checkout()?) };
+
+        let client = Client { tcp: Rc::new(RefCell::new(checkout.tcp.take().unwrap())) };
+
+        let result = f(&client);
+
+        debug_assert_eq!(Rc::strong_count(&client.tcp), 1, "The result of a pooled operation holds the connection.");
+
+        // Closed connections are dropped, so the next checkout opens a new one.
+        checkout.tcp = Rc::try_unwrap(client.tcp).ok()
+            .map(RefCell::into_inner)
+            .filter(|tcp| tcp.state != ConnectionState::Closed);
+
+        result
+    }
+}
+
+fn connect(configuration: &Configuration, reconnecting: &Arc<AtomicUsize>) -> Result<Tcp> {
+    let mut tcp = Tcp::connect(configuration.clone())?;
+
+    tcp.reconnecting = reconnecting.clone();
+
+    Ok(tcp)
+}
+
+// Returns the connection to the pool even if the operation panics.
+struct Checkout<'a> {
+    pool: &'a Pool,
+    tcp: Option<Tcp>,
+}
+
+impl Drop for Checkout<'_> {
+    fn drop(&mut self) {
+        self.pool.release(self.tcp.take());
+    }
+}
+
+/// Client that can be shared between threads. Each operation checks out one of
+/// `Configuration::pool_size` connections and returns it when done, waiting if all of
+/// them are in use.
+///
+/// Queries fetch all pages before returning, and transactions are not supported.
+#[derive(Clone)]
+pub struct PooledClient {
+    pool: Arc<Pool>,
+}
+
+impl PooledClient {
+    /// Opens all connections up front. Lost connections are reopened on demand.
+    pub fn start(configuration: Configuration) -> Result<PooledClient> {
+        configuration.validate()?;
+
+        let reconnecting = Arc::new(AtomicUsize::new(0));
+
+        let mut idle = Vec::with_capacity(configuration.pool_size);
+
+        for _ in 0 .. configuration.pool_size {
+            idle.push(connect(&configuration, &reconnecting)?);
+        }
+
+        let connections = Connections { open: idle.len(), idle };
+
+        Ok(PooledClient {
+            pool: Arc::new(Pool { configuration, connections: Mutex::new(connections), available: Condvar::new(), reconnecting }),
+        })
+    }
+
+    /// `Reconnecting` while one of the connections is being reopened, so callers can shed load
+    /// instead of waiting for a connection. `Closed` if all connections were lost; they are
+    /// reopened by the next operation. Doesn't wait for a connection.
+    pub fn connection_state(&self) -> ConnectionState {
+        if self.pool.reconnecting.load(Ordering::SeqCst) > 0 {
+            ConnectionState::Reconnecting
+        }
+        else if self.pool.lock().open == 0 {
+            ConnectionState::Closed
+        }
+        else {
+            ConnectionState::Connected
+        }
+    }
+
+    pub fn cache_names(&self) -> Result<Vec<String>> {
+        self.pool.run(|client| client.cache_names())
+    }
+
+    pub fn get_or_create_cache(&self, name: &str) -> Result<PooledCache> {
+        self.pool.run(|client| client.get_or_create_cache(name).map(|_| ()))?;
+
+        Ok(self.cache(name))
+    }
+
+    pub fn cache(&self, name: &str) -> PooledCache {
+        PooledCache { name: name.to_string(), pool: self.pool.clone() }
+    }
+
+    /// Same as `Client::debug_state` for one of the pooled connections. Waits for a connection
+    /// if all of them are in use; doesn't send anything to the server.
+    pub fn debug_state(&self) -> Result<ClientDebug> {
+        let in_use = {
+            let connections = self.pool.lock();
+
+            connections.open - connections.idle.len()
+        };
+
+        let state = self.pool.run(|client| Ok(client.debug_state()))?;
+
+        Ok(ClientDebug { pool_size: self.pool.configuration.pool_size, connections_in_use: in_use, ..state })
+    }
+}
+
+/// Cache handle of a `PooledClient`, see `Cache` for the operations.
+#[derive(Clone)]
+pub struct PooledCache {
+    name: String,
+    pool: Arc<Pool>,
+}
+
+impl PooledCache {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get(&self, key: &Value) -> Result<Option<Value>> {
+        self.pool.run(|client| client.cache(&self.name).get(key))
+    }
+
+    pub fn put(&self, key: &Value, value: &Value) -> Result<()> {
+        self.pool.run(|client| client.cache(&self.name).put(key, value))
+    }
+
+    pub fn put_if_absent(&self, key: &Value, value: &Value) -> Result<bool> {
+        self.pool.run(|client| client.cache(&self.name).put_if_absent(key, value))
+    }
+
+    pub fn get_all(&self, keys: &[Value]) -> Result<Vec<(Value, Option<Value>)>> {
+        self.pool.run(|client| client.cache(&self.name).get_all(keys))
+    }
+
+    pub fn put_all(&self, entries: &[(Value, Value)]) -> Result<()> {
+        self.pool.run(|client| client.cache(&self.name).put_all(entries))
+    }
+
+    pub fn get_and_put(&self, key: &Value, value: &Value) -> Result<Option<Value>> {
+        self.pool.run(|client| client.cache(&self.name).get_and_put(key, value))
+    }
+
+    pub fn get_and_remove(&self, key: &Value) -> Result<Option<Value>> {
+        self.pool.run(|client| client.cache(&self.name).get_and_remove(key))
+    }
+
+    pub fn replace(&self, key: &Value, value: &Value) -> Result<bool> {
+        self.pool.run(|client| client.cache(&self.name).replace(key, value))
+    }
+
+    pub fn contains_key(&self, key: &Value) -> Result<bool> {
+        self.pool.run(|client| client.cache(&self.name).contains_key(key))
+    }
+
+    pub fn remove_key(&self, key: &Value) -> Result<bool> {
+        self.pool.run(|client| client.cache(&self.name).remove_key(key))
+    }
+
+    pub fn remove_all(&self) -> Result<()> {
+        self.pool.run(|client| client.cache(&self.name).remove_all())
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        self.pool.run(|client| client.cache(&self.name).clear())
+    }
+
+    pub fn size(&self, peek_modes: &[PeekMode]) -> Result<i64> {
+        self.pool.run(|client| client.cache(&self.name).size(peek_modes))
+    }
+
+    /// Fetches all pages over the same connection before returning.
+    pub fn query_sql_fields(&self, sql: &str, args: &[Value]) -> Result<QueryResult> {
+        self.pool.run(|client| client.cache(&self.name).query_sql_fields(sql, args)?.collect_result())
+    }
+}
+
+// === Tests
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use crate::{ConnectionState, PooledClient, PooledCache};
+    use crate::binary::Value;
+    use crate::mock::MockServer;
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        assert_send_sync::<PooledClient>();
+        assert_send_sync::<PooledCache>();
+    }
+
+    #[test]
+    fn test_concurrent_operations() {
+        let server = MockServer::start();
+
+        let client = PooledClient::start(server.configuration().pool_size(3)).unwrap();
+
+        let threads: Vec<_> = (0 .. 8).map(|t| {
+            let cache = client.cache("test-cache");
+
+            thread::spawn(move || {
+                for i in t * 100 .. (t + 1) * 100 {
+                    assert_eq!(cache.put(&Value::I32(i), &Value::I32(i * 2)), Ok(()));
+                    assert_eq!(cache.get(&Value::I32(i)), Ok(Some(Value::I32(i * 2))));
+                }
+            })
+        }).collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert_eq!(client.cache("test-cache").size(&[]), Ok(800));
+
+        let state = client.debug_state().unwrap();
+
+        assert_eq!(state.pool_size, 3);
+        assert_eq!(state.connections_in_use, 0);
+    }
+
+    #[test]
+    fn test_failed_operation_returns_connection() {
+        let server = MockServer::start();
+
+        let client = PooledClient::start(server.configuration().pool_size(1)).unwrap();
+
+        server.fail(1000, 1, "Failed");
+
+        assert!(client.cache("test-cache").get(&Value::I32(1)).is_err());
+        assert_eq!(client.cache("test-cache").get(&Value::I32(1)), Ok(None));
+        assert_eq!(client.debug_state().unwrap().connections_in_use, 0);
+    }
+
+    #[test]
+    fn test_get_or_create_cache_keeps_connection() {
+        let server = MockServer::start();
+
+        let client = PooledClient::start(server.configuration().pool_size(1)).unwrap();
+
+        server.respond(1052, Vec::new());
+
+        assert_eq!(client.get_or_create_cache("test-cache").unwrap().name(), "test-cache");
+        assert_eq!(client.debug_state().unwrap().connections_in_use, 0);
+
+        // The connection went back to the pool instead of being replaced.
+        assert_eq!(client.cache("test-cache").get(&Value::I32(1)), Ok(None));
+        assert_eq!(server.handshake_count(), 1);
+    }
+
+    #[test]
+    fn test_connection_state() {
+        let server = MockServer::start();
+
+        let client = PooledClient::start(server.configuration().pool_size(1).reconnect(true)).unwrap();
+
+        assert_eq!(client.connection_state(), ConnectionState::Connected);
+
+        server.disconnect(1000);
+        server.delay_handshakes(Duration::from_millis(300));
+
+        let cache = client.cache("test-cache");
+        let get = thread::spawn(move || cache.get(&Value::I32(1)));
+
+        // Readable while the connection is checked out and reopened.
+        let start = Instant::now();
+
+        while client.connection_state() != ConnectionState::Reconnecting {
+            assert!(start.elapsed() < Duration::from_secs(5), "Never saw the reconnect.");
+
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(get.join().unwrap(), Ok(None));
+        assert_eq!(client.connection_state(), ConnectionState::Connected);
+    }
+}
@@ -0,0 +1,1097 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::vec::IntoIter;
+use std::convert::TryFrom;
+
+use bytes::{BytesMut, Bytes};
+
+use crate::binary::{Value, IgniteWrite, IgniteRead};
+use crate::error::{Result, Error, ErrorKind};
+use crate::network::Tcp;
+use crate::configuration::{Configuration, DEFAULT_PAGE_SIZE};
+
+const STATUS_RESOURCE_DOES_NOT_EXIST: i32 = 1011;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum StatementType {
+    Any = 0,
+    Select = 1,
+    Update = 2,
+}
+
+pub struct SqlFieldsQuery {
+    pub(crate) sql: String,
+    pub(crate) args: Vec<Value>,
+    pub(crate) schema: Option<String>,
+    pub(crate) page_size: Option<i32>,
+    pub(crate) max_rows: i32,
+    pub(crate) statement_type: StatementType,
+    pub(crate) distributed_joins: bool,
+    pub(crate) local: bool,
+    pub(crate) replicated_only: bool,
+    pub(crate) enforce_join_order: bool,
+    pub(crate) collocated: bool,
+    pub(crate) lazy: bool,
+    pub(crate) timeout: i64,
+    pub(crate) include_field_names: bool,
+}
+
+impl SqlFieldsQuery {
+    pub fn new(sql: &str) -> SqlFieldsQuery {
+        SqlFieldsQuery {
+            sql: sql.to_string(),
+            args: Vec::new(),
+            schema: None,
+            page_size: None,
+            max_rows: -1,
+            statement_type: StatementType::Any,
+            distributed_joins: false,
+            local: false,
+            replicated_only: false,
+            enforce_join_order: false,
+            collocated: false,
+            lazy: false,
+            timeout: 0,
+            include_field_names: false,
+        }
+    }
+
+    pub fn arg(mut self, arg: Value) -> SqlFieldsQuery {
+        self.args.push(arg);
+
+        self
+    }
+
+    pub fn args(mut self, args: &[Value]) -> SqlFieldsQuery {
+        self.args.extend_from_slice(args);
+
+        self
+    }
+
+    pub fn schema(mut self, schema: &str) -> SqlFieldsQuery {
+        self.schema = Some(schema.to_string());
+
+        self
+    }
+
+    pub fn page_size(mut self, page_size: i32) -> SqlFieldsQuery {
+        self.page_size = Some(page_size);
+
+        self
+    }
+
+    pub fn max_rows(mut self, max_rows: i32) -> SqlFieldsQuery {
+        self.max_rows = max_rows;
+
+        self
+    }
+
+    /// The server rejects statements that don't match (e.g. DML with `StatementType::Select`).
+    pub fn statement_type(mut self, statement_type: StatementType) -> SqlFieldsQuery {
+        self.statement_type = statement_type;
+
+        self
+    }
+
+    pub fn distributed_joins(mut self, distributed_joins: bool) -> SqlFieldsQuery {
+        self.distributed_joins = distributed_joins;
+
+        self
+    }
+
+    pub fn local(mut self, local: bool) -> SqlFieldsQuery {
+        self.local = local;
+
+        self
+    }
+
+    pub fn replicated_only(mut self, replicated_only: bool) -> SqlFieldsQuery {
+        self.replicated_only = replicated_only;
+
+        self
+    }
+
+    pub fn enforce_join_order(mut self, enforce_join_order: bool) -> SqlFieldsQuery {
+        self.enforce_join_order = enforce_join_order;
+
+        self
+    }
+
+    pub fn collocated(mut self, collocated: bool) -> SqlFieldsQuery {
+        self.collocated = collocated;
+
+        self
+    }
+
+    pub fn lazy(mut self, lazy: bool) -> SqlFieldsQuery {
+        self.lazy = lazy;
+
+        self
+    }
+
+    pub fn timeout(mut self, timeout: i64) -> SqlFieldsQuery {
+        self.timeout = timeout;
+
+        self
+    }
+
+    pub fn include_field_names(mut self, include_field_names: bool) -> SqlFieldsQuery {
+        self.include_field_names = include_field_names;
+
+        self
+    }
+
+    pub(crate) fn default_page_size(mut self, page_size: i32) -> SqlFieldsQuery {
+        self.page_size.get_or_insert(page_size);
+
+        self
+    }
+
+    pub(crate) fn default_schema(mut self, schema: Option<&str>) -> SqlFieldsQuery {
+        if self.schema.is_none() {
+            self.schema = schema.map(str::to_string);
+        }
+
+        self
+    }
+
+    // Applies client-wide defaults for anything the query doesn't set.
+    pub(crate) fn with_defaults(self, configuration: &Configuration) -> SqlFieldsQuery {
+        self.default_page_size(configuration.default_page_size)
+            .default_schema(configuration.default_sql_schema.as_deref())
+    }
+}
+
+impl IgniteWrite for SqlFieldsQuery {
+    fn write(&self, bytes: &mut BytesMut) -> Result<()> {
+        self.schema.write(bytes)?;
+        self.page_size.unwrap_or(DEFAULT_PAGE_SIZE).write(bytes)?;
+        self.max_rows.write(bytes)?;
+        self.sql.write(bytes)?;
+        self.args.write(bytes)?;
+        (self.statement_type as i8).write(bytes)?;
+        self.distributed_joins.write(bytes)?;
+        self.local.write(bytes)?;
+        self.replicated_only.write(bytes)?;
+        self.enforce_join_order.write(bytes)?;
+        self.collocated.write(bytes)?;
+        self.lazy.write(bytes)?;
+        self.timeout.write(bytes)?;
+        self.include_field_names.write(bytes)?;
+
+        Ok(())
+    }
+}
+
+pub struct ScanQuery {
+    pub(crate) page_size: Option<i32>,
+    pub(crate) partition: Option<i32>,
+    pub(crate) local: bool,
+}
+
+impl ScanQuery {
+    pub fn new() -> ScanQuery {
+        ScanQuery {
+            page_size: None,
+            partition: None,
+            local: false,
+        }
+    }
+
+    pub fn page_size(mut self, page_size: i32) -> ScanQuery {
+        self.page_size = Some(page_size);
+
+        self
+    }
+
+    pub fn partition(mut self, partition: i32) -> ScanQuery {
+        self.partition = Some(partition);
+
+        self
+    }
+
+    /// Scan only the entries stored on the node the client is connected to. Defaults to `false`.
+    pub fn local(mut self, local: bool) -> ScanQuery {
+        self.local = local;
+
+        self
+    }
+
+    pub(crate) fn default_page_size(mut self, page_size: i32) -> ScanQuery {
+        self.page_size.get_or_insert(page_size);
+
+        self
+    }
+}
+
+impl Default for ScanQuery {
+    fn default() -> ScanQuery {
+        ScanQuery::new()
+    }
+}
+
+impl IgniteWrite for ScanQuery {
+    fn write(&self, bytes: &mut BytesMut) -> Result<()> {
+        <Option<Value>>::None.write(bytes)?; // Filter.
+        self.page_size.unwrap_or(DEFAULT_PAGE_SIZE).write(bytes)?;
+        self.partition.unwrap_or(-1).write(bytes)?;
+        self.local.write(bytes)?;
+
+        Ok(())
+    }
+}
+
+/// Query over the value type registered with a `QueryEntity`. `sql` is the `WHERE` clause;
+/// matching entries are returned as key/value pairs.
+pub struct SqlQuery {
+    pub(crate) type_name: String,
+    pub(crate) sql: String,
+    pub(crate) args: Vec<Value>,
+    pub(crate) page_size: Option<i32>,
+    pub(crate) distributed_joins: bool,
+    pub(crate) local: bool,
+    pub(crate) replicated_only: bool,
+    pub(crate) timeout: i64,
+}
+
+impl SqlQuery {
+    pub fn new(type_name: &str, sql: &str) -> SqlQuery {
+        SqlQuery {
+            type_name: type_name.to_string(),
+            sql: sql.to_string(),
+            args: Vec::new(),
+            page_size: None,
+            distributed_joins: false,
+            local: false,
+            replicated_only: false,
+            timeout: 0,
+        }
+    }
+
+    pub fn arg(mut self, arg: Value) -> SqlQuery {
+        self.args.push(arg);
+
+        self
+    }
+
+    pub fn args(mut self, args: &[Value]) -> SqlQuery {
+        self.args.extend_from_slice(args);
+
+        self
+    }
+
+    pub fn page_size(mut self, page_size: i32) -> SqlQuery {
+        self.page_size = Some(page_size);
+
+        self
+    }
+
+    pub fn distributed_joins(mut self, distributed_joins: bool) -> SqlQuery {
+        self.distributed_joins = distributed_joins;
+
+        self
+    }
+
+    pub fn local(mut self, local: bool) -> SqlQuery {
+        self.local = local;
+
+        self
+    }
+
+    pub fn replicated_only(mut self, replicated_only: bool) -> SqlQuery {
+        self.replicated_only = replicated_only;
+
+        self
+    }
+
+    pub fn timeout(mut self, timeout: i64) -> SqlQuery {
+        self.timeout = timeout;
+
+        self
+    }
+
+    pub(crate) fn default_page_size(mut self, page_size: i32) -> SqlQuery {
+        self.page_size.get_or_insert(page_size);
+
+        self
+    }
+}
+
+impl IgniteWrite for SqlQuery {
+    fn write(&self, bytes: &mut BytesMut) -> Result<()> {
+        self.type_name.write(bytes)?;
+        self.sql.write(bytes)?;
+        self.args.write(bytes)?;
+        self.distributed_joins.write(bytes)?;
+        self.local.write(bytes)?;
+        self.replicated_only.write(bytes)?;
+        self.page_size.unwrap_or(DEFAULT_PAGE_SIZE).write(bytes)?;
+        self.timeout.write(bytes)?;
+
+        Ok(())
+    }
+}
+
+pub type Row = Vec<Option<Value>>;
+
+/// Maps a result row to a user type. Usually derived with `#[derive(FromRow)]`, which binds
+/// each field to the column at the same position, or by name with `#[sql(column = "...")]`.
+/// `Option` fields accept nulls.
+pub trait FromRow: Sized {
+    fn from_row(row: Row, columns: &[String]) -> Result<Self>;
+}
+
+/// Used by the `FromRow` derive.
+#[doc(hidden)]
+pub struct RowReader<'a> {
+    row: Row,
+    columns: &'a [String],
+}
+
+impl<'a> RowReader<'a> {
+    pub fn new(row: Row, columns: &'a [String], field_count: usize) -> Result<RowReader<'a>> {
+        if row.len() != field_count {
+            return Err(Error::new(ErrorKind::Serde,
+                format!("Column count mismatch: row has {} columns, expected {}", row.len(), field_count)));
+        }
+
+        Ok(RowReader { row, columns })
+    }
+
+    pub fn by_index(&mut self, index: usize) -> Option<Value> {
+        self.row[index].take()
+    }
+
+    pub fn by_name(&mut self, name: &str) -> Result<Option<Value>> {
+        match self.columns.iter().position(|column| column.eq_ignore_ascii_case(name)) {
+            Some(index) => Ok(self.by_index(index)),
+            None => Err(Error::new(ErrorKind::Serde, format!("Column not found: {}", name))),
+        }
+    }
+
+    pub fn required<T>(value: Option<Value>, field: &str) -> Result<T>
+        where
+            T: TryFrom<Value>,
+            Error: From<T::Error>,
+    {
+        match value {
+            Some(value) => Ok(T::try_from(value)?),
+            None => Err(Error::new(ErrorKind::Serde, format!("Null value for field: {}", field))),
+        }
+    }
+
+    pub fn optional<T>(value: Option<Value>, _field: &str) -> Result<Option<T>>
+        where
+            T: TryFrom<Value>,
+            Error: From<T::Error>,
+    {
+        match value {
+            Some(value) => Ok(Some(T::try_from(value)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Row>,
+}
+
+pub struct FieldsCursor {
+    tcp: Rc<RefCell<Tcp>>,
+    id: i64,
+    columns: Vec<String>,
+    column_count: usize,
+    page: IntoIter<Row>,
+    has_more: bool,
+    permit: Option<CursorPermit>,
+    fail_on_close: bool,
+}
+
+impl FieldsCursor {
+    pub(crate) fn read(tcp: Rc<RefCell<Tcp>>, include_field_names: bool, bytes: &mut Bytes) -> Result<FieldsCursor> {
+        let id = i64::read(bytes)?;
+        let column_count = i32::read(bytes)? as usize;
+
+        let mut columns = Vec::new();
+
+        if include_field_names {
+            for _ in 0 .. column_count {
+                columns.push(String::read(bytes)?);
+            }
+        }
+
+        let (page, has_more) = read_page(bytes, column_count)?;
+
+        Ok(FieldsCursor {
+            tcp,
+            id,
+            columns,
+            column_count,
+            page: page.into_iter(),
+            has_more,
+            permit: None,
+            fail_on_close: false,
+        })
+    }
+
+    // Only cursors that are still open on the server hold a permit.
+    pub(crate) fn with_permit(mut self, permit: CursorPermit) -> FieldsCursor {
+        if self.has_more {
+            self.permit = Some(permit);
+        }
+
+        self
+    }
+
+    /// By default, iteration just stops if the server closes the cursor (e.g. on timeout).
+    pub fn fail_on_close(mut self, fail_on_close: bool) -> FieldsCursor {
+        self.fail_on_close = fail_on_close;
+
+        self
+    }
+
+    /// Releases the server cursor without fetching the remaining pages.
+    pub fn close(mut self) -> Result<()> {
+        self.permit = None;
+
+        // Exhausted cursors are already closed by the server; this also keeps `drop` from closing twice.
+        if std::mem::replace(&mut self.has_more, false) {
+            close_resource(&self.tcp, self.id)
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    pub fn columns(&self) -> &[String] {
+        self.columns.as_slice()
+    }
+
+    pub fn collect_result(mut self) -> Result<QueryResult> {
+        let columns = std::mem::take(&mut self.columns);
+        let rows = self.by_ref().collect::<Result<Vec<Row>>>()?;
+
+        Ok(QueryResult { columns, rows })
+    }
+
+    fn fetch_page(&mut self) -> Result<()> {
+        let id = self.id;
+        let column_count = self.column_count;
+
+        let (page, has_more) = self.tcp.borrow_mut().execute(
+            2005,
+            |request| {
+                id.write(request)
+            },
+            |response| {
+                read_page(response, column_count)
+            }
+        ).map_err(cursor_closed)?;
+
+        self.page = page.into_iter();
+        self.has_more = has_more;
+
+        if !has_more {
+            self.permit = None;
+        }
+
+        Ok(())
+    }
+}
+
+impl Iterator for FieldsCursor {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Result<Row>> {
+        loop {
+            if let Some(row) = self.page.next() {
+                return Some(Ok(row));
+            }
+
+            if !self.has_more {
+                return None;
+            }
+
+            if let Err(err) = self.fetch_page() {
+                self.has_more = false;
+                self.permit = None;
+
+                if err.kind == ErrorKind::CursorClosed && !self.fail_on_close {
+                    return None;
+                }
+
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+impl Drop for FieldsCursor {
+    fn drop(&mut self) {
+        // Server closes exhausted cursors on its own.
+        if self.has_more {
+            let _ = close_resource(&self.tcp, self.id);
+        }
+    }
+}
+
+pub struct ScanCursor {
+    tcp: Rc<RefCell<Tcp>>,
+    id: i64,
+    page: IntoIter<(Value, Value)>,
+    has_more: bool,
+    permit: Option<CursorPermit>,
+    fail_on_close: bool,
+}
+
+impl ScanCursor {
+    pub(crate) fn read(tcp: Rc<RefCell<Tcp>>, bytes: &mut Bytes) -> Result<ScanCursor> {
+        let id = i64::read(bytes)?;
+
+        let (page, has_more) = read_entries(bytes)?;
+
+        Ok(ScanCursor {
+            tcp,
+            id,
+            page: page.into_iter(),
+            has_more,
+            permit: None,
+            fail_on_close: false,
+        })
+    }
+
+    pub(crate) fn with_permit(mut self, permit: CursorPermit) -> ScanCursor {
+        if self.has_more {
+            self.permit = Some(permit);
+        }
+
+        self
+    }
+
+    /// By default, iteration just stops if the server closes the cursor (e.g. on timeout).
+    pub fn fail_on_close(mut self, fail_on_close: bool) -> ScanCursor {
+        self.fail_on_close = fail_on_close;
+
+        self
+    }
+
+    /// Releases the server cursor without fetching the remaining pages.
+    pub fn close(mut self) -> Result<()> {
+        self.permit = None;
+
+        // Exhausted cursors are already closed by the server; this also keeps `drop` from closing twice.
+        if std::mem::replace(&mut self.has_more, false) {
+            close_resource(&self.tcp, self.id)
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    fn fetch_page(&mut self) -> Result<()> {
+        let id = self.id;
+
+        let (page, has_more) = self.tcp.borrow_mut().execute(
+            2001,
+            |request| {
+                id.write(request)
+            },
+            read_entries
+        ).map_err(cursor_closed)?;
+
+        self.page = page.into_iter();
+        self.has_more = has_more;
+
+        if !has_more {
+            self.permit = None;
+        }
+
+        Ok(())
+    }
+}
+
+impl Iterator for ScanCursor {
+    type Item = Result<(Value, Value)>;
+
+    fn next(&mut self) -> Option<Result<(Value, Value)>> {
+        loop {
+            if let Some(entry) = self.page.next() {
+                return Some(Ok(entry));
+            }
+
+            if !self.has_more {
+                return None;
+            }
+
+            if let Err(err) = self.fetch_page() {
+                self.has_more = false;
+                self.permit = None;
+
+                if err.kind == ErrorKind::CursorClosed && !self.fail_on_close {
+                    return None;
+                }
+
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+impl Drop for ScanCursor {
+    fn drop(&mut self) {
+        if self.has_more {
+            let _ = close_resource(&self.tcp, self.id);
+        }
+    }
+}
+
+pub struct SqlCursor {
+    tcp: Rc<RefCell<Tcp>>,
+    id: i64,
+    page: IntoIter<(Value, Value)>,
+    has_more: bool,
+    permit: Option<CursorPermit>,
+    fail_on_close: bool,
+}
+
+impl SqlCursor {
+    pub(crate) fn read(tcp: Rc<RefCell<Tcp>>, bytes: &mut Bytes) -> Result<SqlCursor> {
+        let id = i64::read(bytes)?;
+
+        let (page, has_more) = read_entries(bytes)?;
+
+        Ok(SqlCursor {
+            tcp,
+            id,
+            page: page.into_iter(),
+            has_more,
+            permit: None,
+            fail_on_close: false,
+        })
+    }
+
+    pub(crate) fn with_permit(mut self, permit: CursorPermit) -> SqlCursor {
+        if self.has_more {
+            self.permit = Some(permit);
+        }
+
+        self
+    }
+
+    /// By default, iteration just stops if the server closes the cursor (e.g. on timeout).
+    pub fn fail_on_close(mut self, fail_on_close: bool) -> SqlCursor {
+        self.fail_on_close = fail_on_close;
+
+        self
+    }
+
+    /// Releases the server cursor without fetching the remaining pages.
+    pub fn close(mut self) -> Result<()> {
+        self.permit = None;
+
+        // Exhausted cursors are already closed by the server; this also keeps `drop` from closing twice.
+        if std::mem::replace(&mut self.has_more, false) {
+            close_resource(&self.tcp, self.id)
+        }
+        else {
+            Ok(())
+        }
+    }
+
+    fn fetch_page(&mut self) -> Result<()> {
+        let id = self.id;
+
+        let (page, has_more) = self.tcp.borrow_mut().execute(
+            2003,
+            |request| {
+                id.write(request)
+            },
+            read_entries
+        ).map_err(cursor_closed)?;
+
+        self.page = page.into_iter();
+        self.has_more = has_more;
+
+        if !has_more {
+            self.permit = None;
+        }
+
+        Ok(())
+    }
+}
+
+impl Iterator for SqlCursor {
+    type Item = Result<(Value, Value)>;
+
+    fn next(&mut self) -> Option<Result<(Value, Value)>> {
+        loop {
+            if let Some(entry) = self.page.next() {
+                return Some(Ok(entry));
+            }
+
+            if !self.has_more {
+                return None;
+            }
+
+            if let Err(err) = self.fetch_page() {
+                self.has_more = false;
+                self.permit = None;
+
+                if err.kind == ErrorKind::CursorClosed && !self.fail_on_close {
+                    return None;
+                }
+
+                return Some(Err(err));
+            }
+        }
+    }
+}
+
+impl Drop for SqlCursor {
+    fn drop(&mut self) {
+        if self.has_more {
+            let _ = close_resource(&self.tcp, self.id);
+        }
+    }
+}
+
+pub(crate) struct CursorPermit {
+    open_cursors: Arc<AtomicUsize>,
+}
+
+impl CursorPermit {
+    pub(crate) fn acquire(open_cursors: &Arc<AtomicUsize>, max_open_cursors: Option<usize>) -> Result<CursorPermit> {
+        if let Some(max) = max_open_cursors {
+            if open_cursors.load(Ordering::Relaxed) >= max {
+                return Err(Error::new(ErrorKind::TooManyCursors, format!("Too many open cursors: {}", max)));
+            }
+        }
+
+        open_cursors.fetch_add(1, Ordering::Relaxed);
+
+        Ok(CursorPermit { open_cursors: open_cursors.clone() })
+    }
+}
+
+impl Drop for CursorPermit {
+    fn drop(&mut self) {
+        self.open_cursors.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn cursor_closed(err: Error) -> Error {
+    if err.kind == ErrorKind::Ignite(STATUS_RESOURCE_DOES_NOT_EXIST) {
+        Error::new(ErrorKind::CursorClosed, err.message)
+    }
+    else {
+        err
+    }
+}
+
+pub(crate) fn read_page(bytes: &mut Bytes, column_count: usize) -> Result<(Vec<Row>, bool)> {
+    let row_count = i32::read(bytes)? as usize;
+
+    let mut rows = Vec::with_capacity(row_count);
+
+    for _ in 0 .. row_count {
+        let mut row = Vec::with_capacity(column_count);
+
+        for _ in 0 .. column_count {
+            row.push(<Option<Value>>::read(bytes)?);
+        }
+
+        rows.push(row);
+    }
+
+    let has_more = bool::read(bytes)?;
+
+    Ok((rows, has_more))
+}
+
+fn read_entries(bytes: &mut Bytes) -> Result<(Vec<(Value, Value)>, bool)> {
+    let entries = <Vec<(Value, Value)>>::read(bytes)?;
+    let has_more = bool::read(bytes)?;
+
+    Ok((entries, has_more))
+}
+
+pub(crate) fn close_resource(tcp: &Rc<RefCell<Tcp>>, id: i64) -> Result<()> {
+    tcp.borrow_mut().execute(
+        0,
+        |request| {
+            id.write(request)
+        },
+        |_| { Ok(()) }
+    )
+}
+
+// === Tests
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use bytes::{Bytes, BytesMut};
+
+    use crate::binary::IgniteWrite;
+    use crate::configuration::Configuration;
+    use crate::error::ErrorKind;
+    use crate::network::tests::tcp_with_responses;
+    use crate::binary::Value;
+    use crate::query::{SqlFieldsQuery, ScanQuery, ScanCursor, SqlQuery, SqlCursor, StatementType, CursorPermit, FieldsCursor, FromRow};
+
+    #[derive(FromRow, PartialEq, Debug)]
+    struct Point {
+        #[sql(column = "y")]
+        y: f64,
+        #[sql(column = "x")]
+        x: i64,
+        label: Option<Value>,
+    }
+
+    fn page_size(query: &SqlFieldsQuery) -> [u8; 4] {
+        let mut bytes = BytesMut::new();
+
+        query.write(&mut bytes).unwrap();
+
+        // Null schema takes a single byte.
+        [bytes[1], bytes[2], bytes[3], bytes[4]]
+    }
+
+    #[test]
+    fn test_default_page_size() {
+        let query = SqlFieldsQuery::new("SELECT 1").default_page_size(256);
+
+        assert_eq!(page_size(&query), 256i32.to_le_bytes());
+
+        let query = SqlFieldsQuery::new("SELECT 1").page_size(16).default_page_size(256);
+
+        assert_eq!(page_size(&query), 16i32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_statement_type() {
+        let statement_type = |query: SqlFieldsQuery| {
+            let mut bytes = BytesMut::new();
+
+            query.write(&mut bytes).unwrap();
+
+            // Null schema, page size, max rows, SQL string and an empty argument list.
+            bytes[1 + 4 + 4 + 5 + 8 + 4]
+        };
+
+        assert_eq!(statement_type(SqlFieldsQuery::new("SELECT 1")), 0);
+        assert_eq!(statement_type(SqlFieldsQuery::new("SELECT 1").statement_type(StatementType::Select)), 1);
+        assert_eq!(statement_type(SqlFieldsQuery::new("DELETE 1").statement_type(StatementType::Update)), 2);
+    }
+
+    #[test]
+    fn test_default_sql_schema() {
+        let configuration = Configuration::default().default_sql_schema("APP");
+
+        let mut bytes = BytesMut::new();
+
+        SqlFieldsQuery::new("SELECT 1").with_defaults(&configuration).write(&mut bytes).unwrap();
+
+        assert_eq!(&bytes[.. 8], &[9, 3, 0, 0, 0, b'A', b'P', b'P']);
+
+        let mut bytes = BytesMut::new();
+
+        SqlFieldsQuery::new("SELECT 1").schema("OTHER").with_defaults(&configuration).write(&mut bytes).unwrap();
+
+        assert_eq!(&bytes[.. 10], &[9, 5, 0, 0, 0, b'O', b'T', b'H', b'E', b'R']);
+
+        let mut bytes = BytesMut::new();
+
+        SqlFieldsQuery::new("SELECT 1").with_defaults(&Configuration::default()).write(&mut bytes).unwrap();
+
+        assert_eq!(bytes[0], 101);
+    }
+
+    #[test]
+    fn test_cursor_permit() {
+        let open_cursors = Arc::new(AtomicUsize::new(0));
+
+        let first = CursorPermit::acquire(&open_cursors, Some(2)).unwrap();
+        let second = CursorPermit::acquire(&open_cursors, Some(2)).unwrap();
+
+        assert_eq!(CursorPermit::acquire(&open_cursors, Some(2)).err().unwrap().kind, ErrorKind::TooManyCursors);
+
+        drop(first);
+
+        let third = CursorPermit::acquire(&open_cursors, Some(2)).unwrap();
+
+        assert_eq!(open_cursors.load(Ordering::Relaxed), 2);
+
+        drop(second);
+        drop(third);
+
+        assert_eq!(open_cursors.load(Ordering::Relaxed), 0);
+        assert!(CursorPermit::acquire(&open_cursors, None).is_ok());
+    }
+
+    #[test]
+    fn test_scan_query() {
+        let mut bytes = BytesMut::new();
+
+        ScanQuery::new().partition(5).default_page_size(256).write(&mut bytes).unwrap();
+
+        assert_eq!(bytes.to_vec(), vec![101, 0, 1, 0, 0, 5, 0, 0, 0, 0]);
+
+        let mut bytes = BytesMut::new();
+
+        ScanQuery::new().local(true).write(&mut bytes).unwrap();
+
+        assert_eq!(bytes.to_vec(), vec![101, 0, 4, 0, 0, 0xff, 0xff, 0xff, 0xff, 1]);
+    }
+
+    #[test]
+    fn test_sql_query() {
+        let mut bytes = BytesMut::new();
+
+        SqlQuery::new("T", "a = ?").arg(Value::I32(1)).local(true).default_page_size(256).write(&mut bytes).unwrap();
+
+        assert_eq!(bytes.to_vec(), vec![
+            9, 1, 0, 0, 0, b'T',
+            9, 5, 0, 0, 0, b'a', b' ', b'=', b' ', b'?',
+            1, 0, 0, 0, 3, 1, 0, 0, 0,
+            0, 1, 0,
+            0, 1, 0, 0,
+            0, 0, 0, 0, 0, 0, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn test_sql_cursor_paging() {
+        let page = |request_id: bool, key: i32, has_more: bool| {
+            let mut bytes = BytesMut::new();
+
+            if request_id {
+                0i64.write(&mut bytes).unwrap();
+                0i32.write(&mut bytes).unwrap(); // Status.
+            }
+            else {
+                1i64.write(&mut bytes).unwrap(); // Cursor ID.
+            }
+
+            1i32.write(&mut bytes).unwrap(); // Entries.
+            Value::I32(key).write(&mut bytes).unwrap();
+            Value::String(key.to_string()).write(&mut bytes).unwrap();
+            has_more.write(&mut bytes).unwrap();
+
+            bytes.to_vec()
+        };
+
+        let tcp = Rc::new(RefCell::new(tcp_with_responses(vec![page(true, 2, false)])));
+
+        let cursor = SqlCursor::read(tcp, &mut Bytes::from(page(false, 1, true))).unwrap();
+
+        let entries: Vec<(Value, Value)> = cursor.map(Result::unwrap).collect();
+
+        assert_eq!(entries, vec![
+            (Value::I32(1), Value::String("1".to_string())),
+            (Value::I32(2), Value::String("2".to_string())),
+        ]);
+    }
+
+    fn cursor_closed_response() -> Vec<u8> {
+        let mut bytes = BytesMut::new();
+
+        0i64.write(&mut bytes).unwrap(); // Request ID.
+        1011i32.write(&mut bytes).unwrap();
+        "Failed to find resource with id: 1".to_string().write(&mut bytes).unwrap();
+
+        bytes.to_vec()
+    }
+
+    // Cursor with one row in the first page that the server has already closed.
+    fn closed_cursor() -> FieldsCursor {
+        let tcp = Rc::new(RefCell::new(tcp_with_responses(vec![cursor_closed_response()])));
+
+        let mut bytes = BytesMut::new();
+
+        1i64.write(&mut bytes).unwrap(); // Cursor ID.
+        1i32.write(&mut bytes).unwrap(); // Columns.
+        1i32.write(&mut bytes).unwrap(); // Rows.
+        crate::Value::I32(42).write(&mut bytes).unwrap();
+        true.write(&mut bytes).unwrap(); // Has more.
+
+        FieldsCursor::read(tcp, false, &mut Bytes::from(bytes.to_vec())).unwrap()
+    }
+
+    #[test]
+    fn test_cursor_closed_by_server() {
+        let mut cursor = closed_cursor();
+
+        assert!(matches!(cursor.next(), Some(Ok(_))));
+        assert!(cursor.next().is_none());
+        assert!(cursor.next().is_none());
+
+        let mut cursor = closed_cursor().fail_on_close(true);
+
+        assert!(matches!(cursor.next(), Some(Ok(_))));
+        assert_eq!(cursor.next().unwrap().unwrap_err().kind, ErrorKind::CursorClosed);
+        assert!(cursor.next().is_none());
+    }
+
+    #[test]
+    fn test_close() {
+        let mut ok = BytesMut::new();
+
+        0i64.write(&mut ok).unwrap(); // Request ID.
+        0i32.write(&mut ok).unwrap();
+
+        let tcp = Rc::new(RefCell::new(tcp_with_responses(vec![ok.to_vec(), cursor_closed_response()])));
+
+        let page = || {
+            let mut bytes = BytesMut::new();
+
+            1i64.write(&mut bytes).unwrap(); // Cursor ID.
+            1i32.write(&mut bytes).unwrap(); // Entries.
+            Value::I32(1).write(&mut bytes).unwrap();
+            Value::I32(1).write(&mut bytes).unwrap();
+            true.write(&mut bytes).unwrap(); // Has more.
+
+            Bytes::from(bytes.to_vec())
+        };
+
+        let mut cursor = ScanCursor::read(tcp.clone(), &mut page()).unwrap();
+
+        assert!(matches!(cursor.next(), Some(Ok(_))));
+        assert_eq!(cursor.close(), Ok(()));
+
+        // Server no longer knows the cursor.
+        let mut cursor = ScanCursor::read(tcp, &mut page()).unwrap().fail_on_close(true);
+
+        assert!(matches!(cursor.next(), Some(Ok(_))));
+        assert_eq!(cursor.next().unwrap().unwrap_err().kind, ErrorKind::CursorClosed);
+    }
+
+    #[test]
+    fn test_from_row() {
+        let columns = vec!["X".to_string(), "Y".to_string(), "LABEL".to_string()];
+
+        let row = vec![Some(Value::I64(1)), Some(Value::F64(2.5)), None];
+
+        assert_eq!(Point::from_row(row, &columns), Ok(Point { y: 2.5, x: 1, label: None }));
+
+        let err = Point::from_row(vec![Some(Value::I64(1)), Some(Value::F64(2.5))], &columns).unwrap_err();
+
+        assert_eq!(err.kind, ErrorKind::Serde);
+        assert_eq!(err.message, "Column count mismatch: row has 2 columns, expected 3");
+
+        let err = Point::from_row(vec![None, Some(Value::F64(2.5)), None], &columns).unwrap_err();
+
+        assert_eq!(err.message, "Null value for field: x");
+
+        let err = Point::from_row(vec![None, None, None], &["X".to_string()]).unwrap_err();
+
+        assert_eq!(err.message, "Column not found: y");
+    }
+}
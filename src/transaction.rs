@@ -0,0 +1,181 @@
+use std::rc::Rc;
+use std::cell::RefCell;
+use std::time::Duration;
+
+use crate::binary::{IgniteWrite, IgniteRead};
+use crate::error::{Result, Error, ErrorKind};
+use crate::Feature;
+use crate::network::{Tcp, lost_transaction};
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum TxConcurrency {
+    Optimistic = 0,
+    Pessimistic = 1,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum TxIsolation {
+    ReadCommitted = 0,
+    RepeatableRead = 1,
+    Serializable = 2,
+}
+
+/// Transaction bound to the client connection. While it is open, every cache operation on the
+/// client takes part in it. Dropping an unfinished transaction rolls it back.
+///
+/// The transaction lives in the server session, so it is lost if the connection drops. After a
+/// reconnect, cache operations fail until the transaction is ended, and ending it returns an error.
+pub struct Transaction {
+    tcp: Rc<RefCell<Tcp>>,
+    id: i32,
+    finished: bool,
+}
+
+impl Transaction {
+    pub(crate) fn start(
+        tcp: Rc<RefCell<Tcp>>,
+        concurrency: TxConcurrency,
+        isolation: TxIsolation,
+        timeout: Duration,
+        label: Option<&str>,
+    ) -> Result<Transaction> {
+        tcp.borrow().features.require(Feature::Transactions)?;
+
+        if tcp.borrow().current_tx()?.is_some() {
+            return Err(Error::new(ErrorKind::InvalidArgument, "Transaction is already started.".to_string()));
+        }
+
+        let id = tcp.borrow_mut().execute(
+            4000,
+            |request| {
+                (concurrency as i8).write(request)?;
+                (isolation as i8).write(request)?;
+                (timeout.as_millis() as i64).write(request)?;
+                label.map(str::to_string).write(request)?;
+
+                Ok(())
+            },
+            i32::read
+        )?;
+
+        tcp.borrow_mut().tx_id = Some(id);
+
+        Ok(Transaction { tcp, id, finished: false })
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn commit(mut self) -> Result<()> {
+        self.end(true)
+    }
+
+    pub fn rollback(mut self) -> Result<()> {
+        self.end(false)
+    }
+
+    fn end(&mut self, committed: bool) -> Result<()> {
+        self.finished = true;
+
+        let id = self.id;
+
+        // The server session that knew the transaction is gone, so there is nothing to end.
+        if self.tcp.borrow().lost_tx_id == Some(id) {
+            self.tcp.borrow_mut().lost_tx_id = None;
+
+            return Err(lost_transaction(id));
+        }
+
+        // Later operations are not transactional, even if the server failed to end the transaction.
+        self.tcp.borrow_mut().tx_id = None;
+
+        self.tcp.borrow_mut().execute(
+            4001,
+            |request| {
+                id.write(request)?;
+                committed.write(request)?;
+
+                Ok(())
+            },
+            |_| { Ok(()) }
+        )
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.end(false);
+        }
+    }
+}
+
+// === Tests
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use std::cell::RefCell;
+    use std::time::Duration;
+
+    use bytes::BytesMut;
+
+    use crate::{FeatureSet, VERSION_1_5_0};
+    use crate::binary::IgniteWrite;
+    use crate::error::ErrorKind;
+    use crate::network::Tcp;
+    use crate::network::tests::tcp_with_responses;
+    use crate::transaction::{Transaction, TxConcurrency, TxIsolation};
+
+    fn response(tx_id: Option<i32>) -> Vec<u8> {
+        let mut bytes = BytesMut::new();
+
+        0i64.write(&mut bytes).unwrap(); // Request ID.
+        0i16.write(&mut bytes).unwrap(); // Flags.
+
+        if let Some(tx_id) = tx_id {
+            tx_id.write(&mut bytes).unwrap();
+        }
+
+        bytes.to_vec()
+    }
+
+    fn tcp(responses: Vec<Vec<u8>>) -> Rc<RefCell<Tcp>> {
+        let mut tcp = tcp_with_responses(responses);
+
+        tcp.features = FeatureSet::new(VERSION_1_5_0, Vec::new());
+
+        Rc::new(RefCell::new(tcp))
+    }
+
+    #[test]
+    fn test_tx_id() {
+        let tcp = tcp(vec![response(Some(7)), response(None), response(Some(8)), response(None)]);
+
+        let start = || Transaction::start(tcp.clone(), TxConcurrency::Pessimistic, TxIsolation::ReadCommitted, Duration::from_secs(1), None);
+
+        let tx = start().unwrap();
+
+        assert_eq!(tx.id(), 7);
+        assert_eq!(tcp.borrow().tx_id, Some(7));
+        assert_eq!(start().err().unwrap().kind, ErrorKind::InvalidArgument);
+
+        assert_eq!(tx.commit(), Ok(()));
+        assert_eq!(tcp.borrow().tx_id, None);
+
+        // Dropping rolls back.
+        drop(start().unwrap());
+
+        assert_eq!(tcp.borrow().tx_id, None);
+    }
+
+    #[test]
+    fn test_not_supported() {
+        let tcp = Rc::new(RefCell::new(tcp_with_responses(Vec::new())));
+
+        let err = Transaction::start(tcp, TxConcurrency::Pessimistic, TxIsolation::ReadCommitted, Duration::from_secs(1), None).err().unwrap();
+
+        assert_eq!(err.kind, ErrorKind::NotSupported);
+    }
+}